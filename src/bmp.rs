@@ -0,0 +1,238 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::binary_serializable::BinarySerializable;
+
+pub const MAGIC: [u8; 2] = *b"BM";
+
+pub const CORE_HEADER_SIZE: u32 = 12;
+pub const INFO_HEADER_SIZE: u32 = 40;
+pub const V4_HEADER_SIZE: u32 = 108;
+pub const V5_HEADER_SIZE: u32 = 124;
+
+/// "sRGB" color space of V4 and V5 headers
+const LCS_SRGB: u32 = 0x73524742;
+
+#[derive(Debug, Clone)]
+pub struct FileHeader {
+    pub size: u32,
+    /// Offset of the pixel data from the start of the file
+    pub pixel_offset: u32,
+}
+
+impl FileHeader {
+    pub const SIZE: u32 = 14;
+}
+
+impl BinarySerializable for FileHeader {
+    fn read<R: Read>(reader: &mut R) -> io::Result<Self> where Self: Sized {
+        let mut magic = [0u8; 2];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid BMP file"));
+        }
+
+        let size = reader.read_u32::<LittleEndian>()?;
+        let _reserved = reader.read_u32::<LittleEndian>()?;
+        let pixel_offset = reader.read_u32::<LittleEndian>()?;
+
+        Ok(FileHeader { size, pixel_offset })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_u32::<LittleEndian>(self.size)?;
+        writer.write_u32::<LittleEndian>(0)?;
+        writer.write_u32::<LittleEndian>(self.pixel_offset)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Compression {
+    RGB = 0,
+    RLE8 = 1,
+    RLE4 = 2,
+    Bitfields = 3,
+    JPEG = 4,
+    PNG = 5,
+    AlphaBitfields = 6,
+}
+
+impl TryFrom<u32> for Compression {
+    type Error = ();
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Compression::RGB),
+            1 => Ok(Compression::RLE8),
+            2 => Ok(Compression::RLE4),
+            3 => Ok(Compression::Bitfields),
+            4 => Ok(Compression::JPEG),
+            5 => Ok(Compression::PNG),
+            6 => Ok(Compression::AlphaBitfields),
+            _ => Err(()),
+        }
+    }
+}
+
+/// BITMAPCOREHEADER, BITMAPINFOHEADER and its V2 to V5 extensions
+#[derive(Debug, Clone)]
+pub struct InfoHeader {
+    pub header_size: u32,
+    pub width: i32,
+    /// Negative for top-down rows
+    pub height: i32,
+    pub planes: u16,
+    pub bit_count: u16,
+    pub compression: Compression,
+    pub image_size: u32,
+    pub x_pixels_per_meter: i32,
+    pub y_pixels_per_meter: i32,
+    pub colors_used: u32,
+    pub colors_important: u32,
+    /// Red, green, blue and alpha masks from the header or following a BITMAPINFOHEADER
+    pub masks: Option<[u32; 4]>,
+}
+
+impl InfoHeader {
+    pub fn is_top_down(&self) -> bool {
+        self.height < 0
+    }
+
+    /// Size of a palette entry, core headers use RGB triples
+    pub fn palette_entry_size(&self) -> usize {
+        if self.header_size == CORE_HEADER_SIZE { 3 } else { 4 }
+    }
+
+    pub fn palette_size(&self) -> usize {
+        match self.colors_used {
+            0 if self.bit_count <= 8 => 1 << self.bit_count,
+            0 => 0,
+            colors => colors as usize,
+        }
+    }
+
+    /// Row length in bytes, rows are padded to 4 bytes
+    pub fn stride(&self) -> usize {
+        (self.width.unsigned_abs() as usize * self.bit_count as usize).div_ceil(32) * 4
+    }
+}
+
+fn check_bit_count(bit_count: u16) -> io::Result<u16> {
+    match bit_count {
+        1 | 2 | 4 | 8 | 16 | 24 | 32 => Ok(bit_count),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid BMP bit count")),
+    }
+}
+
+impl BinarySerializable for InfoHeader {
+    fn read<R: Read>(reader: &mut R) -> io::Result<Self> where Self: Sized {
+        let header_size = reader.read_u32::<LittleEndian>()?;
+
+        if header_size == CORE_HEADER_SIZE {
+            let width = reader.read_u16::<LittleEndian>()? as i32;
+            let height = reader.read_u16::<LittleEndian>()? as i32;
+            let planes = reader.read_u16::<LittleEndian>()?;
+            let bit_count = check_bit_count(reader.read_u16::<LittleEndian>()?)?;
+
+            return Ok(InfoHeader {
+                header_size,
+                width,
+                height,
+                planes,
+                bit_count,
+                compression: Compression::RGB,
+                image_size: 0,
+                x_pixels_per_meter: 0,
+                y_pixels_per_meter: 0,
+                colors_used: 0,
+                colors_important: 0,
+                masks: None,
+            });
+        }
+
+        if header_size < INFO_HEADER_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown BMP header size"));
+        }
+
+        let width = reader.read_i32::<LittleEndian>()?;
+        let height = reader.read_i32::<LittleEndian>()?;
+        let planes = reader.read_u16::<LittleEndian>()?;
+        let bit_count = check_bit_count(reader.read_u16::<LittleEndian>()?)?;
+        let compression = Compression::try_from(reader.read_u32::<LittleEndian>()?)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Unknown BMP compression"))?;
+        let image_size = reader.read_u32::<LittleEndian>()?;
+        let x_pixels_per_meter = reader.read_i32::<LittleEndian>()?;
+        let y_pixels_per_meter = reader.read_i32::<LittleEndian>()?;
+        let colors_used = reader.read_u32::<LittleEndian>()?;
+        let colors_important = reader.read_u32::<LittleEndian>()?;
+
+        // Masks are part of V2 and later headers, BITMAPINFOHEADER is followed by them
+        let mask_count = match (header_size, compression) {
+            (INFO_HEADER_SIZE, Compression::Bitfields) => 3,
+            (INFO_HEADER_SIZE, Compression::AlphaBitfields) => 4,
+            (INFO_HEADER_SIZE, _) => 0,
+            (52, _) => 3,
+            _ => 4,
+        };
+        let mut masks = [0u32; 4];
+        for mask in masks.iter_mut().take(mask_count) {
+            *mask = reader.read_u32::<LittleEndian>()?;
+        }
+
+        let remaining = header_size.saturating_sub(INFO_HEADER_SIZE + 4 * mask_count as u32);
+        if header_size > INFO_HEADER_SIZE && remaining > 0 {
+            io::copy(&mut reader.take(remaining as u64), &mut io::sink())?;
+        }
+
+        Ok(InfoHeader {
+            header_size,
+            width,
+            height,
+            planes,
+            bit_count,
+            compression,
+            image_size,
+            x_pixels_per_meter,
+            y_pixels_per_meter,
+            colors_used,
+            colors_important,
+            masks: if mask_count > 0 { Some(masks) } else { None },
+        })
+    }
+
+    /// Writes BITMAPINFOHEADER or a V4 header in the sRGB color space
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u32::<LittleEndian>(self.header_size)?;
+        writer.write_i32::<LittleEndian>(self.width)?;
+        writer.write_i32::<LittleEndian>(self.height)?;
+        writer.write_u16::<LittleEndian>(self.planes)?;
+        writer.write_u16::<LittleEndian>(self.bit_count)?;
+        writer.write_u32::<LittleEndian>(self.compression as u32)?;
+        writer.write_u32::<LittleEndian>(self.image_size)?;
+        writer.write_i32::<LittleEndian>(self.x_pixels_per_meter)?;
+        writer.write_i32::<LittleEndian>(self.y_pixels_per_meter)?;
+        writer.write_u32::<LittleEndian>(self.colors_used)?;
+        writer.write_u32::<LittleEndian>(self.colors_important)?;
+
+        if self.header_size == V4_HEADER_SIZE {
+            for mask in self.masks.unwrap_or_default() {
+                writer.write_u32::<LittleEndian>(mask)?;
+            }
+            writer.write_u32::<LittleEndian>(LCS_SRGB)?;
+            // Endpoints and gamma are unused for sRGB
+            writer.write_all(&[0u8; 48])?;
+        } else if let Some(masks) = self.masks {
+            let count = if self.compression == Compression::AlphaBitfields { 4 } else { 3 };
+            for mask in masks.iter().take(count) {
+                writer.write_u32::<LittleEndian>(*mask)?;
+            }
+        }
+
+        Ok(())
+    }
+}
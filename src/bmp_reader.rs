@@ -0,0 +1,214 @@
+use std::fs;
+use std::io::{self, Cursor};
+
+use crate::binary_serializable::BinarySerializable;
+use crate::bmp::{Compression, FileHeader, InfoHeader};
+use crate::common::*;
+
+pub struct BMPReader {
+
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Scales the masked bits of the pixel to 8 bits
+pub(crate) fn extract(pixel: u32, mask: u32) -> Option<u8> {
+    if mask == 0 {
+        return None;
+    }
+    let max = (mask >> mask.trailing_zeros()) as u64;
+    let value = ((pixel & mask) >> mask.trailing_zeros()) as u64;
+    Some(((value * 255 + max / 2) / max) as u8)
+}
+
+impl BMPReader {
+    fn palette(&self, header: &InfoHeader, data: &[u8]) -> io::Result<Vec<Color>> {
+        let entry_size = header.palette_entry_size();
+        let size = header.palette_size();
+        if size > 256 && header.bit_count <= 8 {
+            return Err(invalid_data("Invalid BMP palette size"));
+        }
+
+        let data = data.get(..size * entry_size).ok_or_else(|| invalid_data("Incomplete BMP palette"))?;
+        Ok(data.chunks_exact(entry_size).map(|bgr| Color::from_rgb(bgr[2], bgr[1], bgr[0])).collect())
+    }
+
+    /// Palette indices of RLE compressed data, skipped pixels are None
+    fn decode_rle(&self, header: &InfoHeader, data: &[u8]) -> io::Result<Vec<Vec<Option<u8>>>> {
+        let (width, height) = (header.width as usize, header.height.unsigned_abs() as usize);
+        let four_bit = header.compression == Compression::RLE4;
+        // Runs cover at most 255 pixels per 2 bytes and deltas skip at most 255 rows per 4 bytes
+        if width > data.len().saturating_mul(128) || height > data.len().saturating_mul(64) + 1 {
+            return Err(invalid_data("BMP size is too large for its data"));
+        }
+        let mut rows = vec![vec![None; width]; height];
+        let (mut x, mut y) = (0usize, 0usize);
+        let mut position = 0;
+
+        let mut next = || {
+            let byte = data.get(position).copied();
+            position += 1;
+            byte.ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete BMP data"))
+        };
+        let put = |rows: &mut Vec<Vec<Option<u8>>>, x: &mut usize, y: usize, index: u8| {
+            if let Some(pixel) = rows.get_mut(y).and_then(|row| row.get_mut(*x)) {
+                *pixel = Some(index);
+            }
+            *x += 1;
+        };
+
+        loop {
+            let (count, value) = (next()?, next()?);
+            if count > 0 {
+                // Encoded run, RLE4 alternates the two nibbles
+                for i in 0..count {
+                    let index = match four_bit {
+                        true if i % 2 == 0 => value >> 4,
+                        true => value & 0x0F,
+                        false => value,
+                    };
+                    put(&mut rows, &mut x, y, index);
+                }
+                continue;
+            }
+
+            match value {
+                0 => {
+                    x = 0;
+                    y += 1;
+                },
+                1 => break,
+                2 => {
+                    x += next()? as usize;
+                    y += next()? as usize;
+                },
+                // Absolute run, padded to 2 bytes
+                count => {
+                    let bytes = if four_bit { (count as usize).div_ceil(2) } else { count as usize };
+                    let mut run = Vec::with_capacity(bytes);
+                    for _ in 0..bytes {
+                        run.push(next()?);
+                    }
+                    if bytes % 2 == 1 {
+                        next()?;
+                    }
+
+                    for i in 0..count as usize {
+                        let index = match four_bit {
+                            true if i % 2 == 0 => run[i / 2] >> 4,
+                            true => run[i / 2] & 0x0F,
+                            false => run[i],
+                        };
+                        put(&mut rows, &mut x, y, index);
+                    }
+                },
+            }
+        }
+
+        Ok(rows)
+    }
+
+    fn decode_row(&self, header: &InfoHeader, row: &[u8], palette: &[Color], masks: [u32; 4]) -> io::Result<Vec<Color>> {
+        let width = header.width as usize;
+        let from_palette = |index: usize| palette.get(index).copied().ok_or_else(|| invalid_data("BMP palette index out of range"));
+
+        match header.bit_count {
+            1 | 2 | 4 | 8 => {
+                let bits = header.bit_count as usize;
+                (0..width).map(|x| {
+                    let byte = row[x * bits / 8];
+                    let shift = 8 - bits - (x * bits) % 8;
+                    from_palette(((byte >> shift) as usize) & ((1 << bits) - 1))
+                }).collect()
+            },
+            24 => Ok(row.chunks_exact(3).take(width).map(|bgr| Color::from_rgb(bgr[2], bgr[1], bgr[0])).collect()),
+            16 | 32 => {
+                let size = header.bit_count as usize / 8;
+                Ok(row.chunks_exact(size).take(width).map(|bytes| {
+                    let pixel = bytes.iter().rev().fold(0u32, |pixel, &byte| pixel << 8 | byte as u32);
+                    Color::new(
+                        extract(pixel, masks[0]).unwrap_or(0),
+                        extract(pixel, masks[1]).unwrap_or(0),
+                        extract(pixel, masks[2]).unwrap_or(0),
+                        extract(pixel, masks[3]).unwrap_or(u8::MAX),
+                    )
+                }).collect())
+            },
+            _ => Err(io::Error::new(io::ErrorKind::Unsupported, "Unsupported BMP bit count")),
+        }
+    }
+
+    /// Decodes a device independent bitmap, the info header followed by the palette and pixel data.
+    /// Pixel data directly follows the palette without an offset.
+    pub(crate) fn decode_dib(&self, dib: &[u8], pixel_offset: Option<usize>) -> io::Result<Image> {
+        let mut cursor = Cursor::new(dib);
+        let header = InfoHeader::read(&mut cursor)?;
+        let palette_offset = cursor.position() as usize;
+
+        if header.width <= 0 || header.height == 0 {
+            return Err(invalid_data("Invalid BMP size"));
+        }
+        let (width, height) = (header.width as usize, header.height.unsigned_abs() as usize);
+
+        let palette = self.palette(&header, &dib[palette_offset..])?;
+        let pixel_offset = pixel_offset.unwrap_or(palette_offset + palette.len() * header.palette_entry_size());
+        let data = dib.get(pixel_offset..).ok_or_else(|| invalid_data("Invalid BMP pixel data offset"))?;
+
+        let mut rows: Vec<Vec<Color>> = match header.compression {
+            Compression::RLE8 | Compression::RLE4 => {
+                // Skipped pixels are left transparent
+                self.decode_rle(&header, data)?.into_iter()
+                    .map(|row| row.into_iter().map(|index| match index {
+                        Some(index) => palette.get(index as usize).copied().ok_or_else(|| invalid_data("BMP palette index out of range")),
+                        None => Ok(Color::new(0, 0, 0, 0)),
+                    }).collect())
+                    .collect::<io::Result<_>>()?
+            },
+            Compression::RGB | Compression::Bitfields | Compression::AlphaBitfields => {
+                let masks = match (header.compression, header.bit_count) {
+                    (Compression::RGB, 16) => [0x7C00, 0x03E0, 0x001F, 0],
+                    (Compression::RGB, _) => [0x00FF0000, 0x0000FF00, 0x000000FF, 0],
+                    _ => header.masks.ok_or_else(|| invalid_data("Missing BMP bitfield masks"))?,
+                };
+
+                let stride = header.stride();
+                if stride.checked_mul(height).is_none_or(|size| data.len() < size) {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete BMP data"));
+                }
+                data.chunks_exact(stride).take(height)
+                    .map(|row| self.decode_row(&header, row, &palette, masks))
+                    .collect::<io::Result<_>>()?
+            },
+            Compression::JPEG | Compression::PNG => {
+                return Err(io::Error::new(io::ErrorKind::Unsupported, "Embedded JPEG and PNG BMP data is not supported"));
+            },
+        };
+
+        // Rows are stored from bottom to top unless the height is negative
+        if !header.is_top_down() {
+            rows.reverse();
+        }
+
+        Ok(Image::from_mat(width, height, rows))
+    }
+
+    pub fn decode(&self, data: &[u8]) -> io::Result<Image> {
+        let header = FileHeader::read(&mut Cursor::new(data))?;
+        let dib = &data[FileHeader::SIZE as usize..];
+        let pixel_offset = (header.pixel_offset as usize).checked_sub(FileHeader::SIZE as usize)
+            .ok_or_else(|| invalid_data("Invalid BMP pixel data offset"))?;
+
+        self.decode_dib(dib, Some(pixel_offset))
+    }
+}
+
+impl Reader for BMPReader {
+    fn read(&self, path: &str) -> io::Result<Image> {
+        println!("Reading BMP file");
+
+        let data = fs::read(path)?;
+        self.decode(&data)
+    }
+}
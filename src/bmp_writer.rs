@@ -0,0 +1,96 @@
+use std::fs;
+use std::io;
+
+use crate::binary_serializable::BinarySerializable;
+use crate::bmp::{self, Compression, FileHeader, InfoHeader};
+use crate::common::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PixelFormat {
+    /// 24 bit BGR, alpha is dropped
+    #[default]
+    RGB,
+    /// 32 bit BGRA with bitfield masks in a V4 header
+    RGBA,
+}
+
+#[derive(Default)]
+pub struct Settings {
+    pub pixel_format: PixelFormat,
+}
+
+pub struct BMPWriter {
+    pub settings: Settings
+}
+
+impl BMPWriter {
+    fn info_header(&self, image: &Image) -> InfoHeader {
+        let (header_size, bit_count, compression, masks) = match self.settings.pixel_format {
+            PixelFormat::RGB => (bmp::INFO_HEADER_SIZE, 24, Compression::RGB, None),
+            PixelFormat::RGBA => (bmp::V4_HEADER_SIZE, 32, Compression::Bitfields, Some([0x00FF0000, 0x0000FF00, 0x000000FF, 0xFF000000])),
+        };
+
+        let mut header = InfoHeader {
+            header_size,
+            width: image.width() as i32,
+            height: image.height() as i32,
+            planes: 1,
+            bit_count,
+            compression,
+            image_size: 0,
+            // 72 DPI
+            x_pixels_per_meter: 2835,
+            y_pixels_per_meter: 2835,
+            colors_used: 0,
+            colors_important: 0,
+            masks,
+        };
+        header.image_size = (header.stride() * image.height()) as u32;
+        header
+    }
+
+    /// Pixel data with rows from bottom to top
+    fn pixel_data(&self, image: &Image, header: &InfoHeader) -> Vec<u8> {
+        let stride = header.stride();
+        let mut data = Vec::with_capacity(stride * image.height());
+
+        for row in image.pixels.iter().rev() {
+            let start = data.len();
+            for color in row.iter() {
+                match self.settings.pixel_format {
+                    PixelFormat::RGB => data.extend_from_slice(&[color.b, color.g, color.r]),
+                    PixelFormat::RGBA => data.extend_from_slice(&[color.b, color.g, color.r, color.a]),
+                }
+            }
+            data.resize(start + stride, 0);
+        }
+
+        data
+    }
+
+    pub fn encode(&self, image: &Image) -> io::Result<Vec<u8>> {
+        let header = self.info_header(image);
+        let pixel_offset = FileHeader::SIZE + header.header_size;
+        let file_header = FileHeader { size: pixel_offset + header.image_size, pixel_offset };
+
+        let mut data = Vec::with_capacity(file_header.size as usize);
+        file_header.write(&mut data)?;
+        header.write(&mut data)?;
+        data.extend_from_slice(&self.pixel_data(image, &header));
+
+        Ok(data)
+    }
+}
+
+impl Writer for BMPWriter {
+    fn extension(&self) -> &str {
+        "bmp"
+    }
+
+    fn write(&self, image: Image, path: &str) {
+        println!("Writing BMP file at path: {}", path);
+
+        let data = self.encode(&image).expect("Can't encode BMP file");
+        fs::write(path, data).expect("Can't save output BMP file");
+    }
+}
@@ -1,11 +1,13 @@
 mod color;
 mod image;
+mod float_image;
 
 mod reader;
 mod writer;
 
-pub use color::Color;
+pub use color::{Color, Luminance};
 pub use image::Image;
+pub use float_image::FloatImage;
 
 pub use reader::Reader;
 pub use writer::Writer;
\ No newline at end of file
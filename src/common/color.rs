@@ -1,6 +1,6 @@
 use std::ops::{Add, AddAssign, Mul};
 
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -24,7 +24,7 @@ impl Color {
     pub fn from_slice(slice: &[u8]) -> Self {
         let mut color = Color::black();
 
-        if slice.len() > 0 {
+        if !slice.is_empty() {
             color.r = slice[0];
         }
         if slice.len() > 1 {
@@ -43,6 +43,41 @@ impl Color {
     pub fn black() -> Self {
         Color { r: 0, g: 0, b: 0, a: 255 }
     }
+
+    pub fn is_gray(&self) -> bool {
+        self.r == self.g && self.g == self.b
+    }
+
+    pub fn luminance(&self, luminance: Luminance) -> u8 {
+        let (wr, wg, wb) = luminance.weights();
+        let total = wr + wg + wb;
+        ((wr * self.r as u32 + wg * self.g as u32 + wb * self.b as u32 + total / 2) / total) as u8
+    }
+
+    /// Composites the color over an opaque background
+    pub fn flatten(&self, background: Color) -> Color {
+        let blend = |fg: u8, bg: u8| ((fg as u32 * self.a as u32 + bg as u32 * (255 - self.a as u32) + 127) / 255) as u8;
+        Color::from_rgb(blend(self.r, background.r), blend(self.g, background.g), blend(self.b, background.b))
+    }
+}
+
+/// Weights of the red, green and blue channels when converting to grayscale
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Luminance {
+    #[default]
+    Rec709,
+    Rec601,
+    Average,
+}
+
+impl Luminance {
+    fn weights(&self) -> (u32, u32, u32) {
+        match self {
+            Luminance::Rec709 => (2126, 7152, 722),
+            Luminance::Rec601 => (299, 587, 114),
+            Luminance::Average => (1, 1, 1),
+        }
+    }
 }
 
 impl Add for Color {
@@ -0,0 +1,74 @@
+use crate::common::*;
+
+/// Image with floating point samples, rows from top to bottom
+pub struct FloatImage {
+    width: usize,
+    height: usize,
+    channels: usize,
+    pub data: Vec<f32>,
+}
+
+impl FloatImage {
+    pub fn new(width: usize, height: usize, channels: usize) -> Self {
+        FloatImage { width, height, channels, data: vec![0.0; width * height * channels] }
+    }
+
+    pub fn from_data(width: usize, height: usize, channels: usize, data: Vec<f32>) -> Self {
+        assert_eq!(data.len(), width * height * channels, "Data size does not match the image size");
+        FloatImage { width, height, channels, data }
+    }
+
+    /// RGBA samples in 0..=1
+    pub fn from_image(image: &Image) -> Self {
+        let data = image.pixels.iter().flatten()
+            .flat_map(|color| [color.r, color.g, color.b, color.a])
+            .map(|sample| sample as f32 / 255.0)
+            .collect();
+
+        FloatImage { width: image.width(), height: image.height(), channels: 4, data }
+    }
+
+    /// Clamps samples to 0..=1, one and two channel images are gray
+    pub fn to_image(&self) -> Image {
+        let quantize = |sample: f32| (sample.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+        let pixels = (0..self.height)
+            .map(|y| (0..self.width).map(|x| {
+                let pixel: Vec<u8> = self.pixel(x, y).iter().map(|&sample| quantize(sample)).collect();
+                match pixel[..] {
+                    [gray] => Color::from_rgb(gray, gray, gray),
+                    [gray, alpha] => Color::new(gray, gray, gray, alpha),
+                    [r, g, b] => Color::from_rgb(r, g, b),
+                    [r, g, b, a, ..] => Color::new(r, g, b, a),
+                    _ => Color::black(),
+                }
+            }).collect())
+            .collect();
+
+        Image::from_mat(self.width, self.height, pixels)
+    }
+}
+
+impl FloatImage {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> &[f32] {
+        let start = (y * self.width + x) * self.channels;
+        &self.data[start..start + self.channels]
+    }
+
+    pub fn pixel_mut(&mut self, x: usize, y: usize) -> &mut [f32] {
+        let start = (y * self.width + x) * self.channels;
+        &mut self.data[start..start + self.channels]
+    }
+}
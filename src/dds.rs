@@ -0,0 +1,333 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::binary_serializable::BinarySerializable;
+use crate::common::Image;
+
+pub const MAGIC: &[u8; 4] = b"DDS ";
+
+// Header flags
+pub const DDSD_CAPS: u32 = 0x1;
+pub const DDSD_HEIGHT: u32 = 0x2;
+pub const DDSD_WIDTH: u32 = 0x4;
+pub const DDSD_PITCH: u32 = 0x8;
+pub const DDSD_PIXEL_FORMAT: u32 = 0x1000;
+pub const DDSD_MIP_MAP_COUNT: u32 = 0x20000;
+pub const DDSD_LINEAR_SIZE: u32 = 0x80000;
+pub const DDSD_DEPTH: u32 = 0x800000;
+
+// Pixel format flags
+pub const DDPF_ALPHA_PIXELS: u32 = 0x1;
+pub const DDPF_ALPHA: u32 = 0x2;
+pub const DDPF_FOUR_CC: u32 = 0x4;
+pub const DDPF_RGB: u32 = 0x40;
+pub const DDPF_LUMINANCE: u32 = 0x20000;
+
+// Capabilities
+pub const DDSCAPS_COMPLEX: u32 = 0x8;
+pub const DDSCAPS_TEXTURE: u32 = 0x1000;
+pub const DDSCAPS_MIP_MAP: u32 = 0x400000;
+pub const DDSCAPS2_CUBEMAP: u32 = 0x200;
+pub const DDSCAPS2_VOLUME: u32 = 0x200000;
+
+// DX10 header values
+pub const DX10_RESOURCE_DIMENSION_TEXTURE3D: u32 = 4;
+pub const DX10_MISC_TEXTURE_CUBE: u32 = 0x4;
+
+pub const DXGI_FORMAT_R8G8B8A8_UNORM: u32 = 28;
+pub const DXGI_FORMAT_R8G8B8A8_UNORM_SRGB: u32 = 29;
+pub const DXGI_FORMAT_BC1_UNORM: u32 = 71;
+pub const DXGI_FORMAT_BC1_UNORM_SRGB: u32 = 72;
+pub const DXGI_FORMAT_BC2_UNORM: u32 = 74;
+pub const DXGI_FORMAT_BC2_UNORM_SRGB: u32 = 75;
+pub const DXGI_FORMAT_BC3_UNORM: u32 = 77;
+pub const DXGI_FORMAT_BC3_UNORM_SRGB: u32 = 78;
+pub const DXGI_FORMAT_BC4_UNORM: u32 = 80;
+pub const DXGI_FORMAT_BC4_SNORM: u32 = 81;
+pub const DXGI_FORMAT_BC5_UNORM: u32 = 83;
+pub const DXGI_FORMAT_BC5_SNORM: u32 = 84;
+pub const DXGI_FORMAT_B8G8R8A8_UNORM: u32 = 87;
+pub const DXGI_FORMAT_B8G8R8X8_UNORM: u32 = 88;
+pub const DXGI_FORMAT_B8G8R8A8_UNORM_SRGB: u32 = 91;
+pub const DXGI_FORMAT_B8G8R8X8_UNORM_SRGB: u32 = 93;
+
+#[derive(Debug, Clone, Default)]
+pub struct PixelFormat {
+    pub flags: u32,
+    pub four_cc: [u8; 4],
+    pub rgb_bit_count: u32,
+    /// Red, green, blue and alpha
+    pub masks: [u32; 4],
+}
+
+impl PixelFormat {
+    pub const SIZE: u32 = 32;
+}
+
+impl BinarySerializable for PixelFormat {
+    fn read<R: Read>(reader: &mut R) -> io::Result<Self> where Self: Sized {
+        if reader.read_u32::<LittleEndian>()? != PixelFormat::SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid DDS pixel format size"));
+        }
+        let flags = reader.read_u32::<LittleEndian>()?;
+        let mut four_cc = [0; 4];
+        reader.read_exact(&mut four_cc)?;
+        let rgb_bit_count = reader.read_u32::<LittleEndian>()?;
+        let mut masks = [0; 4];
+        reader.read_u32_into::<LittleEndian>(&mut masks)?;
+
+        Ok(PixelFormat { flags, four_cc, rgb_bit_count, masks })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u32::<LittleEndian>(PixelFormat::SIZE)?;
+        writer.write_u32::<LittleEndian>(self.flags)?;
+        writer.write_all(&self.four_cc)?;
+        writer.write_u32::<LittleEndian>(self.rgb_bit_count)?;
+        for mask in self.masks {
+            writer.write_u32::<LittleEndian>(mask)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Header following the magic
+#[derive(Debug, Clone, Default)]
+pub struct Header {
+    pub flags: u32,
+    pub height: u32,
+    pub width: u32,
+    pub pitch_or_linear_size: u32,
+    pub depth: u32,
+    pub mip_map_count: u32,
+    pub pixel_format: PixelFormat,
+    pub caps: u32,
+    pub caps2: u32,
+}
+
+impl Header {
+    pub const SIZE: u32 = 124;
+
+    /// Files without a count hold a single level
+    pub fn mip_levels(&self) -> usize {
+        self.mip_map_count.max(1) as usize
+    }
+}
+
+impl BinarySerializable for Header {
+    fn read<R: Read>(reader: &mut R) -> io::Result<Self> where Self: Sized {
+        if reader.read_u32::<LittleEndian>()? != Header::SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid DDS header size"));
+        }
+        let flags = reader.read_u32::<LittleEndian>()?;
+        let height = reader.read_u32::<LittleEndian>()?;
+        let width = reader.read_u32::<LittleEndian>()?;
+        let pitch_or_linear_size = reader.read_u32::<LittleEndian>()?;
+        let depth = reader.read_u32::<LittleEndian>()?;
+        let mip_map_count = reader.read_u32::<LittleEndian>()?;
+        let _reserved = (0..11).map(|_| reader.read_u32::<LittleEndian>()).collect::<io::Result<Vec<_>>>()?;
+        let pixel_format = PixelFormat::read(reader)?;
+        let caps = reader.read_u32::<LittleEndian>()?;
+        let caps2 = reader.read_u32::<LittleEndian>()?;
+        let _caps3 = reader.read_u32::<LittleEndian>()?;
+        let _caps4 = reader.read_u32::<LittleEndian>()?;
+        let _reserved2 = reader.read_u32::<LittleEndian>()?;
+
+        Ok(Header { flags, height, width, pitch_or_linear_size, depth, mip_map_count, pixel_format, caps, caps2 })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u32::<LittleEndian>(Header::SIZE)?;
+        writer.write_u32::<LittleEndian>(self.flags)?;
+        writer.write_u32::<LittleEndian>(self.height)?;
+        writer.write_u32::<LittleEndian>(self.width)?;
+        writer.write_u32::<LittleEndian>(self.pitch_or_linear_size)?;
+        writer.write_u32::<LittleEndian>(self.depth)?;
+        writer.write_u32::<LittleEndian>(self.mip_map_count)?;
+        writer.write_all(&[0; 44])?;
+        self.pixel_format.write(writer)?;
+        writer.write_u32::<LittleEndian>(self.caps)?;
+        writer.write_u32::<LittleEndian>(self.caps2)?;
+        writer.write_all(&[0; 12])?;
+
+        Ok(())
+    }
+}
+
+/// Extended header present when the four CC is DX10
+#[derive(Debug, Clone, Default)]
+pub struct HeaderDX10 {
+    pub dxgi_format: u32,
+    pub resource_dimension: u32,
+    pub misc_flag: u32,
+    pub array_size: u32,
+    pub misc_flags2: u32,
+}
+
+impl HeaderDX10 {
+    pub const SIZE: u32 = 20;
+}
+
+impl BinarySerializable for HeaderDX10 {
+    fn read<R: Read>(reader: &mut R) -> io::Result<Self> where Self: Sized {
+        let dxgi_format = reader.read_u32::<LittleEndian>()?;
+        let resource_dimension = reader.read_u32::<LittleEndian>()?;
+        let misc_flag = reader.read_u32::<LittleEndian>()?;
+        let array_size = reader.read_u32::<LittleEndian>()?;
+        let misc_flags2 = reader.read_u32::<LittleEndian>()?;
+
+        Ok(HeaderDX10 { dxgi_format, resource_dimension, misc_flag, array_size, misc_flags2 })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u32::<LittleEndian>(self.dxgi_format)?;
+        writer.write_u32::<LittleEndian>(self.resource_dimension)?;
+        writer.write_u32::<LittleEndian>(self.misc_flag)?;
+        writer.write_u32::<LittleEndian>(self.array_size)?;
+        writer.write_u32::<LittleEndian>(self.misc_flags2)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    /// Pixels of `bit_count` bits whose red, green, blue and alpha are picked by the masks
+    Uncompressed { bit_count: u32, masks: [u32; 4] },
+    BC1,
+    BC2,
+    BC3,
+    /// Single channel, read as gray
+    BC4 { signed: bool },
+    /// Red and green channels
+    BC5 { signed: bool },
+}
+
+impl Format {
+    pub fn from_pixel_format(pixel_format: &PixelFormat) -> Option<Format> {
+        let flags = pixel_format.flags;
+        if flags & DDPF_FOUR_CC != 0 {
+            return match &pixel_format.four_cc {
+                b"DXT1" => Some(Format::BC1),
+                b"DXT2" | b"DXT3" => Some(Format::BC2),
+                b"DXT4" | b"DXT5" => Some(Format::BC3),
+                b"ATI1" | b"BC4U" => Some(Format::BC4 { signed: false }),
+                b"BC4S" => Some(Format::BC4 { signed: true }),
+                b"ATI2" | b"BC5U" => Some(Format::BC5 { signed: false }),
+                b"BC5S" => Some(Format::BC5 { signed: true }),
+                _ => None,
+            };
+        }
+
+        let bit_count = pixel_format.rgb_bit_count;
+        if !matches!(bit_count, 8 | 16 | 24 | 32) {
+            return None;
+        }
+        let [red, green, blue, alpha] = pixel_format.masks;
+        let alpha = if flags & (DDPF_ALPHA_PIXELS | DDPF_ALPHA) != 0 { alpha } else { 0 };
+        if flags & DDPF_RGB != 0 {
+            Some(Format::Uncompressed { bit_count, masks: [red, green, blue, alpha] })
+        } else if flags & DDPF_LUMINANCE != 0 {
+            Some(Format::Uncompressed { bit_count, masks: [red, red, red, alpha] })
+        } else if flags & DDPF_ALPHA != 0 {
+            Some(Format::Uncompressed { bit_count, masks: [0, 0, 0, alpha] })
+        } else {
+            None
+        }
+    }
+
+    /// sRGB formats keep their stored values
+    pub fn from_dxgi(dxgi_format: u32) -> Option<Format> {
+        match dxgi_format {
+            DXGI_FORMAT_R8G8B8A8_UNORM | DXGI_FORMAT_R8G8B8A8_UNORM_SRGB => {
+                Some(Format::Uncompressed { bit_count: 32, masks: [0xFF, 0xFF00, 0xFF0000, 0xFF000000] })
+            },
+            DXGI_FORMAT_B8G8R8A8_UNORM | DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => {
+                Some(Format::Uncompressed { bit_count: 32, masks: [0xFF0000, 0xFF00, 0xFF, 0xFF000000] })
+            },
+            DXGI_FORMAT_B8G8R8X8_UNORM | DXGI_FORMAT_B8G8R8X8_UNORM_SRGB => {
+                Some(Format::Uncompressed { bit_count: 32, masks: [0xFF0000, 0xFF00, 0xFF, 0] })
+            },
+            DXGI_FORMAT_BC1_UNORM | DXGI_FORMAT_BC1_UNORM_SRGB => Some(Format::BC1),
+            DXGI_FORMAT_BC2_UNORM | DXGI_FORMAT_BC2_UNORM_SRGB => Some(Format::BC2),
+            DXGI_FORMAT_BC3_UNORM | DXGI_FORMAT_BC3_UNORM_SRGB => Some(Format::BC3),
+            DXGI_FORMAT_BC4_UNORM => Some(Format::BC4 { signed: false }),
+            DXGI_FORMAT_BC4_SNORM => Some(Format::BC4 { signed: true }),
+            DXGI_FORMAT_BC5_UNORM => Some(Format::BC5 { signed: false }),
+            DXGI_FORMAT_BC5_SNORM => Some(Format::BC5 { signed: true }),
+            _ => None,
+        }
+    }
+
+    /// Bytes per 4x4 block of compressed formats
+    pub fn block_size(&self) -> Option<usize> {
+        match self {
+            Format::Uncompressed { .. } => None,
+            Format::BC1 | Format::BC4 { .. } => Some(8),
+            Format::BC2 | Format::BC3 | Format::BC5 { .. } => Some(16),
+        }
+    }
+
+    /// Bytes of one mip level
+    pub fn level_size(&self, width: usize, height: usize) -> usize {
+        match (self, self.block_size()) {
+            (_, Some(block_size)) => width.div_ceil(4) * height.div_ceil(4) * block_size,
+            (Format::Uncompressed { bit_count, .. }, None) => width * height * *bit_count as usize / 8,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl CubeFace {
+    /// Order of the faces in the file
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX, CubeFace::NegativeX,
+        CubeFace::PositiveY, CubeFace::NegativeY,
+        CubeFace::PositiveZ, CubeFace::NegativeZ,
+    ];
+
+    /// Capability flag marking the face as present in legacy files
+    pub fn caps2(&self) -> u32 {
+        0x400 << *self as u32
+    }
+}
+
+/// Array element or cubemap face
+pub struct Layer {
+    pub face: Option<CubeFace>,
+    /// From the full size image down to the smallest level
+    pub mips: Vec<Image>,
+}
+
+pub struct DDS {
+    pub format: Format,
+    pub layers: Vec<Layer>,
+}
+
+impl DDS {
+    pub fn is_cubemap(&self) -> bool {
+        self.layers.iter().any(|layer| layer.face.is_some())
+    }
+
+    /// Face of the first cubemap
+    pub fn face(&self, face: CubeFace) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.face == Some(face))
+    }
+
+    /// Mip level of the first layer
+    pub fn mip(&self, level: usize) -> Option<&Image> {
+        self.layers.first().and_then(|layer| layer.mips.get(level))
+    }
+}
@@ -0,0 +1,220 @@
+use std::fs;
+use std::io::{self, Cursor};
+
+use crate::binary_serializable::BinarySerializable;
+use crate::bmp_reader::extract;
+use crate::common::*;
+use crate::dds::{self, CubeFace, Format, Header, HeaderDX10, Layer, DDS};
+
+pub struct DDSReader {
+
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn unsupported(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, message.to_string())
+}
+
+fn expand_565(color: u16) -> [u8; 3] {
+    let (red, green, blue) = ((color >> 11) as u8, (color >> 5 & 0x3F) as u8, (color & 0x1F) as u8);
+    [red << 3 | red >> 2, green << 2 | green >> 4, blue << 3 | blue >> 2]
+}
+
+/// BC1 color block, BC2 and BC3 always use four colors
+fn color_block(block: &[u8], four_colors: bool) -> [Color; 16] {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+    let (first, second) = (expand_565(color0), expand_565(color1));
+    let mix = |weight0: u16, weight1: u16| {
+        let total = weight0 + weight1;
+        let channel = |i: usize| ((first[i] as u16 * weight0 + second[i] as u16 * weight1 + total / 2) / total) as u8;
+        Color::from_rgb(channel(0), channel(1), channel(2))
+    };
+    let palette = if four_colors || color0 > color1 {
+        [mix(1, 0), mix(0, 1), mix(2, 1), mix(1, 2)]
+    } else {
+        // The fourth color is transparent black
+        [mix(1, 0), mix(0, 1), mix(1, 1), Color::new(0, 0, 0, 0)]
+    };
+
+    let mut colors = [Color::black(); 16];
+    for (i, color) in colors.iter_mut().enumerate() {
+        *color = palette[(indices >> (i * 2) & 0b11) as usize];
+    }
+    colors
+}
+
+/// BC3 alpha and BC4 channel block of two endpoints and 3 bit indices
+fn channel_block(block: &[u8], signed: bool) -> [u8; 16] {
+    let indices = block[2..8].iter().rev().fold(0u64, |indices, &byte| indices << 8 | byte as u64);
+
+    // Signed values are interpolated as such and then moved to the unsigned range
+    let (value0, value1, min, max) = match signed {
+        true => ((block[0] as i8).max(-127) as i32, (block[1] as i8).max(-127) as i32, -127, 127),
+        false => (block[0] as i32, block[1] as i32, 0, 255),
+    };
+    let mix = |weight0: i32, weight1: i32| {
+        let total = weight0 + weight1;
+        (value0 * weight0 + value1 * weight1 + total / 2).div_euclid(total)
+    };
+    let palette = if value0 > value1 {
+        [value0, value1, mix(6, 1), mix(5, 2), mix(4, 3), mix(3, 4), mix(2, 5), mix(1, 6)]
+    } else {
+        [value0, value1, mix(4, 1), mix(3, 2), mix(2, 3), mix(1, 4), min, max]
+    };
+
+    let mut values = [0u8; 16];
+    for (i, value) in values.iter_mut().enumerate() {
+        let entry = palette[(indices >> (i * 3) & 0b111) as usize];
+        *value = ((entry - min) * 255 / (max - min)) as u8;
+    }
+    values
+}
+
+fn decode_block(format: Format, block: &[u8]) -> [Color; 16] {
+    match format {
+        Format::BC1 => color_block(block, false),
+        Format::BC2 => {
+            let mut colors = color_block(&block[8..], true);
+            for (i, color) in colors.iter_mut().enumerate() {
+                color.a = (block[i / 2] >> (i % 2 * 4) & 0xF) * 0x11;
+            }
+            colors
+        },
+        Format::BC3 => {
+            let mut colors = color_block(&block[8..], true);
+            for (color, alpha) in colors.iter_mut().zip(channel_block(block, false)) {
+                color.a = alpha;
+            }
+            colors
+        },
+        Format::BC4 { signed } => channel_block(block, signed).map(|value| Color::from_rgb(value, value, value)),
+        Format::BC5 { signed } => {
+            let (red, green) = (channel_block(block, signed), channel_block(&block[8..], signed));
+            std::array::from_fn(|i| Color::from_rgb(red[i], green[i], 0))
+        },
+        Format::Uncompressed { .. } => unreachable!(),
+    }
+}
+
+impl DDSReader {
+    fn decode_level(&self, format: Format, data: &[u8], width: usize, height: usize) -> Image {
+        let mut pixels = vec![vec![Color::black(); width]; height];
+
+        match (format, format.block_size()) {
+            (Format::Uncompressed { bit_count, masks }, _) => {
+                let size = bit_count as usize / 8;
+                for (row, row_data) in pixels.iter_mut().zip(data.chunks_exact(width * size)) {
+                    for (color, bytes) in row.iter_mut().zip(row_data.chunks_exact(size)) {
+                        let pixel = bytes.iter().rev().fold(0u32, |pixel, &byte| pixel << 8 | byte as u32);
+                        *color = Color::new(
+                            extract(pixel, masks[0]).unwrap_or(0),
+                            extract(pixel, masks[1]).unwrap_or(0),
+                            extract(pixel, masks[2]).unwrap_or(0),
+                            extract(pixel, masks[3]).unwrap_or(u8::MAX),
+                        );
+                    }
+                }
+            },
+            (_, Some(block_size)) => {
+                let blocks_width = width.div_ceil(4);
+                for (index, block) in data.chunks_exact(block_size).enumerate() {
+                    let (block_x, block_y) = (index % blocks_width * 4, index / blocks_width * 4);
+                    // Blocks on the right and bottom edges are cropped
+                    for (i, color) in decode_block(format, block).into_iter().enumerate() {
+                        let (x, y) = (block_x + i % 4, block_y + i / 4);
+                        if x < width && y < height {
+                            pixels[y][x] = color;
+                        }
+                    }
+                }
+            },
+            _ => unreachable!(),
+        }
+
+        Image::from_mat(width, height, pixels)
+    }
+
+    /// Decodes every layer and mip level, volume textures are not supported
+    pub fn decode(&self, data: &[u8]) -> io::Result<DDS> {
+        if !data.starts_with(dds::MAGIC) {
+            return Err(invalid_data("Invalid DDS file"));
+        }
+        let mut cursor = Cursor::new(&data[dds::MAGIC.len()..]);
+        let header = Header::read(&mut cursor)?;
+        if header.caps2 & dds::DDSCAPS2_VOLUME != 0 {
+            return Err(unsupported("Volume DDS textures are not supported"));
+        }
+
+        let pixel_format = &header.pixel_format;
+        let (format, faces, array_size) = if pixel_format.flags & dds::DDPF_FOUR_CC != 0 && &pixel_format.four_cc == b"DX10" {
+            let header_dx10 = HeaderDX10::read(&mut cursor)?;
+            if header_dx10.resource_dimension == dds::DX10_RESOURCE_DIMENSION_TEXTURE3D {
+                return Err(unsupported("Volume DDS textures are not supported"));
+            }
+            let format = Format::from_dxgi(header_dx10.dxgi_format).ok_or_else(|| unsupported("Unsupported DXGI format"))?;
+            let cube = header_dx10.misc_flag & dds::DX10_MISC_TEXTURE_CUBE != 0;
+            let faces: Vec<Option<CubeFace>> = match cube {
+                true => CubeFace::ALL.iter().copied().map(Some).collect(),
+                false => vec![None],
+            };
+            (format, faces, header_dx10.array_size.max(1) as usize)
+        } else {
+            let format = Format::from_pixel_format(pixel_format).ok_or_else(|| unsupported("Unsupported DDS pixel format"))?;
+            // Legacy cubemaps may leave some faces out
+            let faces = match header.caps2 & dds::DDSCAPS2_CUBEMAP != 0 {
+                true => CubeFace::ALL.iter().copied().filter(|face| header.caps2 & face.caps2() != 0).map(Some).collect(),
+                false => vec![None],
+            };
+            (format, faces, 1)
+        };
+
+        let (width, height) = (header.width as usize, header.height as usize);
+        if width == 0 || height == 0 || width.checked_mul(height).and_then(|pixels| pixels.checked_mul(16)).is_none() {
+            return Err(invalid_data("Invalid DDS size"));
+        }
+        let mip_levels = header.mip_levels().min(32);
+        let mut offset = dds::MAGIC.len() + cursor.position() as usize;
+
+        // Every layer must fit in the rest of the file before the array is expanded
+        let layer_size: usize = (0..mip_levels).map(|level| format.level_size((width >> level).max(1), (height >> level).max(1))).sum();
+        if layer_size.checked_mul(faces.len()).and_then(|size| size.checked_mul(array_size)).is_none_or(|size| size > data.len() - offset) {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete DDS data"));
+        }
+
+        let layers = faces.repeat(array_size).into_iter().map(|face| {
+            let mips = (0..mip_levels).map(|level| {
+                let (level_width, level_height) = ((width >> level).max(1), (height >> level).max(1));
+                let size = format.level_size(level_width, level_height);
+                let level_data = data.get(offset..offset + size)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete DDS data"))?;
+                offset += size;
+                Ok(self.decode_level(format, level_data, level_width, level_height))
+            }).collect::<io::Result<Vec<_>>>()?;
+            Ok(Layer { face, mips })
+        }).collect::<io::Result<Vec<_>>>()?;
+
+        Ok(DDS { format, layers })
+    }
+
+    pub fn read_dds(&self, path: &str) -> io::Result<DDS> {
+        println!("Reading DDS file");
+
+        let data = fs::read(path)?;
+        self.decode(&data)
+    }
+}
+
+impl Reader for DDSReader {
+    /// Full size image of the first layer
+    fn read(&self, path: &str) -> io::Result<Image> {
+        self.read_dds(path)?.layers.into_iter().next()
+            .and_then(|layer| layer.mips.into_iter().next())
+            .ok_or_else(|| invalid_data("DDS file has no images"))
+    }
+}
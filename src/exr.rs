@@ -0,0 +1,389 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::common::FloatImage;
+
+pub const MAGIC: [u8; 4] = [0x76, 0x2F, 0x31, 0x01];
+pub const VERSION: u32 = 2;
+
+pub(crate) const TILED_FLAG: u32 = 0x200;
+pub(crate) const LONG_NAMES_FLAG: u32 = 0x400;
+pub(crate) const DEEP_FLAG: u32 = 0x800;
+pub(crate) const MULTIPART_FLAG: u32 = 0x1000;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(i32)]
+pub enum PixelType {
+    UInt = 0,
+    Half = 1,
+    Float = 2,
+}
+
+impl PixelType {
+    pub fn size(&self) -> usize {
+        match self {
+            PixelType::Half => 2,
+            _ => 4,
+        }
+    }
+}
+
+/// Chunks that do not get smaller are stored uncompressed
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum Compression {
+    None = 0,
+    RLE = 1,
+    /// Zlib, one scanline per chunk
+    ZIPS = 2,
+    /// Zlib, 16 scanlines per chunk
+    #[default]
+    ZIP = 3,
+}
+
+impl Compression {
+    pub fn scanlines_per_chunk(&self) -> usize {
+        match self {
+            Compression::ZIP => 16,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub name: String,
+    pub pixel_type: PixelType,
+    /// Hint that samples are perceptually linear
+    pub linear: bool,
+}
+
+/// Inclusive pixel bounds
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Box2i {
+    pub x_min: i32,
+    pub y_min: i32,
+    pub x_max: i32,
+    pub y_max: i32,
+}
+
+impl Box2i {
+    pub fn new(width: usize, height: usize) -> Self {
+        Box2i { x_min: 0, y_min: 0, x_max: width as i32 - 1, y_max: height as i32 - 1 }
+    }
+
+    pub fn width(&self) -> usize {
+        (self.x_max as i64 - self.x_min as i64 + 1).max(0) as usize
+    }
+
+    pub fn height(&self) -> usize {
+        (self.y_max as i64 - self.y_min as i64 + 1).max(0) as usize
+    }
+
+    fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Box2i {
+            x_min: reader.read_i32::<LittleEndian>()?,
+            y_min: reader.read_i32::<LittleEndian>()?,
+            x_max: reader.read_i32::<LittleEndian>()?,
+            y_max: reader.read_i32::<LittleEndian>()?,
+        })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        [self.x_min, self.y_min, self.x_max, self.y_max].iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+}
+
+/// Header attribute that is not interpreted
+#[derive(Debug, Clone)]
+pub struct Attribute {
+    pub name: String,
+    pub attribute_type: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub channels: Vec<Channel>,
+    pub compression: Compression,
+    pub data_window: Box2i,
+    pub display_window: Box2i,
+    /// 0 for increasing y, 1 for decreasing y, 2 for random order
+    pub line_order: u8,
+    pub pixel_aspect_ratio: f32,
+    pub screen_window_center: (f32, f32),
+    pub screen_window_width: f32,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Header {
+    pub fn new(width: usize, height: usize, channels: Vec<Channel>, compression: Compression) -> Self {
+        Header {
+            channels,
+            compression,
+            data_window: Box2i::new(width, height),
+            display_window: Box2i::new(width, height),
+            line_order: 0,
+            pixel_aspect_ratio: 1.0,
+            screen_window_center: (0.0, 0.0),
+            screen_window_width: 1.0,
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Bytes of one scanline with all channels
+    pub fn scanline_size(&self) -> usize {
+        self.data_window.width() * self.channels.iter().map(|channel| channel.pixel_type.size()).sum::<usize>()
+    }
+
+    fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+        let mut bytes = Vec::new();
+        loop {
+            match reader.read_u8()? {
+                0 => break,
+                byte => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| invalid_data("Invalid EXR name"))
+    }
+
+    fn read_channels(data: &[u8]) -> io::Result<Vec<Channel>> {
+        let mut reader = data;
+        let mut channels = Vec::new();
+
+        loop {
+            let name = Header::read_string(&mut reader)?;
+            if name.is_empty() {
+                break;
+            }
+
+            let pixel_type = match reader.read_i32::<LittleEndian>()? {
+                0 => PixelType::UInt,
+                1 => PixelType::Half,
+                2 => PixelType::Float,
+                _ => return Err(invalid_data("Unknown EXR pixel type")),
+            };
+            let linear = reader.read_u8()? != 0;
+            let mut reserved = [0u8; 3];
+            reader.read_exact(&mut reserved)?;
+            let (x_sampling, y_sampling) = (reader.read_i32::<LittleEndian>()?, reader.read_i32::<LittleEndian>()?);
+            if x_sampling != 1 || y_sampling != 1 {
+                return Err(io::Error::new(io::ErrorKind::Unsupported, "Subsampled EXR channels are not supported"));
+            }
+
+            channels.push(Channel { name, pixel_type, linear });
+        }
+
+        Ok(channels)
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Header> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid_data("Invalid EXR file"));
+        }
+
+        let version = reader.read_u32::<LittleEndian>()?;
+        if version & 0xFF != VERSION {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "Unsupported EXR version"));
+        }
+        if version & (TILED_FLAG | DEEP_FLAG | MULTIPART_FLAG) != 0 {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "Only single part scanline EXR files are supported"));
+        }
+
+        let (mut channels, mut compression, mut data_window, mut display_window) = (None, None, None, None);
+        let mut header = Header::new(0, 0, Vec::new(), Compression::None);
+
+        loop {
+            let name = Header::read_string(reader)?;
+            if name.is_empty() {
+                break;
+            }
+            let attribute_type = Header::read_string(reader)?;
+            let size = reader.read_i32::<LittleEndian>()?;
+            let mut data = vec![0u8; usize::try_from(size).map_err(|_| invalid_data("Invalid EXR attribute size"))?];
+            reader.read_exact(&mut data)?;
+            let mut value = data.as_slice();
+
+            match (name.as_str(), attribute_type.as_str()) {
+                ("channels", "chlist") => channels = Some(Header::read_channels(&data)?),
+                ("compression", "compression") => compression = Some(match value.read_u8()? {
+                    0 => Compression::None,
+                    1 => Compression::RLE,
+                    2 => Compression::ZIPS,
+                    3 => Compression::ZIP,
+                    _ => return Err(io::Error::new(io::ErrorKind::Unsupported, "Unsupported EXR compression")),
+                }),
+                ("dataWindow", "box2i") => data_window = Some(Box2i::read(&mut value)?),
+                ("displayWindow", "box2i") => display_window = Some(Box2i::read(&mut value)?),
+                ("lineOrder", "lineOrder") => header.line_order = value.read_u8()?,
+                ("pixelAspectRatio", "float") => header.pixel_aspect_ratio = value.read_f32::<LittleEndian>()?,
+                ("screenWindowCenter", "v2f") => header.screen_window_center = (value.read_f32::<LittleEndian>()?, value.read_f32::<LittleEndian>()?),
+                ("screenWindowWidth", "float") => header.screen_window_width = value.read_f32::<LittleEndian>()?,
+                _ => header.attributes.push(Attribute { name, attribute_type, data }),
+            }
+        }
+
+        let missing = || invalid_data("Missing required EXR attribute");
+        header.channels = channels.ok_or_else(missing)?;
+        header.compression = compression.ok_or_else(missing)?;
+        header.data_window = data_window.ok_or_else(missing)?;
+        header.display_window = display_window.ok_or_else(missing)?;
+
+        Ok(header)
+    }
+
+    fn write_attribute<W: Write>(writer: &mut W, name: &str, attribute_type: &str, data: &[u8]) -> io::Result<()> {
+        writer.write_all(name.as_bytes())?;
+        writer.write_u8(0)?;
+        writer.write_all(attribute_type.as_bytes())?;
+        writer.write_u8(0)?;
+        writer.write_i32::<LittleEndian>(data.len() as i32)?;
+        writer.write_all(data)
+    }
+
+    /// Writes the magic number, version and attributes, channels must be sorted by name
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let long_names = self.channels.iter().map(|channel| channel.name.len())
+            .chain(self.attributes.iter().map(|attribute| attribute.name.len()))
+            .any(|length| length > 31);
+
+        writer.write_all(&MAGIC)?;
+        writer.write_u32::<LittleEndian>(VERSION | if long_names { LONG_NAMES_FLAG } else { 0 })?;
+
+        let mut channels = Vec::new();
+        for channel in self.channels.iter() {
+            channels.extend_from_slice(channel.name.as_bytes());
+            channels.push(0);
+            channels.extend_from_slice(&(channel.pixel_type as i32).to_le_bytes());
+            channels.extend_from_slice(&[channel.linear as u8, 0, 0, 0]);
+            channels.extend_from_slice(&1i32.to_le_bytes());
+            channels.extend_from_slice(&1i32.to_le_bytes());
+        }
+        channels.push(0);
+
+        let center = [self.screen_window_center.0.to_le_bytes(), self.screen_window_center.1.to_le_bytes()].concat();
+
+        Header::write_attribute(writer, "channels", "chlist", &channels)?;
+        Header::write_attribute(writer, "compression", "compression", &[self.compression as u8])?;
+        Header::write_attribute(writer, "dataWindow", "box2i", &self.data_window.to_bytes())?;
+        Header::write_attribute(writer, "displayWindow", "box2i", &self.display_window.to_bytes())?;
+        Header::write_attribute(writer, "lineOrder", "lineOrder", &[self.line_order])?;
+        Header::write_attribute(writer, "pixelAspectRatio", "float", &self.pixel_aspect_ratio.to_le_bytes())?;
+        Header::write_attribute(writer, "screenWindowCenter", "v2f", &center)?;
+        Header::write_attribute(writer, "screenWindowWidth", "float", &self.screen_window_width.to_le_bytes())?;
+        for attribute in self.attributes.iter() {
+            Header::write_attribute(writer, &attribute.name, &attribute.attribute_type, &attribute.data)?;
+        }
+        writer.write_u8(0)
+    }
+}
+
+/// Samples of the data window by channel, unsigned integer samples are converted to floats
+pub struct EXRImage {
+    pub header: Header,
+    pub samples: Vec<Vec<f32>>,
+}
+
+impl EXRImage {
+    /// Channels are named Y, YA, RGB or RGBA by their count
+    pub fn from_float_image(image: &FloatImage, pixel_type: PixelType, compression: Compression) -> io::Result<EXRImage> {
+        let names: &[&str] = match image.channels() {
+            1 => &["Y"],
+            2 => &["Y", "A"],
+            3 => &["R", "G", "B"],
+            4 => &["R", "G", "B", "A"],
+            _ => return Err(io::Error::new(io::ErrorKind::Unsupported, "Unsupported number of channels")),
+        };
+
+        let channels = names.iter().map(|name| Channel { name: name.to_string(), pixel_type, linear: false }).collect();
+        let samples = (0..names.len())
+            .map(|channel| image.data.iter().skip(channel).step_by(names.len()).copied().collect())
+            .collect();
+
+        Ok(EXRImage { header: Header::new(image.width(), image.height(), channels, compression), samples })
+    }
+
+    pub fn width(&self) -> usize {
+        self.header.data_window.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.header.data_window.height()
+    }
+
+    pub fn channel(&self, name: &str) -> Option<&[f32]> {
+        self.header.channels.iter().position(|channel| channel.name == name).map(|index| self.samples[index].as_slice())
+    }
+
+    /// RGB or Y channels with optional alpha, falls back to the first channel as gray
+    pub fn to_float_image(&self) -> io::Result<FloatImage> {
+        let names: Vec<&str> = match (self.channel("R"), self.channel("G"), self.channel("B"), self.channel("Y")) {
+            (Some(_), Some(_), Some(_), _) => vec!["R", "G", "B"],
+            (_, _, _, Some(_)) => vec!["Y"],
+            _ => vec![self.header.channels.first().ok_or_else(|| invalid_data("EXR image has no channels"))?.name.as_str()],
+        };
+        let mut channels: Vec<&[f32]> = names.iter().filter_map(|name| self.channel(name)).collect();
+        if let Some(alpha) = self.channel("A") {
+            channels.push(alpha);
+        }
+
+        let count = self.width() * self.height();
+        let data = (0..count).flat_map(|i| channels.iter().map(move |channel| channel[i])).collect();
+
+        Ok(FloatImage::from_data(self.width(), self.height(), channels.len(), data))
+    }
+}
+
+pub fn half_to_f32(half: u16) -> f32 {
+    let sign = ((half as u32) & 0x8000) << 16;
+    let exponent = ((half >> 10) & 0x1F) as u32;
+    let mantissa = (half & 0x03FF) as u32;
+
+    match exponent {
+        0 => {
+            let value = mantissa as f32 * 2f32.powi(-24);
+            if sign != 0 { -value } else { value }
+        },
+        31 => f32::from_bits(sign | 0x7F80_0000 | mantissa << 13),
+        _ => f32::from_bits(sign | (exponent + 112) << 23 | mantissa << 13),
+    }
+}
+
+/// Rounds to the nearest half, ties to even
+pub fn f32_to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x007F_FFFF;
+
+    if exponent == 0xFF {
+        return sign | 0x7C00 | if mantissa != 0 { 0x0200 } else { 0 };
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 31 {
+        return sign | 0x7C00;
+    }
+
+    let round = |value: u32, shift: u32| {
+        let (truncated, remainder, halfway) = (value >> shift, value & ((1 << shift) - 1), 1 << (shift - 1));
+        if remainder > halfway || (remainder == halfway && truncated & 1 == 1) { truncated + 1 } else { truncated }
+    };
+
+    if half_exponent <= 0 {
+        if half_exponent < -10 {
+            return sign;
+        }
+        return sign | round(mantissa | 0x0080_0000, (14 - half_exponent) as u32) as u16;
+    }
+
+    // A carry out of the mantissa correctly increments the exponent
+    sign | round((half_exponent as u32) << 23 | mantissa, 13) as u16
+}
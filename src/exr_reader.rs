@@ -0,0 +1,153 @@
+use std::fs;
+use std::io::{self, Cursor, Read};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use flate2::read::ZlibDecoder;
+
+use crate::common::*;
+use crate::exr::{self, Compression, EXRImage, Header, PixelType};
+
+pub struct EXRReader {
+
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Runs are a count followed by the byte repeated count + 1 times, negative counts precede literal bytes
+fn decode_rle(data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(size);
+    let mut position = 0;
+
+    while position < data.len() && output.len() < size {
+        let count = data[position] as i8;
+        position += 1;
+
+        if count < 0 {
+            let literal = data.get(position..position + count.unsigned_abs() as usize).ok_or_else(|| invalid_data("Invalid EXR RLE data"))?;
+            output.extend_from_slice(literal);
+            position += literal.len();
+        } else {
+            let value = *data.get(position).ok_or_else(|| invalid_data("Invalid EXR RLE data"))?;
+            output.extend(std::iter::repeat_n(value, count as usize + 1));
+            position += 1;
+        }
+    }
+
+    Ok(output)
+}
+
+/// Undoes the byte delta predictor and the split of even and odd bytes
+fn reconstruct(mut data: Vec<u8>) -> Vec<u8> {
+    for i in 1..data.len() {
+        data[i] = data[i - 1].wrapping_add(data[i]).wrapping_sub(128);
+    }
+
+    let (even, odd) = data.split_at(data.len().div_ceil(2));
+    let mut output = Vec::with_capacity(data.len());
+    for (i, &byte) in even.iter().enumerate() {
+        output.push(byte);
+        if let Some(&byte) = odd.get(i) {
+            output.push(byte);
+        }
+    }
+    output
+}
+
+impl EXRReader {
+    fn decompress(&self, compression: Compression, data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+        let data = match compression {
+            Compression::None => return Ok(data.to_vec()),
+            Compression::RLE => decode_rle(data, size)?,
+            Compression::ZIPS | Compression::ZIP => {
+                // One byte past the expected size is enough to tell that the chunk is too large
+                let mut output = Vec::with_capacity(size);
+                ZlibDecoder::new(data).take(size as u64 + 1).read_to_end(&mut output)?;
+                output
+            },
+        };
+
+        if data.len() != size {
+            return Err(invalid_data("Invalid EXR chunk size"));
+        }
+        Ok(reconstruct(data))
+    }
+
+    pub fn decode(&self, data: &[u8]) -> io::Result<EXRImage> {
+        let mut cursor = Cursor::new(data);
+        let header = Header::read(&mut cursor)?;
+        if header.channels.is_empty() {
+            return Err(invalid_data("EXR file has no channels"));
+        }
+        let (width, height) = (header.data_window.width(), header.data_window.height());
+        let lines_per_chunk = header.compression.scanlines_per_chunk();
+
+        let offsets = (0..height.div_ceil(lines_per_chunk))
+            .map(|_| cursor.read_u64::<LittleEndian>())
+            .collect::<io::Result<Vec<u64>>>()?;
+
+        // Every chunk in the offset table decompresses to at most the largest ratio of its compression
+        let pixel_size: usize = header.channels.iter().map(|channel| channel.pixel_type.size()).sum();
+        let max_ratio = match header.compression {
+            Compression::None => 1,
+            Compression::RLE => 64,
+            Compression::ZIPS | Compression::ZIP => 1032,
+        };
+        let too_large = || invalid_data("Invalid EXR size");
+        let pixel_count = width.checked_mul(height)
+            .filter(|&count| count.checked_mul(pixel_size).is_some_and(|size| size <= (data.len() - cursor.position() as usize).saturating_mul(max_ratio)))
+            .ok_or_else(too_large)?;
+
+        let mut samples = vec![vec![0f32; pixel_count]; header.channels.len()];
+
+        for offset in offsets {
+            cursor.set_position(offset);
+            let y = cursor.read_i32::<LittleEndian>()?;
+            let size = cursor.read_i32::<LittleEndian>()?;
+
+            let first = (y as i64 - header.data_window.y_min as i64) as usize;
+            if first >= height {
+                return Err(invalid_data("Invalid EXR chunk scanline"));
+            }
+            let lines = lines_per_chunk.min(height - first);
+            let expected = header.scanline_size() * lines;
+
+            let start = cursor.position() as usize;
+            let chunk = usize::try_from(size).ok()
+                .and_then(|size| data.get(start..start + size))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete EXR chunk"))?;
+
+            let block = if chunk.len() == expected { chunk.to_vec() } else { self.decompress(header.compression, chunk, expected)? };
+
+            let mut reader = block.as_slice();
+            for line in first..first + lines {
+                for (channel, channel_samples) in header.channels.iter().zip(samples.iter_mut()) {
+                    for sample in channel_samples[line * width..(line + 1) * width].iter_mut() {
+                        *sample = match channel.pixel_type {
+                            PixelType::UInt => reader.read_u32::<LittleEndian>()? as f32,
+                            PixelType::Half => exr::half_to_f32(reader.read_u16::<LittleEndian>()?),
+                            PixelType::Float => reader.read_f32::<LittleEndian>()?,
+                        };
+                    }
+                }
+            }
+        }
+
+        Ok(EXRImage { header, samples })
+    }
+
+    pub fn read_exr(&self, path: &str) -> io::Result<EXRImage> {
+        println!("Reading EXR file");
+
+        let data = fs::read(path)?;
+        self.decode(&data)
+    }
+}
+
+impl Reader for EXRReader {
+    /// Samples are clamped to 0..=1
+    fn read(&self, path: &str) -> io::Result<Image> {
+        Ok(self.read_exr(path)?.to_float_image()?.to_image())
+    }
+}
@@ -0,0 +1,139 @@
+use std::fs;
+use std::io::{self, Write};
+
+use flate2::write::ZlibEncoder;
+
+use crate::common::*;
+use crate::exr::{self, Compression, EXRImage, PixelType};
+
+pub struct Settings {
+    pub compression: Compression,
+    pub pixel_type: PixelType,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings { compression: Compression::default(), pixel_type: PixelType::Half }
+    }
+}
+
+pub struct EXRWriter {
+    pub settings: Settings
+}
+
+/// Splits even and odd bytes and replaces bytes by their difference to the previous one
+fn predict(data: &[u8]) -> Vec<u8> {
+    let mut output: Vec<u8> = data.iter().step_by(2).chain(data.iter().skip(1).step_by(2)).copied().collect();
+    for i in (1..output.len()).rev() {
+        output[i] = output[i].wrapping_sub(output[i - 1]).wrapping_add(128);
+    }
+    output
+}
+
+/// Runs of at least 3 bytes are stored as a count and the byte, other bytes as negative counted literals
+fn encode_rle(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut i = 0;
+
+    while i < data.len() {
+        let run = data[i..].iter().take(128).take_while(|&&byte| byte == data[i]).count();
+        if run >= 3 {
+            output.extend_from_slice(&[(run - 1) as u8, data[i]]);
+            i += run;
+            continue;
+        }
+
+        let start = i;
+        while i < data.len() && i - start < 127 && !(i + 2 < data.len() && data[i] == data[i + 1] && data[i] == data[i + 2]) {
+            i += 1;
+        }
+        output.push((-((i - start) as i8)) as u8);
+        output.extend_from_slice(&data[start..i]);
+    }
+
+    output
+}
+
+impl EXRWriter {
+    fn compress(&self, compression: Compression, data: &[u8]) -> io::Result<Vec<u8>> {
+        let compressed = match compression {
+            Compression::None => return Ok(data.to_vec()),
+            Compression::RLE => encode_rle(&predict(data)),
+            Compression::ZIPS | Compression::ZIP => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&predict(data))?;
+                encoder.finish()?
+            },
+        };
+
+        Ok(if compressed.len() < data.len() { compressed } else { data.to_vec() })
+    }
+
+    pub fn encode(&self, image: &EXRImage) -> io::Result<Vec<u8>> {
+        let (width, height) = (image.width(), image.height());
+        if image.samples.len() != image.header.channels.len() || image.samples.iter().any(|samples| samples.len() != width * height) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "EXR samples do not match the header"));
+        }
+
+        // Channels are stored sorted by name
+        let mut order: Vec<usize> = (0..image.header.channels.len()).collect();
+        order.sort_by(|&a, &b| image.header.channels[a].name.cmp(&image.header.channels[b].name));
+        let mut header = image.header.clone();
+        header.channels = order.iter().map(|&index| image.header.channels[index].clone()).collect();
+        header.line_order = 0;
+
+        let mut data = Vec::new();
+        header.write(&mut data)?;
+
+        let lines_per_chunk = header.compression.scanlines_per_chunk();
+        let chunk_count = height.div_ceil(lines_per_chunk);
+        let table_offset = data.len();
+        data.resize(table_offset + chunk_count * 8, 0);
+
+        for chunk in 0..chunk_count {
+            let first = chunk * lines_per_chunk;
+            let mut block = Vec::with_capacity(header.scanline_size() * lines_per_chunk);
+
+            for line in first..(first + lines_per_chunk).min(height) {
+                for &index in order.iter() {
+                    let samples = &image.samples[index][line * width..(line + 1) * width];
+                    for &sample in samples {
+                        match image.header.channels[index].pixel_type {
+                            PixelType::UInt => block.extend_from_slice(&(sample.max(0.0).round() as u32).to_le_bytes()),
+                            PixelType::Half => block.extend_from_slice(&exr::f32_to_half(sample).to_le_bytes()),
+                            PixelType::Float => block.extend_from_slice(&sample.to_le_bytes()),
+                        }
+                    }
+                }
+            }
+
+            let compressed = self.compress(header.compression, &block)?;
+            let offset = data.len() as u64;
+            data[table_offset + chunk * 8..table_offset + chunk * 8 + 8].copy_from_slice(&offset.to_le_bytes());
+
+            data.extend_from_slice(&(header.data_window.y_min + first as i32).to_le_bytes());
+            data.extend_from_slice(&(compressed.len() as i32).to_le_bytes());
+            data.extend_from_slice(&compressed);
+        }
+
+        Ok(data)
+    }
+
+    pub fn write_exr(&self, image: &EXRImage, path: &str) -> io::Result<()> {
+        println!("Writing EXR file at path: {}", path);
+
+        fs::write(path, self.encode(image)?)
+    }
+}
+
+impl Writer for EXRWriter {
+    fn extension(&self) -> &str {
+        "exr"
+    }
+
+    fn write(&self, image: Image, path: &str) {
+        let image = EXRImage::from_float_image(&FloatImage::from_image(&image), self.settings.pixel_type, self.settings.compression)
+            .expect("Can't convert image to EXR");
+        self.write_exr(&image, path).expect("Can't save output EXR file");
+    }
+}
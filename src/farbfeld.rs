@@ -0,0 +1,79 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::binary_serializable::BinarySerializable;
+
+pub const MAGIC: [u8; 8] = *b"farbfeld";
+
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Header {
+    /// Sizes beyond 32 bits cannot be stored
+    pub fn new(width: usize, height: usize) -> io::Result<Header> {
+        let too_large = |_| io::Error::new(io::ErrorKind::InvalidInput, "Image too large for farbfeld");
+        Ok(Header { width: u32::try_from(width).map_err(too_large)?, height: u32::try_from(height).map_err(too_large)? })
+    }
+}
+
+impl BinarySerializable for Header {
+    fn read<R: Read>(reader: &mut R) -> io::Result<Self> where Self: Sized {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid farbfeld file"));
+        }
+
+        let width = reader.read_u32::<BigEndian>()?;
+        let height = reader.read_u32::<BigEndian>()?;
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid farbfeld size"));
+        }
+
+        Ok(Header { width, height })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_u32::<BigEndian>(self.width)?;
+        writer.write_u32::<BigEndian>(self.height)?;
+
+        Ok(())
+    }
+}
+
+/// Reads the header and passes each row of 16 bit RGBA samples to `row`
+pub(crate) fn read_rows<R: Read>(reader: &mut R, mut row: impl FnMut(&[u16])) -> io::Result<Header> {
+    let header = Header::read(reader)?;
+    let row_size = header.width as u64 * 8;
+    // Buffers grow with the data read, so a bad width in the header cannot allocate more than the input
+    let mut bytes = Vec::new();
+    let mut samples = Vec::new();
+
+    for _ in 0..header.height {
+        bytes.clear();
+        reader.by_ref().take(row_size).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != row_size {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete farbfeld row"));
+        }
+        samples.clear();
+        samples.extend(bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])));
+        row(&samples);
+    }
+
+    Ok(header)
+}
+
+/// Writes the header and the rows of 16 bit RGBA samples
+pub(crate) fn write_rows<W: Write>(writer: &mut W, header: &Header, rows: impl Iterator<Item = Vec<u16>>) -> io::Result<()> {
+    header.write(writer)?;
+    for row in rows {
+        let bytes: Vec<u8> = row.iter().flat_map(|sample| sample.to_be_bytes()).collect();
+        writer.write_all(&bytes)?;
+    }
+    writer.flush()
+}
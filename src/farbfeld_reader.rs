@@ -0,0 +1,50 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+
+use crate::common::*;
+use crate::farbfeld;
+
+pub struct FarbfeldReader {
+
+}
+
+impl FarbfeldReader {
+    /// Reads an image from a stream such as stdin, samples are rounded to 8 bits
+    pub fn read_from<R: Read>(&self, reader: &mut R) -> io::Result<Image> {
+        let to_8_bit = |sample: u16| ((sample as u32 * 255 + 32767) / 65535) as u8;
+        let mut pixels = Vec::new();
+
+        let header = farbfeld::read_rows(reader, |row| {
+            pixels.push(row.chunks_exact(4)
+                .map(|rgba| Color::new(to_8_bit(rgba[0]), to_8_bit(rgba[1]), to_8_bit(rgba[2]), to_8_bit(rgba[3])))
+                .collect());
+        })?;
+
+        Ok(Image::from_mat(header.width as usize, header.height as usize, pixels))
+    }
+
+    /// Reads an image keeping the full 16 bit precision, RGBA samples in 0..=1
+    pub fn read_float_from<R: Read>(&self, reader: &mut R) -> io::Result<FloatImage> {
+        let mut data = Vec::new();
+
+        let header = farbfeld::read_rows(reader, |row| {
+            data.extend(row.iter().map(|&sample| sample as f32 / 65535.0));
+        })?;
+
+        Ok(FloatImage::from_data(header.width as usize, header.height as usize, 4, data))
+    }
+
+    pub fn read_float(&self, path: &str) -> io::Result<FloatImage> {
+        println!("Reading farbfeld file");
+
+        self.read_float_from(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+impl Reader for FarbfeldReader {
+    fn read(&self, path: &str) -> io::Result<Image> {
+        println!("Reading farbfeld file");
+
+        self.read_from(&mut BufReader::new(File::open(path)?))
+    }
+}
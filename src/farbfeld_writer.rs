@@ -0,0 +1,58 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::common::*;
+use crate::farbfeld::{self, Header};
+
+pub struct FarbfeldWriter {
+
+}
+
+impl FarbfeldWriter {
+    /// Writes the image to a stream such as stdout
+    pub fn write_to<W: Write>(&self, image: &Image, writer: &mut W) -> io::Result<()> {
+        let header = Header::new(image.width(), image.height())?;
+        let rows = image.pixels.iter()
+            .map(|row| row.iter().flat_map(|color| [color.r, color.g, color.b, color.a]).map(|sample| sample as u16 * 257).collect());
+
+        farbfeld::write_rows(writer, &header, rows)
+    }
+
+    /// Writes RGBA or RGB samples in 0..=1 with 16 bit precision, gray images are expanded
+    pub fn write_float_to<W: Write>(&self, image: &FloatImage, writer: &mut W) -> io::Result<()> {
+        let header = Header::new(image.width(), image.height())?;
+        let to_16_bit = |sample: f32| (sample.clamp(0.0, 1.0) * 65535.0).round() as u16;
+
+        let rows = (0..image.height()).map(|y| (0..image.width()).flat_map(|x| {
+            let pixel = image.pixel(x, y);
+            match *pixel {
+                [gray] => [gray, gray, gray, 1.0],
+                [gray, alpha] => [gray, gray, gray, alpha],
+                [r, g, b] => [r, g, b, 1.0],
+                [r, g, b, a, ..] => [r, g, b, a],
+                [] => [0.0, 0.0, 0.0, 1.0],
+            }
+        }).map(to_16_bit).collect());
+
+        farbfeld::write_rows(writer, &header, rows)
+    }
+
+    pub fn write_float(&self, image: &FloatImage, path: &str) -> io::Result<()> {
+        println!("Writing farbfeld file at path: {}", path);
+
+        self.write_float_to(image, &mut BufWriter::new(File::create(path)?))
+    }
+}
+
+impl Writer for FarbfeldWriter {
+    fn extension(&self) -> &str {
+        "ff"
+    }
+
+    fn write(&self, image: Image, path: &str) {
+        println!("Writing farbfeld file at path: {}", path);
+
+        let file = File::create(path).expect("Can't create output farbfeld file");
+        self.write_to(&image, &mut BufWriter::new(file)).expect("Can't save output farbfeld file");
+    }
+}
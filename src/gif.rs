@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::io;
+
+use crate::common::Image;
+
+pub const MAGIC_87A: &[u8; 6] = b"GIF87a";
+pub const MAGIC_89A: &[u8; 6] = b"GIF89a";
+
+pub(crate) const EXTENSION: u8 = 0x21;
+pub(crate) const IMAGE_DESCRIPTOR: u8 = 0x2C;
+pub(crate) const TRAILER: u8 = 0x3B;
+pub(crate) const GRAPHIC_CONTROL: u8 = 0xF9;
+pub(crate) const APPLICATION: u8 = 0xFF;
+
+const MAX_CODES: usize = 4096;
+/// Codes take at least 2 bits and each one expands to at most `MAX_CODES` pixels
+pub(crate) const MAX_PIXELS_PER_BYTE: usize = 4 * MAX_CODES;
+
+/// What happens to the frame area before the next frame is drawn
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Disposal {
+    #[default]
+    Unspecified,
+    /// Leave the frame in place
+    Keep,
+    /// Clear the frame area to transparent
+    Background,
+    /// Restore the canvas to what it was before the frame
+    Previous,
+}
+
+impl Disposal {
+    pub fn from_bits(bits: u8) -> Disposal {
+        match bits {
+            1 => Disposal::Keep,
+            2 => Disposal::Background,
+            3 => Disposal::Previous,
+            _ => Disposal::Unspecified,
+        }
+    }
+
+    pub fn bits(&self) -> u8 {
+        match self {
+            Disposal::Unspecified => 0,
+            Disposal::Keep => 1,
+            Disposal::Background => 2,
+            Disposal::Previous => 3,
+        }
+    }
+}
+
+pub struct Frame {
+    /// The whole canvas after the frame is drawn
+    pub image: Image,
+    /// In hundredths of a second
+    pub delay: u16,
+    pub disposal: Disposal,
+}
+
+pub struct GIF {
+    pub width: usize,
+    pub height: usize,
+    /// Number of repetitions, 0 repeats forever, None plays once
+    pub loop_count: Option<u16>,
+    pub frames: Vec<Frame>,
+}
+
+/// Appends the bytes of the code by walking its prefixes backwards
+fn append(output: &mut Vec<u8>, mut code: u16, prefixes: &[u16], suffixes: &[u8], lengths: &[usize]) {
+    let start = output.len();
+    output.resize(start + lengths[code as usize], 0);
+    for i in (start..output.len()).rev() {
+        output[i] = suffixes[code as usize];
+        code = prefixes[code as usize];
+    }
+}
+
+/// Decodes variable length codes packed least significant bit first
+pub(crate) fn lzw_decode(data: &[u8], min_code_size: u8, pixel_count: usize) -> io::Result<Vec<u8>> {
+    if !(1..=11).contains(&min_code_size) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid GIF LZW code size"));
+    }
+
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+
+    // Every code is a prefix code and a last byte
+    let mut prefixes = vec![0u16; MAX_CODES];
+    let mut suffixes = vec![0u8; MAX_CODES];
+    let mut lengths = vec![0usize; MAX_CODES];
+    for code in 0..clear {
+        suffixes[code as usize] = code as u8;
+        lengths[code as usize] = 1;
+    }
+
+    let mut output = Vec::with_capacity(pixel_count.min(data.len().saturating_mul(MAX_PIXELS_PER_BYTE)));
+    let (mut code_size, mut next) = (min_code_size + 1, end + 1);
+    let mut previous: Option<u16> = None;
+    let (mut buffer, mut bits, mut position) = (0u32, 0u8, 0usize);
+
+    while output.len() < pixel_count {
+        while bits < code_size {
+            match data.get(position) {
+                Some(&byte) => buffer |= (byte as u32) << bits,
+                None => return Ok(output),
+            }
+            position += 1;
+            bits += 8;
+        }
+        let code = (buffer & ((1 << code_size) - 1)) as u16;
+        buffer >>= code_size;
+        bits -= code_size;
+
+        if code == clear {
+            code_size = min_code_size + 1;
+            next = end + 1;
+            previous = None;
+            continue;
+        }
+        if code == end {
+            break;
+        }
+
+        let Some(previous_code) = previous else {
+            if code >= clear {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid GIF LZW code"));
+            }
+            output.push(code as u8);
+            previous = Some(code);
+            continue;
+        };
+
+        let start = output.len();
+        if code < next {
+            append(&mut output, code, &prefixes, &suffixes, &lengths);
+        } else if code == next {
+            append(&mut output, previous_code, &prefixes, &suffixes, &lengths);
+            output.push(output[start]);
+        } else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid GIF LZW code"));
+        }
+
+        if (next as usize) < MAX_CODES {
+            prefixes[next as usize] = previous_code;
+            suffixes[next as usize] = output[start];
+            lengths[next as usize] = lengths[previous_code as usize] + 1;
+            next += 1;
+            if next == 1 << code_size && code_size < 12 {
+                code_size += 1;
+            }
+        }
+        previous = Some(code);
+    }
+
+    output.truncate(pixel_count);
+    Ok(output)
+}
+
+/// Packs codes least significant bit first
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, code_size: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += code_size;
+        while self.bits >= 8 {
+            self.output.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.output.push(self.buffer as u8);
+        }
+        self.output
+    }
+}
+
+/// Encodes palette indices, clearing the table when it is full
+pub(crate) fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+
+    let mut writer = BitWriter { output: Vec::new(), buffer: 0, bits: 0 };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let (mut code_size, mut next) = (min_code_size + 1, end + 1);
+    writer.write(clear, code_size);
+
+    let Some((&first, rest)) = indices.split_first() else {
+        writer.write(end, code_size);
+        return writer.finish();
+    };
+    let mut prefix = first as u16;
+
+    for &index in rest {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+
+        writer.write(prefix, code_size);
+        table.insert((prefix, index), next);
+        next += 1;
+        if next > 1 << code_size && code_size < 12 {
+            code_size += 1;
+        }
+        if next as usize == MAX_CODES {
+            writer.write(clear, code_size);
+            table.clear();
+            code_size = min_code_size + 1;
+            next = end + 1;
+        }
+        prefix = index as u16;
+    }
+
+    writer.write(prefix, code_size);
+    writer.write(end, code_size);
+    writer.finish()
+}
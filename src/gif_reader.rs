@@ -0,0 +1,197 @@
+use std::fs;
+use std::io;
+
+use crate::common::*;
+use crate::gif::{self, Disposal, Frame, GIF};
+
+pub struct GIFReader {
+
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads bytes from the file, failing at its end
+struct Bytes<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        let bytes = self.data.get(self.position..self.position + count)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete GIF data"))?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Concatenated data sub-blocks up to the zero length terminator
+    fn sub_blocks(&mut self) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        loop {
+            match self.u8()? {
+                0 => return Ok(data),
+                length => data.extend_from_slice(self.take(length as usize)?),
+            }
+        }
+    }
+
+    fn color_table(&mut self, size_bits: u8) -> io::Result<Vec<Color>> {
+        let entries = self.take(3 << (size_bits + 1))?;
+        Ok(entries.chunks_exact(3).map(|rgb| Color::from_rgb(rgb[0], rgb[1], rgb[2])).collect())
+    }
+}
+
+/// Graphic control extension applying to the next image
+#[derive(Default)]
+struct GraphicControl {
+    disposal: Disposal,
+    delay: u16,
+    transparent: Option<u8>,
+}
+
+impl GIFReader {
+    /// Rows in the order they are stored in an interlaced image
+    fn interlaced_rows(&self, height: usize) -> Vec<usize> {
+        [(0, 8), (4, 8), (2, 4), (1, 2)].iter()
+            .flat_map(|&(start, step)| (start..height).step_by(step))
+            .collect()
+    }
+
+    /// Decodes all frames composited onto a canvas that starts transparent
+    pub fn decode(&self, data: &[u8]) -> io::Result<GIF> {
+        let mut bytes = Bytes { data, position: 0 };
+
+        let magic = bytes.take(6)?;
+        if magic != gif::MAGIC_87A && magic != gif::MAGIC_89A {
+            return Err(invalid_data("Invalid GIF file"));
+        }
+
+        let (width, height) = (bytes.u16()? as usize, bytes.u16()? as usize);
+        let flags = bytes.u8()?;
+        let _background = bytes.u8()?;
+        let _aspect_ratio = bytes.u8()?;
+        let global_table = if flags & 0x80 != 0 { Some(bytes.color_table(flags & 0x07)?) } else { None };
+
+        // The canvas is only allocated with the first frame, and its size must be reachable from the data
+        let mut canvas: Option<Image> = None;
+        let mut gif = GIF { width, height, loop_count: None, frames: Vec::new() };
+        let mut control = GraphicControl::default();
+
+        loop {
+            match bytes.u8()? {
+                gif::EXTENSION => {
+                    let label = bytes.u8()?;
+                    let block = bytes.sub_blocks()?;
+                    match label {
+                        gif::GRAPHIC_CONTROL if block.len() >= 4 => control = GraphicControl {
+                            disposal: Disposal::from_bits((block[0] >> 2) & 0x07),
+                            delay: u16::from_le_bytes([block[1], block[2]]),
+                            transparent: if block[0] & 0x01 != 0 { Some(block[3]) } else { None },
+                        },
+                        // Sub-blocks of NETSCAPE2.0 are joined, the loop count follows the sub-block id
+                        gif::APPLICATION if block.starts_with(b"NETSCAPE2.0") && block.len() >= 14 && block[11] == 1 => {
+                            gif.loop_count = Some(u16::from_le_bytes([block[12], block[13]]));
+                        },
+                        _ => {},
+                    }
+                },
+                gif::IMAGE_DESCRIPTOR => {
+                    let (left, top) = (bytes.u16()? as usize, bytes.u16()? as usize);
+                    let (frame_width, frame_height) = (bytes.u16()? as usize, bytes.u16()? as usize);
+                    let flags = bytes.u8()?;
+                    let local_table = if flags & 0x80 != 0 { Some(bytes.color_table(flags & 0x07)?) } else { None };
+                    let table = local_table.as_ref().or(global_table.as_ref())
+                        .ok_or_else(|| invalid_data("GIF image without a color table"))?;
+
+                    let min_code_size = bytes.u8()?;
+                    let indices = gif::lzw_decode(&bytes.sub_blocks()?, min_code_size, frame_width * frame_height)?;
+
+                    let rows: Vec<usize> = if flags & 0x40 != 0 {
+                        self.interlaced_rows(frame_height)
+                    } else {
+                        (0..frame_height).collect()
+                    };
+
+                    let canvas = match canvas.as_mut() {
+                        Some(canvas) => canvas,
+                        None if width.saturating_mul(height) <= data.len().saturating_mul(gif::MAX_PIXELS_PER_BYTE) => canvas.insert(Image::new(width, height)),
+                        None => return Err(invalid_data("GIF canvas is too large for its data")),
+                    };
+
+                    // Only the frame area changes, so only it is kept for restoring
+                    let frame_area = |pixels: &[Vec<Color>]| -> Vec<Vec<Color>> {
+                        pixels.iter().skip(top).take(frame_height).map(|row| row.iter().skip(left).take(frame_width).copied().collect()).collect()
+                    };
+                    let previous = (control.disposal == Disposal::Previous).then(|| frame_area(&canvas.pixels));
+
+                    // Missing indices at the end of truncated data leave the canvas unchanged
+                    for (row, line) in indices.chunks(frame_width.max(1)).zip(rows.iter()) {
+                        let Some(canvas_row) = canvas.pixels.get_mut(top + line) else { continue };
+                        for (x, &index) in row.iter().enumerate() {
+                            if Some(index) == control.transparent {
+                                continue;
+                            }
+                            if let (Some(pixel), Some(&color)) = (canvas_row.get_mut(left + x), table.get(index as usize)) {
+                                *pixel = color;
+                            }
+                        }
+                    }
+
+                    gif.frames.push(Frame {
+                        image: Image::from_mat(width, height, canvas.pixels.clone()),
+                        delay: control.delay,
+                        disposal: control.disposal,
+                    });
+
+                    match control.disposal {
+                        Disposal::Background => {
+                            for row in canvas.pixels.iter_mut().skip(top).take(frame_height) {
+                                for pixel in row.iter_mut().skip(left).take(frame_width) {
+                                    *pixel = Color::new(0, 0, 0, 0);
+                                }
+                            }
+                        },
+                        Disposal::Previous => {
+                            for (row, saved) in canvas.pixels.iter_mut().skip(top).zip(previous.unwrap()) {
+                                row.iter_mut().skip(left).zip(saved).for_each(|(pixel, color)| *pixel = color);
+                            }
+                        },
+                        _ => {},
+                    }
+                    control = GraphicControl::default();
+                },
+                gif::TRAILER => break,
+                _ => return Err(invalid_data("Unknown GIF block")),
+            }
+        }
+
+        Ok(gif)
+    }
+
+    pub fn read_gif(&self, path: &str) -> io::Result<GIF> {
+        println!("Reading GIF file");
+
+        let data = fs::read(path)?;
+        self.decode(&data)
+    }
+}
+
+impl Reader for GIFReader {
+    /// First frame of the animation
+    fn read(&self, path: &str) -> io::Result<Image> {
+        self.read_gif(path)?.frames.into_iter().next()
+            .map(|frame| frame.image)
+            .ok_or_else(|| invalid_data("GIF file has no images"))
+    }
+}
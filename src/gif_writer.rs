@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+use crate::common::*;
+use crate::gif::{self, Disposal, Frame, GIF};
+
+pub struct Settings {
+    /// Colors per frame including the transparent one, at most 256
+    pub max_colors: usize,
+    /// Pixels with lower alpha are transparent
+    pub alpha_threshold: u8,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings { max_colors: 256, alpha_threshold: 128 }
+    }
+}
+
+pub struct GIFWriter {
+    pub settings: Settings
+}
+
+/// Colors of a median cut box with their pixel counts
+struct ColorBox {
+    colors: Vec<(Color, usize)>,
+}
+
+impl ColorBox {
+    fn channel(color: &Color, channel: usize) -> u8 {
+        [color.r, color.g, color.b][channel]
+    }
+
+    /// Channel with the widest range and its range
+    fn widest_channel(&self) -> (usize, u8) {
+        (0..3).map(|channel| {
+            let values = self.colors.iter().map(|(color, _)| ColorBox::channel(color, channel));
+            let (min, max) = values.fold((u8::MAX, u8::MIN), |(min, max), value| (min.min(value), max.max(value)));
+            (channel, max - min)
+        }).max_by_key(|&(_, range)| range).unwrap()
+    }
+
+    /// Splits at the median pixel along the widest channel
+    fn split(mut self) -> (ColorBox, ColorBox) {
+        let (channel, _) = self.widest_channel();
+        self.colors.sort_by_key(|(color, _)| ColorBox::channel(color, channel));
+
+        let total: usize = self.colors.iter().map(|(_, count)| count).sum();
+        let mut count = 0;
+        let median = self.colors.iter()
+            .position(|(_, pixels)| {
+                count += pixels;
+                count * 2 >= total
+            })
+            .unwrap()
+            .clamp(0, self.colors.len() - 2);
+
+        let upper = self.colors.split_off(median + 1);
+        (self, ColorBox { colors: upper })
+    }
+
+    fn average(&self) -> Color {
+        let total: usize = self.colors.iter().map(|(_, count)| count).sum();
+        let channel = |channel: usize| {
+            let sum: usize = self.colors.iter().map(|(color, count)| ColorBox::channel(color, channel) as usize * count).sum();
+            ((sum + total / 2) / total) as u8
+        };
+        Color::from_rgb(channel(0), channel(1), channel(2))
+    }
+}
+
+/// Palette of at most `max` colors, exact when there are few enough colors
+fn quantize(histogram: HashMap<Color, usize>, max: usize) -> Vec<Color> {
+    if histogram.len() <= max {
+        let mut colors: Vec<Color> = histogram.into_keys().collect();
+        colors.sort_by_key(|color| (color.r, color.g, color.b));
+        return colors;
+    }
+
+    let mut boxes = vec![ColorBox { colors: histogram.into_iter().collect() }];
+    while boxes.len() < max {
+        let Some(index) = boxes.iter().enumerate()
+            .filter(|(_, color_box)| color_box.colors.len() > 1)
+            .max_by_key(|(_, color_box)| color_box.widest_channel().1 as usize * color_box.colors.len())
+            .map(|(index, _)| index) else { break };
+
+        let (lower, upper) = boxes.swap_remove(index).split();
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+
+    boxes.iter().map(ColorBox::average).collect()
+}
+
+fn nearest(palette: &[Color], color: Color) -> u8 {
+    let distance = |other: &Color| {
+        let (dr, dg, db) = (color.r as i32 - other.r as i32, color.g as i32 - other.g as i32, color.b as i32 - other.b as i32);
+        dr * dr + dg * dg + db * db
+    };
+    palette.iter().enumerate().min_by_key(|(_, other)| distance(other)).map(|(index, _)| index as u8).unwrap_or(0)
+}
+
+/// Color table padded to a power of two and the palette indices of a frame
+struct IndexedFrame {
+    table: Vec<Color>,
+    size_bits: u8,
+    indices: Vec<u8>,
+    transparent: Option<u8>,
+}
+
+impl GIFWriter {
+    fn index_frame(&self, image: &Image) -> IndexedFrame {
+        let opaque = |color: &&Color| color.a >= self.settings.alpha_threshold;
+        let has_transparency = image.pixels.iter().flatten().any(|color| !opaque(&color));
+
+        let mut histogram: HashMap<Color, usize> = HashMap::new();
+        for color in image.pixels.iter().flatten().filter(opaque) {
+            *histogram.entry(Color::from_rgb(color.r, color.g, color.b)).or_default() += 1;
+        }
+
+        let max_colors = self.settings.max_colors.clamp(2, 256) - has_transparency as usize;
+        let mut table = quantize(histogram, max_colors);
+        let transparent = has_transparency.then_some(table.len() as u8);
+
+        let mut cache: HashMap<Color, u8> = HashMap::new();
+        let indices = image.pixels.iter().flatten()
+            .map(|color| match transparent {
+                Some(index) if !opaque(&color) => index,
+                _ => {
+                    let color = Color::from_rgb(color.r, color.g, color.b);
+                    *cache.entry(color).or_insert_with(|| nearest(&table, color))
+                },
+            })
+            .collect();
+
+        let size_bits = (table.len() + has_transparency as usize).max(2).next_power_of_two().trailing_zeros() as u8 - 1;
+        table.resize(2 << size_bits, Color::black());
+
+        IndexedFrame { table, size_bits, indices, transparent }
+    }
+
+    fn write_table(&self, data: &mut Vec<u8>, table: &[Color]) {
+        table.iter().for_each(|color| data.extend_from_slice(&[color.r, color.g, color.b]));
+    }
+
+    fn write_frame(&self, data: &mut Vec<u8>, gif: &GIF, frame: &Frame, disposal: Disposal, indexed: &IndexedFrame, local_table: bool) {
+        let transparent_flag = indexed.transparent.is_some() as u8;
+        data.extend_from_slice(&[gif::EXTENSION, gif::GRAPHIC_CONTROL, 4, disposal.bits() << 2 | transparent_flag]);
+        data.extend_from_slice(&frame.delay.to_le_bytes());
+        data.extend_from_slice(&[indexed.transparent.unwrap_or(0), 0]);
+
+        data.push(gif::IMAGE_DESCRIPTOR);
+        for value in [0, 0, gif.width as u16, gif.height as u16] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        if local_table {
+            data.push(0x80 | indexed.size_bits);
+            self.write_table(data, &indexed.table);
+        } else {
+            data.push(0);
+        }
+
+        let min_code_size = (indexed.size_bits + 1).max(2);
+        data.push(min_code_size);
+        for block in gif::lzw_encode(&indexed.indices, min_code_size).chunks(255) {
+            data.push(block.len() as u8);
+            data.extend_from_slice(block);
+        }
+        data.push(0);
+    }
+
+    /// Frames are written whole, the first color table is global and later ones are local.
+    /// Frames followed by one with transparent pixels are disposed to the background.
+    pub fn encode(&self, gif: &GIF) -> io::Result<Vec<u8>> {
+        if gif.width > u16::MAX as usize || gif.height > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image is too large for GIF"));
+        }
+        if gif.frames.iter().any(|frame| frame.image.width() != gif.width || frame.image.height() != gif.height) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "GIF frames must have the size of the canvas"));
+        }
+
+        let frames: Vec<IndexedFrame> = gif.frames.iter().map(|frame| self.index_frame(&frame.image)).collect();
+
+        let mut data = gif::MAGIC_89A.to_vec();
+        data.extend_from_slice(&(gif.width as u16).to_le_bytes());
+        data.extend_from_slice(&(gif.height as u16).to_le_bytes());
+        match frames.first() {
+            Some(first) => {
+                data.extend_from_slice(&[0x80 | 0x70 | first.size_bits, 0, 0]);
+                self.write_table(&mut data, &first.table);
+            },
+            None => data.extend_from_slice(&[0, 0, 0]),
+        }
+
+        if let (Some(loop_count), true) = (gif.loop_count, gif.frames.len() > 1) {
+            data.extend_from_slice(&[gif::EXTENSION, gif::APPLICATION, 11]);
+            data.extend_from_slice(b"NETSCAPE2.0");
+            data.extend_from_slice(&[3, 1]);
+            data.extend_from_slice(&loop_count.to_le_bytes());
+            data.push(0);
+        }
+
+        for (i, (frame, indexed)) in gif.frames.iter().zip(frames.iter()).enumerate() {
+            // Transparent pixels of the next whole canvas frame must show through to a cleared canvas
+            let disposal = match frames.get(i + 1) {
+                Some(next) if next.transparent.is_some() => Disposal::Background,
+                _ => frame.disposal,
+            };
+            self.write_frame(&mut data, gif, frame, disposal, indexed, i > 0);
+        }
+        data.push(gif::TRAILER);
+
+        Ok(data)
+    }
+
+    pub fn write_gif(&self, gif: &GIF, path: &str) -> io::Result<()> {
+        println!("Writing GIF file at path: {}", path);
+
+        fs::write(path, self.encode(gif)?)
+    }
+}
+
+impl Writer for GIFWriter {
+    fn extension(&self) -> &str {
+        "gif"
+    }
+
+    fn write(&self, image: Image, path: &str) {
+        let gif = GIF {
+            width: image.width(),
+            height: image.height(),
+            loop_count: None,
+            frames: vec![Frame { image, delay: 0, disposal: Default::default() }],
+        };
+        self.write_gif(&gif, path).expect("Can't save output GIF file");
+    }
+}
@@ -0,0 +1,120 @@
+use std::io;
+
+/// Header lines other than comments, FORMAT and EXPOSURE are kept as they are
+#[derive(Debug, Clone)]
+pub struct Header {
+    /// "32-bit_rle_rgbe" or "32-bit_rle_xyze"
+    pub format: Option<String>,
+    /// Product of all EXPOSURE lines, pixels were multiplied by it
+    pub exposure: f32,
+    pub variables: Vec<String>,
+    pub width: usize,
+    pub height: usize,
+    /// Rows are stored from bottom to top for a "+Y" resolution line
+    pub bottom_up: bool,
+}
+
+pub const RGBE_FORMAT: &str = "32-bit_rle_rgbe";
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl Header {
+    /// Reads the header and resolution line, returns the header and the offset of the pixel data
+    pub fn read(data: &[u8]) -> io::Result<(Header, usize)> {
+        let mut position = 0;
+        let mut next_line = || {
+            let end = data[position..].iter().position(|&byte| byte == b'\n')
+                .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete HDR header"))?;
+            let line = String::from_utf8_lossy(&data[position..position + end]).trim_end().to_string();
+            position += end + 1;
+            Ok::<String, io::Error>(line)
+        };
+
+        let magic = next_line()?;
+        if magic != "#?RADIANCE" && magic != "#?RGBE" {
+            return Err(invalid_data("Invalid HDR file"));
+        }
+
+        let (mut format, mut exposure, mut variables) = (None, 1.0f32, Vec::new());
+        loop {
+            let line = next_line()?;
+            if line.is_empty() {
+                break;
+            }
+
+            if let Some(value) = line.strip_prefix("FORMAT=") {
+                format = Some(value.trim().to_string());
+            } else if let Some(value) = line.strip_prefix("EXPOSURE=") {
+                exposure *= value.trim().parse::<f32>().ok()
+                    .filter(|exposure| exposure.is_finite() && *exposure > 0.0)
+                    .ok_or_else(|| invalid_data("Invalid HDR exposure"))?;
+            } else if !line.starts_with('#') {
+                variables.push(line);
+            }
+        }
+
+        let resolution = next_line()?;
+        let parts: Vec<&str> = resolution.split_whitespace().collect();
+        let (bottom_up, height, width) = match parts[..] {
+            ["-Y", height, "+X", width] => (false, height, width),
+            ["+Y", height, "+X", width] => (true, height, width),
+            [_, _, _, _] => return Err(io::Error::new(io::ErrorKind::Unsupported, "Unsupported HDR orientation")),
+            _ => return Err(invalid_data("Invalid HDR resolution")),
+        };
+        let dimension = |value: &str| value.parse::<usize>().ok().filter(|&size| size > 0).ok_or_else(|| invalid_data("Invalid HDR resolution"));
+
+        Ok((Header {
+            format,
+            exposure,
+            variables,
+            width: dimension(width)?,
+            height: dimension(height)?,
+            bottom_up,
+        }, position))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut header = String::from("#?RADIANCE\n");
+        if let Some(format) = self.format.as_ref() {
+            header += &format!("FORMAT={}\n", format);
+        }
+        if self.exposure != 1.0 {
+            header += &format!("EXPOSURE={}\n", self.exposure);
+        }
+        for variable in self.variables.iter() {
+            header += variable;
+            header.push('\n');
+        }
+        let y = if self.bottom_up { "+Y" } else { "-Y" };
+        header += &format!("\n{} {} +X {}\n", y, self.height, self.width);
+
+        header.into_bytes()
+    }
+}
+
+pub fn rgbe_to_float(rgbe: [u8; 4]) -> [f32; 3] {
+    if rgbe[3] == 0 {
+        return [0.0; 3];
+    }
+    let scale = 2f32.powi(rgbe[3] as i32 - 136);
+    [(rgbe[0] as f32 + 0.5) * scale, (rgbe[1] as f32 + 0.5) * scale, (rgbe[2] as f32 + 0.5) * scale]
+}
+
+pub fn float_to_rgbe(rgb: [f32; 3]) -> [u8; 4] {
+    let max = rgb[0].max(rgb[1]).max(rgb[2]);
+    if max.is_nan() || max < 1e-32 {
+        return [0; 4];
+    }
+
+    // max = mantissa * 2^exponent with mantissa in 0.5..1
+    let exponent = ((max.to_bits() >> 23) & 0xFF) as i32 - 126;
+    if exponent > 127 {
+        return [255, 255, 255, 255];
+    }
+    let scale = 256.0 / 2f32.powi(exponent);
+    let channel = |value: f32| (value.max(0.0) * scale).min(255.0) as u8;
+
+    [channel(rgb[0]), channel(rgb[1]), channel(rgb[2]), (exponent + 128) as u8]
+}
@@ -0,0 +1,137 @@
+use std::fs;
+use std::io;
+
+use crate::common::*;
+use crate::hdr::{self, Header};
+
+pub struct HDRReader {
+
+}
+
+const MAX_PIXELS_PER_BYTE: usize = 64;
+
+fn unexpected_eof() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete HDR data")
+}
+
+impl HDRReader {
+    /// Channels of adaptive run length encoded scanlines are stored one after another
+    fn decode_rle_scanline(&self, data: &[u8], position: &mut usize, width: usize) -> io::Result<Vec<[u8; 4]>> {
+        let mut scanline = vec![[0u8; 4]; width];
+
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = *data.get(*position).ok_or_else(unexpected_eof)? as usize;
+                *position += 1;
+
+                if count > 128 {
+                    let value = *data.get(*position).ok_or_else(unexpected_eof)?;
+                    *position += 1;
+                    for pixel in scanline.iter_mut().skip(x).take(count - 128) {
+                        pixel[channel] = value;
+                    }
+                    x += count - 128;
+                } else {
+                    let values = data.get(*position..*position + count).ok_or_else(unexpected_eof)?;
+                    *position += count;
+                    for (pixel, &value) in scanline.iter_mut().skip(x).zip(values.iter()) {
+                        pixel[channel] = value;
+                    }
+                    x += count;
+                }
+
+                if count == 0 || x > width {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid HDR run length"));
+                }
+            }
+        }
+
+        Ok(scanline)
+    }
+
+    /// Flat pixels, where 1 1 1 n repeats the previous pixel with consecutive repeats counting in higher bytes
+    fn decode_old_scanline(&self, data: &[u8], position: &mut usize, width: usize) -> io::Result<Vec<[u8; 4]>> {
+        // Runs make the data shorter than the scanline, so the capacity only covers the pixels present
+        let mut scanline: Vec<[u8; 4]> = Vec::with_capacity(width.min(data.len().saturating_sub(*position) / 4));
+        let mut shift = 0;
+
+        while scanline.len() < width {
+            let bytes = data.get(*position..*position + 4).ok_or_else(unexpected_eof)?;
+            let pixel = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            *position += 4;
+
+            if pixel[..3] == [1, 1, 1] {
+                // Like the reference reader, at most four consecutive repeats make up one count
+                if shift > 24 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Too many consecutive HDR repeats"));
+                }
+                let previous = *scanline.last().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "HDR run without a pixel"))?;
+                let count = ((pixel[3] as usize) << shift).min(width - scanline.len());
+                scanline.extend(std::iter::repeat_n(previous, count));
+                shift += 8;
+            } else {
+                scanline.push(pixel);
+                shift = 0;
+            }
+        }
+
+        Ok(scanline)
+    }
+
+    fn decode_scanline(&self, data: &[u8], position: &mut usize, width: usize) -> io::Result<Vec<[u8; 4]>> {
+        match data.get(*position..*position + 4) {
+            Some(&[2, 2, high, low]) if (8..=0x7FFF).contains(&width) && high & 0x80 == 0 && ((high as usize) << 8 | low as usize) == width => {
+                *position += 4;
+                self.decode_rle_scanline(data, position, width)
+            },
+            _ => self.decode_old_scanline(data, position, width),
+        }
+    }
+
+    /// RGB radiance with the header exposure divided out
+    pub fn decode(&self, data: &[u8]) -> io::Result<(Header, FloatImage)> {
+        let (header, mut position) = Header::read(data)?;
+        if header.format.as_deref().is_some_and(|format| format != hdr::RGBE_FORMAT) {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "Only RGBE HDR files are supported"));
+        }
+
+        let (width, height) = (header.width, header.height);
+        if !header.exposure.is_normal() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid HDR exposure"));
+        }
+        // One run of 4 bytes repeats at most 255 pixels, consecutive runs may not expand the data further than that
+        let remaining = data.len().saturating_sub(position);
+        if width.checked_mul(height).is_none_or(|count| count > remaining.saturating_mul(MAX_PIXELS_PER_BYTE)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "HDR size is too large for its data"));
+        }
+        // Every scanline takes at least four bytes
+        let mut rows = Vec::with_capacity(height.min(remaining / 4));
+        for _ in 0..height {
+            let scanline = self.decode_scanline(data, &mut position, width)?;
+            rows.push(scanline.into_iter()
+                .flat_map(|rgbe| hdr::rgbe_to_float(rgbe).map(|sample| sample / header.exposure))
+                .collect::<Vec<f32>>());
+        }
+        if header.bottom_up {
+            rows.reverse();
+        }
+
+        let image = FloatImage::from_data(width, height, 3, rows.concat());
+        Ok((header, image))
+    }
+
+    pub fn read_float(&self, path: &str) -> io::Result<FloatImage> {
+        println!("Reading HDR file");
+
+        let data = fs::read(path)?;
+        Ok(self.decode(&data)?.1)
+    }
+}
+
+impl Reader for HDRReader {
+    /// Samples are clamped to 0..=1
+    fn read(&self, path: &str) -> io::Result<Image> {
+        Ok(self.read_float(path)?.to_image())
+    }
+}
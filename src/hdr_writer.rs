@@ -0,0 +1,102 @@
+use std::fs;
+use std::io;
+
+use crate::common::*;
+use crate::hdr::{self, Header};
+
+pub struct Settings {
+    /// Adaptive run length encoding for widths from 8 to 32767
+    pub rle: bool,
+    /// Pixels are multiplied by it and it is recorded in the header
+    pub exposure: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings { rle: true, exposure: 1.0 }
+    }
+}
+
+pub struct HDRWriter {
+    pub settings: Settings
+}
+
+impl HDRWriter {
+    /// Runs of at least 4 equal bytes are encoded as runs, other bytes as literals of up to 128
+    fn encode_channel(&self, values: &[u8], data: &mut Vec<u8>) {
+        let mut i = 0;
+        while i < values.len() {
+            let run = values[i..].iter().take(127).take_while(|&&value| value == values[i]).count();
+            if run >= 4 {
+                data.extend_from_slice(&[128 + run as u8, values[i]]);
+                i += run;
+                continue;
+            }
+
+            let start = i;
+            while i < values.len() && i - start < 128 {
+                if values[i..].iter().take(4).take_while(|&&value| value == values[i]).count() >= 4 {
+                    break;
+                }
+                i += 1;
+            }
+            data.push((i - start) as u8);
+            data.extend_from_slice(&values[start..i]);
+        }
+    }
+
+    /// Gray images are expanded, alpha is dropped
+    pub fn encode(&self, image: &FloatImage) -> io::Result<Vec<u8>> {
+        let header = Header {
+            format: Some(hdr::RGBE_FORMAT.to_string()),
+            exposure: self.settings.exposure,
+            variables: Vec::new(),
+            width: image.width(),
+            height: image.height(),
+            bottom_up: false,
+        };
+        let mut data = header.to_bytes();
+        let rle = self.settings.rle && (8..=0x7FFF).contains(&image.width());
+
+        for y in 0..image.height() {
+            let scanline: Vec<[u8; 4]> = (0..image.width())
+                .map(|x| {
+                    let rgb = match *image.pixel(x, y) {
+                        [gray] | [gray, _] => [gray; 3],
+                        [r, g, b, ..] => [r, g, b],
+                        [] => [0.0; 3],
+                    };
+                    hdr::float_to_rgbe(rgb.map(|sample| sample * self.settings.exposure))
+                })
+                .collect();
+
+            if rle {
+                data.extend_from_slice(&[2, 2, (image.width() >> 8) as u8, image.width() as u8]);
+                for channel in 0..4 {
+                    let values: Vec<u8> = scanline.iter().map(|rgbe| rgbe[channel]).collect();
+                    self.encode_channel(&values, &mut data);
+                }
+            } else {
+                scanline.iter().for_each(|rgbe| data.extend_from_slice(rgbe));
+            }
+        }
+
+        Ok(data)
+    }
+
+    pub fn write_float(&self, image: &FloatImage, path: &str) -> io::Result<()> {
+        println!("Writing HDR file at path: {}", path);
+
+        fs::write(path, self.encode(image)?)
+    }
+}
+
+impl Writer for HDRWriter {
+    fn extension(&self) -> &str {
+        "hdr"
+    }
+
+    fn write(&self, image: Image, path: &str) {
+        self.write_float(&FloatImage::from_image(&image), path).expect("Can't save output HDR file");
+    }
+}
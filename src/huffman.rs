@@ -0,0 +1,108 @@
+/// Huffman code lengths for the symbol frequencies, none longer than `max_length`
+pub fn code_lengths(frequencies: &[u64], max_length: u8) -> Vec<u8> {
+    let mut lengths = vec![0u8; frequencies.len()];
+
+    let mut symbols: Vec<usize> = (0..frequencies.len()).filter(|&symbol| frequencies[symbol] > 0).collect();
+    match symbols.len() {
+        0 => return lengths,
+        1 => {
+            lengths[symbols[0]] = 1;
+            return lengths;
+        }
+        _ => {}
+    }
+
+    symbols.sort_by_key(|&symbol| (frequencies[symbol], symbol));
+    let leaves = symbols.len();
+
+    // Leaves are sorted and merged nodes come out in increasing weight order, so two queues replace a heap
+    let mut weights: Vec<u64> = symbols.iter().map(|&symbol| frequencies[symbol]).collect();
+    let mut parents = vec![0usize; 2 * leaves - 1];
+    let (mut next_leaf, mut next_node) = (0, leaves);
+
+    for _ in 0..leaves - 1 {
+        let mut children = [0usize; 2];
+        for child in children.iter_mut() {
+            if next_leaf < leaves && (next_node >= weights.len() || weights[next_leaf] <= weights[next_node]) {
+                *child = next_leaf;
+                next_leaf += 1;
+            } else {
+                *child = next_node;
+                next_node += 1;
+            }
+        }
+
+        let node = weights.len();
+        weights.push(weights[children[0]] + weights[children[1]]);
+        parents[children[0]] = node;
+        parents[children[1]] = node;
+    }
+
+    // Parents always come after their children, so depths can be filled from the root down
+    let mut depths = vec![0usize; 2 * leaves - 1];
+    for node in (0..2 * leaves - 2).rev() {
+        depths[node] = depths[parents[node]] + 1;
+    }
+
+    let longest = depths[..leaves].iter().copied().max().unwrap();
+    let mut length_counts = vec![0usize; longest.max(max_length as usize) + 1];
+    for &depth in depths[..leaves].iter() {
+        length_counts[depth] += 1;
+    }
+
+    // Moves pairs of the deepest leaves up while keeping the code complete, see JPEG Annex K.3
+    for length in (max_length as usize + 1..=longest).rev() {
+        while length_counts[length] > 0 {
+            let mut shorter = length - 2;
+            while length_counts[shorter] == 0 {
+                shorter -= 1;
+            }
+            length_counts[length] -= 2;
+            length_counts[length - 1] += 1;
+            length_counts[shorter + 1] += 2;
+            length_counts[shorter] -= 1;
+        }
+    }
+
+    // The least frequent symbols get the longest codes
+    let mut symbol = symbols.iter();
+    for length in (1..=max_length as usize).rev() {
+        for _ in 0..length_counts[length] {
+            lengths[*symbol.next().unwrap()] = length as u8;
+        }
+    }
+
+    lengths
+}
+
+/// Canonical codes for the code lengths, most significant bit first
+pub fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let longest = lengths.iter().copied().max().unwrap_or(0) as usize;
+
+    let mut length_counts = vec![0u16; longest + 1];
+    for &length in lengths.iter().filter(|&&length| length > 0) {
+        length_counts[length as usize] += 1;
+    }
+
+    let mut next_code = vec![0u16; longest + 1];
+    let mut code = 0u16;
+    for length in 1..=longest {
+        code = (code + length_counts[length - 1]) << 1;
+        next_code[length] = code;
+    }
+    next_code[0] = 0;
+
+    lengths.iter().map(|&length| {
+        if length == 0 {
+            return 0;
+        }
+        let code = next_code[length as usize];
+        next_code[length as usize] += 1;
+        code
+    }).collect()
+}
+
+/// Reverses the lowest `length` bits of the code, for formats packing codes least significant bit first
+pub fn reverse_bits(code: u16, length: u8) -> u16 {
+    if length == 0 { 0 } else { code.reverse_bits() >> (16 - length) }
+}
@@ -0,0 +1,138 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::binary_serializable::BinarySerializable;
+use crate::common::Image;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[repr(u16)]
+pub enum ResourceType {
+    #[default]
+    Icon = 1,
+    Cursor = 2,
+}
+
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub resource_type: ResourceType,
+    pub count: u16,
+}
+
+impl Header {
+    pub const SIZE: u32 = 6;
+}
+
+impl BinarySerializable for Header {
+    fn read<R: Read>(reader: &mut R) -> io::Result<Self> where Self: Sized {
+        let _reserved = reader.read_u16::<LittleEndian>()?;
+        let resource_type = match reader.read_u16::<LittleEndian>()? {
+            1 => ResourceType::Icon,
+            2 => ResourceType::Cursor,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid ICO file")),
+        };
+        let count = reader.read_u16::<LittleEndian>()?;
+
+        Ok(Header { resource_type, count })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u16::<LittleEndian>(0)?;
+        writer.write_u16::<LittleEndian>(self.resource_type as u16)?;
+        writer.write_u16::<LittleEndian>(self.count)?;
+
+        Ok(())
+    }
+}
+
+/// Directory entry describing one image of the file
+#[derive(Debug, Clone)]
+pub struct DirectoryEntry {
+    /// 0 stands for 256
+    pub width: u8,
+    pub height: u8,
+    /// 0 for images with more than 256 colors
+    pub color_count: u8,
+    /// Color planes of icons, horizontal hotspot of cursors
+    pub planes: u16,
+    /// Bits per pixel of icons, vertical hotspot of cursors
+    pub bit_count: u16,
+    pub size: u32,
+    /// Offset of the image data from the start of the file
+    pub offset: u32,
+}
+
+impl DirectoryEntry {
+    pub const SIZE: u32 = 16;
+
+    /// Width and height in pixels
+    pub fn size(&self) -> (usize, usize) {
+        let pixels = |size: u8| if size == 0 { 256 } else { size as usize };
+        (pixels(self.width), pixels(self.height))
+    }
+}
+
+impl BinarySerializable for DirectoryEntry {
+    fn read<R: Read>(reader: &mut R) -> io::Result<Self> where Self: Sized {
+        let width = reader.read_u8()?;
+        let height = reader.read_u8()?;
+        let color_count = reader.read_u8()?;
+        let _reserved = reader.read_u8()?;
+        let planes = reader.read_u16::<LittleEndian>()?;
+        let bit_count = reader.read_u16::<LittleEndian>()?;
+        let size = reader.read_u32::<LittleEndian>()?;
+        let offset = reader.read_u32::<LittleEndian>()?;
+
+        Ok(DirectoryEntry { width, height, color_count, planes, bit_count, size, offset })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u8(self.width)?;
+        writer.write_u8(self.height)?;
+        writer.write_u8(self.color_count)?;
+        writer.write_u8(0)?;
+        writer.write_u16::<LittleEndian>(self.planes)?;
+        writer.write_u16::<LittleEndian>(self.bit_count)?;
+        writer.write_u32::<LittleEndian>(self.size)?;
+        writer.write_u32::<LittleEndian>(self.offset)?;
+
+        Ok(())
+    }
+}
+
+pub struct Entry {
+    pub image: Image,
+    /// Cursor hotspot from the top left corner, ignored for icons
+    pub hotspot: (u16, u16),
+}
+
+pub struct ICO {
+    pub resource_type: ResourceType,
+    pub entries: Vec<Entry>,
+}
+
+/// Index of the entry of exactly the given size
+pub(crate) fn find_size(mut sizes: impl Iterator<Item = (usize, usize)>, width: usize, height: usize) -> Option<usize> {
+    sizes.position(|size| size == (width, height))
+}
+
+/// Index of the entry with the most pixels
+pub(crate) fn find_largest(sizes: impl Iterator<Item = (usize, usize)>) -> Option<usize> {
+    sizes.enumerate().max_by_key(|(_, (width, height))| width * height).map(|(index, _)| index)
+}
+
+impl ICO {
+    fn sizes(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.entries.iter().map(|entry| (entry.image.width(), entry.image.height()))
+    }
+
+    /// Entry of exactly the given size
+    pub fn entry(&self, width: usize, height: usize) -> Option<&Entry> {
+        find_size(self.sizes(), width, height).map(|index| &self.entries[index])
+    }
+
+    /// Entry with the most pixels
+    pub fn largest(&self) -> Option<&Entry> {
+        find_largest(self.sizes()).map(|index| &self.entries[index])
+    }
+}
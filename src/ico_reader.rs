@@ -0,0 +1,137 @@
+use std::fs;
+use std::io::{self, Cursor};
+
+use crate::binary_serializable::BinarySerializable;
+use crate::bmp::{self, Compression, InfoHeader};
+use crate::bmp_reader::BMPReader;
+use crate::common::*;
+use crate::ico::{self, DirectoryEntry, Entry, Header, ResourceType, ICO};
+use crate::png::{self, PNG};
+use crate::png_reader::PNGReader;
+
+pub struct ICOReader {
+
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl ICOReader {
+    /// DIB whose height covers the color data followed by the 1 bit AND mask, where 1 is transparent
+    fn decode_bmp(&self, dib: &[u8]) -> io::Result<Image> {
+        let header = InfoHeader::read(&mut Cursor::new(dib))?;
+        let (width, height) = (header.width.unsigned_abs() as usize, header.height.unsigned_abs() as usize / 2);
+
+        // The halved height makes the color data a complete bitmap
+        let mut color_dib = dib.to_vec();
+        if header.header_size == bmp::CORE_HEADER_SIZE {
+            color_dib[6..8].copy_from_slice(&(height as u16).to_le_bytes());
+        } else {
+            color_dib[8..12].copy_from_slice(&(height as i32).to_le_bytes());
+        }
+        let mut image = BMPReader {}.decode_dib(&color_dib, None)?;
+
+        let color_offset = header.header_size as usize + header.palette_size() * header.palette_entry_size();
+        let color_size = header.stride() * height;
+
+        // 32 bit entries keep alpha in the fourth byte, older ones only have the mask
+        if header.bit_count == 32 && header.compression == Compression::RGB {
+            let color_data = dib.get(color_offset..color_offset + color_size).ok_or_else(|| invalid_data("Incomplete ICO bitmap"))?;
+            let alpha: Vec<u8> = color_data.chunks_exact(4).map(|bgra| bgra[3]).collect();
+            if alpha.iter().any(|&alpha| alpha != 0) {
+                for (y, row) in image.pixels.iter_mut().rev().enumerate() {
+                    for (x, color) in row.iter_mut().enumerate() {
+                        color.a = alpha[y * width + x];
+                    }
+                }
+                return Ok(image);
+            }
+        }
+
+        let mask_stride = width.div_ceil(32) * 4;
+        let mask_offset = color_offset + color_size;
+        let Some(mask) = dib.get(mask_offset..mask_offset + mask_stride * height) else {
+            // Some files omit the mask of entries with alpha
+            return Ok(image);
+        };
+        for (mask_row, row) in mask.chunks_exact(mask_stride).zip(image.pixels.iter_mut().rev()) {
+            for (x, color) in row.iter_mut().enumerate() {
+                if mask_row[x / 8] >> (7 - x % 8) & 1 == 1 {
+                    color.a = 0;
+                }
+            }
+        }
+
+        Ok(image)
+    }
+
+    fn decode_image(&self, data: &[u8]) -> io::Result<Image> {
+        if data.starts_with(&png::MAGIC) {
+            return PNGReader {}.decode(&PNG::from_bytes(data)?);
+        }
+        self.decode_bmp(data)
+    }
+
+    fn directory(&self, data: &[u8]) -> io::Result<(Header, Vec<DirectoryEntry>)> {
+        let mut cursor = Cursor::new(data);
+        let header = Header::read(&mut cursor)?;
+        let directory = (0..header.count).map(|_| DirectoryEntry::read(&mut cursor)).collect::<io::Result<Vec<_>>>()?;
+
+        Ok((header, directory))
+    }
+
+    fn decode_entry(&self, data: &[u8], header: &Header, entry: &DirectoryEntry) -> io::Result<Entry> {
+        let (offset, size) = (entry.offset as usize, entry.size as usize);
+        let entry_data = data.get(offset..offset + size).ok_or_else(|| invalid_data("Incomplete ICO entry"))?;
+        let hotspot = match header.resource_type {
+            ResourceType::Cursor => (entry.planes, entry.bit_count),
+            ResourceType::Icon => (0, 0),
+        };
+        Ok(Entry { image: self.decode_image(entry_data)?, hotspot })
+    }
+
+    /// Decodes every entry, PNG and BMP encoded ones alike
+    pub fn decode(&self, data: &[u8]) -> io::Result<ICO> {
+        let (header, directory) = self.directory(data)?;
+        let entries = directory.iter().map(|entry| self.decode_entry(data, &header, entry)).collect::<io::Result<Vec<_>>>()?;
+
+        Ok(ICO { resource_type: header.resource_type, entries })
+    }
+
+    pub fn read_ico(&self, path: &str) -> io::Result<ICO> {
+        println!("Reading ICO file");
+
+        let data = fs::read(path)?;
+        self.decode(&data)
+    }
+
+    /// Entry of exactly the given size, only that entry is decoded
+    pub fn read_size(&self, path: &str, width: usize, height: usize) -> io::Result<Image> {
+        println!("Reading ICO file");
+
+        let data = fs::read(path)?;
+        let (header, directory) = self.directory(&data)?;
+        let index = ico::find_size(directory.iter().map(DirectoryEntry::size), width, height)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("ICO file has no {}x{} entry", width, height)))?;
+
+        let image = self.decode_entry(&data, &header, &directory[index])?.image;
+        if (image.width(), image.height()) != (width, height) {
+            return Err(invalid_data("ICO entry does not match its directory size"));
+        }
+        Ok(image)
+    }
+}
+
+impl Reader for ICOReader {
+    /// Largest entry of the file, only that entry is decoded
+    fn read(&self, path: &str) -> io::Result<Image> {
+        println!("Reading ICO file");
+
+        let data = fs::read(path)?;
+        let (header, directory) = self.directory(&data)?;
+        let index = ico::find_largest(directory.iter().map(DirectoryEntry::size)).ok_or_else(|| invalid_data("ICO file has no entries"))?;
+
+        Ok(self.decode_entry(&data, &header, &directory[index])?.image)
+    }
+}
@@ -0,0 +1,124 @@
+use std::fs;
+use std::io;
+
+use crate::binary_serializable::BinarySerializable;
+use crate::bmp::{self, Compression, InfoHeader};
+use crate::common::*;
+use crate::ico::{DirectoryEntry, Entry, Header, ResourceType, ICO};
+use crate::png_writer::{self, PNGWriter};
+
+pub struct Settings {
+    /// Entries at least this wide or high are stored as PNG, smaller ones as 32 bit BMP
+    pub png_size: usize,
+    pub png: png_writer::Settings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings { png_size: 256, png: Default::default() }
+    }
+}
+
+pub struct ICOWriter {
+    pub settings: Settings
+}
+
+impl ICOWriter {
+    /// BGRA rows from bottom to top followed by the AND mask marking transparent pixels
+    fn encode_bmp(&self, image: &Image) -> io::Result<Vec<u8>> {
+        let (width, height) = (image.width(), image.height());
+        let mask_stride = width.div_ceil(32) * 4;
+        let header = InfoHeader {
+            header_size: bmp::INFO_HEADER_SIZE,
+            width: width as i32,
+            height: 2 * height as i32,
+            planes: 1,
+            bit_count: 32,
+            compression: Compression::RGB,
+            image_size: ((width * 4 + mask_stride) * height) as u32,
+            x_pixels_per_meter: 0,
+            y_pixels_per_meter: 0,
+            colors_used: 0,
+            colors_important: 0,
+            masks: None,
+        };
+
+        let mut data = Vec::with_capacity(bmp::INFO_HEADER_SIZE as usize + header.image_size as usize);
+        header.write(&mut data)?;
+        for row in image.pixels.iter().rev() {
+            for color in row.iter() {
+                data.extend_from_slice(&[color.b, color.g, color.r, color.a]);
+            }
+        }
+        for row in image.pixels.iter().rev() {
+            let mut mask = vec![0u8; mask_stride];
+            for (x, color) in row.iter().enumerate() {
+                if color.a == 0 {
+                    mask[x / 8] |= 0x80 >> (x % 8);
+                }
+            }
+            data.extend_from_slice(&mask);
+        }
+
+        Ok(data)
+    }
+
+    fn encode_entry(&self, image: &Image) -> io::Result<Vec<u8>> {
+        if image.width() >= self.settings.png_size || image.height() >= self.settings.png_size {
+            let png_writer = PNGWriter { settings: self.settings.png.clone() };
+            return Ok(png_writer.encode(image)?.to_bytes());
+        }
+        self.encode_bmp(image)
+    }
+
+    /// Entries keep their order, images can be at most 256 pixels wide and high
+    pub fn encode(&self, ico: &ICO) -> io::Result<Vec<u8>> {
+        if ico.entries.iter().any(|entry| !(1..=256).contains(&entry.image.width()) || !(1..=256).contains(&entry.image.height())) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "ICO entries must be from 1 to 256 pixels wide and high"));
+        }
+
+        let images = ico.entries.iter().map(|entry| self.encode_entry(&entry.image)).collect::<io::Result<Vec<_>>>()?;
+
+        let mut data = Vec::new();
+        Header { resource_type: ico.resource_type, count: ico.entries.len() as u16 }.write(&mut data)?;
+
+        let mut offset = Header::SIZE + DirectoryEntry::SIZE * ico.entries.len() as u32;
+        for (entry, image) in ico.entries.iter().zip(images.iter()) {
+            let (planes, bit_count) = match ico.resource_type {
+                ResourceType::Icon => (1, 32),
+                ResourceType::Cursor => entry.hotspot,
+            };
+            DirectoryEntry {
+                // 256 wraps to 0
+                width: entry.image.width() as u8,
+                height: entry.image.height() as u8,
+                color_count: 0,
+                planes,
+                bit_count,
+                size: image.len() as u32,
+                offset,
+            }.write(&mut data)?;
+            offset += image.len() as u32;
+        }
+        images.iter().for_each(|image| data.extend_from_slice(image));
+
+        Ok(data)
+    }
+
+    pub fn write_ico(&self, ico: &ICO, path: &str) -> io::Result<()> {
+        println!("Writing ICO file at path: {}", path);
+
+        fs::write(path, self.encode(ico)?)
+    }
+}
+
+impl Writer for ICOWriter {
+    fn extension(&self) -> &str {
+        "ico"
+    }
+
+    fn write(&self, image: Image, path: &str) {
+        let ico = ICO { resource_type: ResourceType::Icon, entries: vec![Entry { image, hotspot: (0, 0) }] };
+        self.write_ico(&ico, path).expect("Can't save output ICO file");
+    }
+}
@@ -0,0 +1,269 @@
+use std::f32::consts::PI;
+
+pub const SOI: u8 = 0xD8;
+pub const EOI: u8 = 0xD9;
+pub const SOF0: u8 = 0xC0;
+pub const SOF1: u8 = 0xC1;
+pub const SOF2: u8 = 0xC2;
+pub const DHT: u8 = 0xC4;
+pub const DQT: u8 = 0xDB;
+pub const DRI: u8 = 0xDD;
+pub const SOS: u8 = 0xDA;
+pub const RST0: u8 = 0xD0;
+pub const RST7: u8 = 0xD7;
+pub const APP0: u8 = 0xE0;
+pub const APP1: u8 = 0xE1;
+pub const APP14: u8 = 0xEE;
+pub const COM: u8 = 0xFE;
+
+pub const JFIF_IDENTIFIER: &[u8; 5] = b"JFIF\0";
+pub const EXIF_IDENTIFIER: &[u8; 6] = b"Exif\0\0";
+
+/// Position in the block of each coefficient in zigzag order
+pub const ZIGZAG: [usize; 64] = [
+     0,  1,  8, 16,  9,  2,  3, 10,
+    17, 24, 32, 25, 18, 11,  4,  5,
+    12, 19, 26, 33, 40, 48, 41, 34,
+    27, 20, 13,  6,  7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36,
+    29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46,
+    53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// Luminance quantization table of JPEG Annex K.1 in natural order, for quality 50
+pub const LUMINANCE_QUANT_TABLE: [u16; 64] = [
+    16, 11, 10, 16,  24,  40,  51,  61,
+    12, 12, 14, 19,  26,  58,  60,  55,
+    14, 13, 16, 24,  40,  57,  69,  56,
+    14, 17, 22, 29,  51,  87,  80,  62,
+    18, 22, 37, 56,  68, 109, 103,  77,
+    24, 35, 55, 64,  81, 104, 113,  92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103,  99,
+];
+
+/// Chrominance quantization table of JPEG Annex K.1 in natural order, for quality 50
+pub const CHROMINANCE_QUANT_TABLE: [u16; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+/// Run and size pairs of the luminance AC table of JPEG Annex K.3 in code order
+const LUMINANCE_AC_SYMBOLS: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xA1, 0x08, 0x23, 0x42, 0xB1, 0xC1, 0x15, 0x52, 0xD1, 0xF0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0A, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2A, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7,
+    0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3, 0xC4, 0xC5,
+    0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA, 0xE1, 0xE2,
+    0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8,
+    0xF9, 0xFA,
+];
+
+/// Run and size pairs of the chrominance AC table of JPEG Annex K.3 in code order
+const CHROMINANCE_AC_SYMBOLS: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xA1, 0xB1, 0xC1, 0x09, 0x23, 0x33, 0x52, 0xF0,
+    0x15, 0x62, 0x72, 0xD1, 0x0A, 0x16, 0x24, 0x34, 0xE1, 0x25, 0xF1, 0x17, 0x18, 0x19, 0x1A, 0x26,
+    0x27, 0x28, 0x29, 0x2A, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5,
+    0xA6, 0xA7, 0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3,
+    0xC4, 0xC5, 0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA,
+    0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8,
+    0xF9, 0xFA,
+];
+
+/// Huffman table as stored in a DHT segment, the number of codes of each length and the symbols in code order
+pub struct HuffmanSpec {
+    pub counts: [u8; 16],
+    pub symbols: Vec<u8>,
+}
+
+impl HuffmanSpec {
+    /// Typical tables of JPEG Annex K.3, luminance DC, luminance AC, chrominance DC and chrominance AC
+    pub fn standard() -> [HuffmanSpec; 4] {
+        [
+            HuffmanSpec {
+                counts: [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+                symbols: (0..12).collect(),
+            },
+            HuffmanSpec {
+                counts: [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7D],
+                symbols: LUMINANCE_AC_SYMBOLS.to_vec(),
+            },
+            HuffmanSpec {
+                counts: [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0],
+                symbols: (0..12).collect(),
+            },
+            HuffmanSpec {
+                counts: [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77],
+                symbols: CHROMINANCE_AC_SYMBOLS.to_vec(),
+            },
+        ]
+    }
+}
+
+/// EXIF orientation tag
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// How the stored image is transformed for display, as in the EXIF orientation tag
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Orientation {
+    #[default]
+    Normal,
+    FlipHorizontal,
+    Rotate180,
+    FlipVertical,
+    Transpose,
+    Rotate90,
+    Transverse,
+    Rotate270,
+}
+
+impl Orientation {
+    pub fn from_value(value: u16) -> Orientation {
+        match value {
+            2 => Orientation::FlipHorizontal,
+            3 => Orientation::Rotate180,
+            4 => Orientation::FlipVertical,
+            5 => Orientation::Transpose,
+            6 => Orientation::Rotate90,
+            7 => Orientation::Transverse,
+            8 => Orientation::Rotate270,
+            _ => Orientation::Normal,
+        }
+    }
+
+    pub fn value(&self) -> u16 {
+        *self as u16 + 1
+    }
+
+    /// Width and height are swapped for display
+    pub fn is_transposed(&self) -> bool {
+        matches!(self, Orientation::Transpose | Orientation::Rotate90 | Orientation::Transverse | Orientation::Rotate270)
+    }
+
+    /// Stored pixel shown at (x, y) of the displayed image
+    pub fn source(&self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Orientation::Normal => (x, y),
+            Orientation::FlipHorizontal => (width - 1 - x, y),
+            Orientation::Rotate180 => (width - 1 - x, height - 1 - y),
+            Orientation::FlipVertical => (x, height - 1 - y),
+            Orientation::Transpose => (y, x),
+            Orientation::Rotate90 => (y, height - 1 - x),
+            Orientation::Transverse => (width - 1 - y, height - 1 - x),
+            Orientation::Rotate270 => (width - 1 - y, x),
+        }
+    }
+}
+
+/// Orientation tag of the first IFD of EXIF data following the identifier
+pub fn exif_orientation(exif: &[u8]) -> Option<Orientation> {
+    let big_endian = match exif.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |offset: usize| exif.get(offset..offset + 2).map(|bytes| {
+        if big_endian { u16::from_be_bytes([bytes[0], bytes[1]]) } else { u16::from_le_bytes([bytes[0], bytes[1]]) }
+    });
+    let u32_at = |offset: usize| exif.get(offset..offset + 4).map(|bytes| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+    });
+
+    let ifd = u32_at(4)? as usize;
+    let entries = u16_at(ifd)? as usize;
+    (0..entries).map(|i| ifd + 2 + i * 12)
+        .find(|&entry| u16_at(entry) == Some(ORIENTATION_TAG))
+        .and_then(|entry| u16_at(entry + 8))
+        .map(Orientation::from_value)
+}
+
+/// Separable 8x8 DCT-II and its inverse with orthonormal scaling
+pub struct DCT {
+    /// Basis function `u` sampled at `x`, scaled by its normalization factor
+    basis: [[f32; 8]; 8],
+}
+
+impl Default for DCT {
+    fn default() -> Self {
+        let mut basis = [[0.0; 8]; 8];
+        for (u, row) in basis.iter_mut().enumerate() {
+            let scale = if u == 0 { (0.125f32).sqrt() } else { 0.5 };
+            for (x, value) in row.iter_mut().enumerate() {
+                *value = scale * ((2 * x + 1) as f32 * u as f32 * PI / 16.0).cos();
+            }
+        }
+        DCT { basis }
+    }
+}
+
+impl DCT {
+    /// Coefficients in natural order to samples
+    pub fn inverse(&self, coefficients: &[f32; 64]) -> [f32; 64] {
+        let mut rows = [0.0f32; 64];
+        for v in 0..8 {
+            for x in 0..8 {
+                rows[v * 8 + x] = (0..8).map(|u| self.basis[u][x] * coefficients[v * 8 + u]).sum();
+            }
+        }
+
+        let mut samples = [0.0f32; 64];
+        for y in 0..8 {
+            for x in 0..8 {
+                samples[y * 8 + x] = (0..8).map(|v| self.basis[v][y] * rows[v * 8 + x]).sum();
+            }
+        }
+        samples
+    }
+
+    /// Samples to coefficients in natural order
+    pub fn forward(&self, samples: &[f32; 64]) -> [f32; 64] {
+        let mut rows = [0.0f32; 64];
+        for y in 0..8 {
+            for u in 0..8 {
+                rows[y * 8 + u] = (0..8).map(|x| self.basis[u][x] * samples[y * 8 + x]).sum();
+            }
+        }
+
+        let mut coefficients = [0.0f32; 64];
+        for v in 0..8 {
+            for u in 0..8 {
+                coefficients[v * 8 + u] = (0..8).map(|y| self.basis[v][y] * rows[y * 8 + u]).sum();
+            }
+        }
+        coefficients
+    }
+}
+
+pub fn ycbcr_to_rgb(y: f32, cb: f32, cr: f32) -> [u8; 3] {
+    let (cb, cr) = (cb - 128.0, cr - 128.0);
+    [
+        (y + 1.402 * cr).round().clamp(0.0, 255.0) as u8,
+        (y - 0.344136 * cb - 0.714136 * cr).round().clamp(0.0, 255.0) as u8,
+        (y + 1.772 * cb).round().clamp(0.0, 255.0) as u8,
+    ]
+}
+
+pub fn rgb_to_ycbcr(r: u8, g: u8, b: u8) -> [f32; 3] {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    [
+        0.299 * r + 0.587 * g + 0.114 * b,
+        -0.168736 * r - 0.331264 * g + 0.5 * b + 128.0,
+        0.5 * r - 0.418688 * g - 0.081312 * b + 128.0,
+    ]
+}
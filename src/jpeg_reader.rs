@@ -0,0 +1,645 @@
+use std::fs;
+use std::io;
+
+use crate::common::*;
+use crate::jpeg::{self, Orientation, DCT, ZIGZAG};
+
+pub struct JPEGReader {
+
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn unsupported(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, message.to_string())
+}
+
+/// Decoding tables of a canonical Huffman code, see JPEG Annex F.2.2.3
+struct HuffmanTable {
+    /// Largest code of each length, -1 when there is none
+    max_codes: [i32; 17],
+    /// Added to a code of each length to get the index of its symbol
+    offsets: [i32; 17],
+    symbols: Vec<u8>,
+}
+
+impl HuffmanTable {
+    fn new(counts: &[u8], symbols: Vec<u8>) -> HuffmanTable {
+        let (mut max_codes, mut offsets) = ([-1; 17], [0; 17]);
+        let (mut code, mut index) = (0i32, 0i32);
+        for length in 1..=16 {
+            let count = counts[length - 1] as i32;
+            offsets[length] = index - code;
+            code += count;
+            index += count;
+            if count > 0 {
+                max_codes[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        HuffmanTable { max_codes, offsets, symbols }
+    }
+}
+
+/// Reads entropy coded data most significant bit first, removing stuffed bytes and stopping at markers
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    bits: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn fill(&mut self) {
+        while self.bits <= 24 {
+            // Past a marker or the end of the data the bits are zeros
+            let byte = match (self.data.get(self.position), self.data.get(self.position + 1)) {
+                (Some(0xFF), Some(0x00)) => {
+                    self.position += 2;
+                    0xFF
+                },
+                (Some(0xFF), _) | (None, _) => 0,
+                (Some(&byte), _) => {
+                    self.position += 1;
+                    byte
+                },
+            };
+            self.buffer |= (byte as u32) << (24 - self.bits);
+            self.bits += 8;
+        }
+    }
+
+    fn receive(&mut self, count: u8) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+        self.fill();
+        let value = self.buffer >> (32 - count);
+        self.buffer <<= count;
+        self.bits -= count;
+        value
+    }
+
+    /// Value of `size` bits with negative values below half the range, see JPEG Annex F.2.2.1
+    fn receive_extend(&mut self, size: u8) -> i32 {
+        let value = self.receive(size) as i32;
+        if size > 0 && value < 1 << (size - 1) { value - (1 << size) + 1 } else { value }
+    }
+
+    fn decode(&mut self, table: &HuffmanTable) -> io::Result<u8> {
+        let mut code = 0i32;
+        for length in 1..=16 {
+            code = code << 1 | self.receive(1) as i32;
+            if code <= table.max_codes[length] {
+                return table.symbols.get((code + table.offsets[length]) as usize).copied()
+                    .ok_or_else(|| invalid_data("Invalid JPEG Huffman code"));
+            }
+        }
+        Err(invalid_data("Invalid JPEG Huffman code"))
+    }
+
+    /// Position of the next marker
+    fn next_marker(&self) -> usize {
+        (self.position..self.data.len())
+            .find(|&i| self.data[i] == 0xFF && self.data.get(i + 1).is_some_and(|&byte| byte != 0x00 && byte != 0xFF))
+            .unwrap_or(self.data.len())
+    }
+
+    /// Drops the bits left in the interval and skips the restart marker
+    fn restart(&mut self) {
+        self.buffer = 0;
+        self.bits = 0;
+        self.position = self.next_marker();
+        if self.data.get(self.position + 1).is_some_and(|marker| (jpeg::RST0..=jpeg::RST7).contains(marker)) {
+            self.position += 2;
+        }
+    }
+}
+
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant_table: usize,
+    dc_table: usize,
+    ac_table: usize,
+    /// Blocks covering whole MCUs
+    blocks_per_line: usize,
+    blocks_per_column: usize,
+    /// Size of the component without MCU padding
+    width: usize,
+    height: usize,
+    /// Quantized coefficients of each block in natural order
+    coefficients: Vec<[i32; 64]>,
+    dc_prediction: i32,
+}
+
+struct Frame {
+    progressive: bool,
+    width: usize,
+    height: usize,
+    components: Vec<Component>,
+    max_h: usize,
+    max_v: usize,
+    mcus_per_line: usize,
+    mcus_per_column: usize,
+}
+
+struct Scan {
+    /// Indices into the frame components
+    components: Vec<usize>,
+    /// Spectral selection in zigzag order
+    start: usize,
+    end: usize,
+    /// Successive approximation bit positions
+    high: u8,
+    low: u8,
+}
+
+#[derive(Default)]
+struct Decoder {
+    /// Quantization tables in natural order
+    quant_tables: [Option<[u16; 64]>; 4],
+    dc_tables: [Option<HuffmanTable>; 4],
+    ac_tables: [Option<HuffmanTable>; 4],
+    restart_interval: usize,
+    eob_run: u32,
+    jfif: bool,
+    adobe_transform: Option<u8>,
+    orientation: Option<Orientation>,
+}
+
+/// 8-bit samples have DC differences of at most 11 bits and AC coefficients of at most 10 bits
+fn dc_size(size: u8) -> io::Result<u8> {
+    if size > 11 { Err(invalid_data("Invalid JPEG DC magnitude category")) } else { Ok(size) }
+}
+
+fn ac_size(size: u8) -> io::Result<u8> {
+    if size > 10 { Err(invalid_data("Invalid JPEG AC magnitude category")) } else { Ok(size) }
+}
+
+fn table(tables: &[Option<HuffmanTable>; 4], index: usize) -> io::Result<&HuffmanTable> {
+    tables[index].as_ref().ok_or_else(|| invalid_data("Missing JPEG Huffman table"))
+}
+
+impl Decoder {
+    fn read_quant_tables(&mut self, mut segment: &[u8]) -> io::Result<()> {
+        while let Some((&info, rest)) = segment.split_first() {
+            let (sixteen_bit, id) = (info >> 4 != 0, (info & 0x0F) as usize);
+            let size = if sixteen_bit { 128 } else { 64 };
+            let values = rest.get(..size).ok_or_else(|| invalid_data("Incomplete JPEG quantization table"))?;
+            if id > 3 {
+                return Err(invalid_data("Invalid JPEG quantization table"));
+            }
+
+            let mut table = [0u16; 64];
+            for (k, &position) in ZIGZAG.iter().enumerate() {
+                table[position] = if sixteen_bit { u16::from_be_bytes([values[2 * k], values[2 * k + 1]]) } else { values[k] as u16 };
+            }
+            self.quant_tables[id] = Some(table);
+            segment = &rest[size..];
+        }
+        Ok(())
+    }
+
+    fn read_huffman_tables(&mut self, mut segment: &[u8]) -> io::Result<()> {
+        while segment.len() >= 17 {
+            let (class, id) = (segment[0] >> 4, (segment[0] & 0x0F) as usize);
+            let counts = &segment[1..17];
+            let count: usize = counts.iter().map(|&count| count as usize).sum();
+            let symbols = segment.get(17..17 + count).ok_or_else(|| invalid_data("Incomplete JPEG Huffman table"))?;
+            if class > 1 || id > 3 || count > 256 {
+                return Err(invalid_data("Invalid JPEG Huffman table"));
+            }
+
+            let table = HuffmanTable::new(counts, symbols.to_vec());
+            if class == 0 { self.dc_tables[id] = Some(table) } else { self.ac_tables[id] = Some(table) }
+            segment = &segment[17 + count..];
+        }
+        Ok(())
+    }
+
+    fn read_frame(&self, segment: &[u8], progressive: bool) -> io::Result<Frame> {
+        if segment.len() < 6 {
+            return Err(invalid_data("Incomplete JPEG frame header"));
+        }
+        if segment[0] != 8 {
+            return Err(unsupported("Only 8-bit JPEG is supported"));
+        }
+        let height = u16::from_be_bytes([segment[1], segment[2]]) as usize;
+        let width = u16::from_be_bytes([segment[3], segment[4]]) as usize;
+        if width == 0 || height == 0 {
+            return Err(unsupported("JPEG without a height in the frame header is not supported"));
+        }
+
+        let count = segment[5] as usize;
+        let specs = segment.get(6..6 + count * 3).ok_or_else(|| invalid_data("Incomplete JPEG frame header"))?;
+        let mut components: Vec<Component> = specs.chunks_exact(3).map(|spec| Component {
+            id: spec[0],
+            h: (spec[1] >> 4) as usize,
+            v: (spec[1] & 0x0F) as usize,
+            quant_table: (spec[2] & 0x03) as usize,
+            dc_table: 0,
+            ac_table: 0,
+            blocks_per_line: 0,
+            blocks_per_column: 0,
+            width: 0,
+            height: 0,
+            coefficients: Vec::new(),
+            dc_prediction: 0,
+        }).collect();
+        if components.iter().any(|component| !(1..=4).contains(&component.h) || !(1..=4).contains(&component.v)) {
+            return Err(invalid_data("Invalid JPEG sampling factors"));
+        }
+
+        let max_h = components.iter().map(|component| component.h).max().unwrap_or(1);
+        let max_v = components.iter().map(|component| component.v).max().unwrap_or(1);
+        let (mcus_per_line, mcus_per_column) = (width.div_ceil(8 * max_h), height.div_ceil(8 * max_v));
+        for component in components.iter_mut() {
+            component.width = (width * component.h).div_ceil(max_h);
+            component.height = (height * component.v).div_ceil(max_v);
+            component.blocks_per_line = mcus_per_line * component.h;
+            component.blocks_per_column = mcus_per_column * component.v;
+        }
+
+        Ok(Frame { progressive, width, height, components, max_h, max_v, mcus_per_line, mcus_per_column })
+    }
+
+    fn read_scan(&self, segment: &[u8], frame: &mut Frame) -> io::Result<Scan> {
+        let count = *segment.first().ok_or_else(|| invalid_data("Incomplete JPEG scan header"))? as usize;
+        let specs = segment.get(1..1 + count * 2).ok_or_else(|| invalid_data("Incomplete JPEG scan header"))?;
+        let parameters = segment.get(1 + count * 2..4 + count * 2).ok_or_else(|| invalid_data("Incomplete JPEG scan header"))?;
+
+        let mut components = Vec::with_capacity(count);
+        for spec in specs.chunks_exact(2) {
+            let index = frame.components.iter().position(|component| component.id == spec[0])
+                .ok_or_else(|| invalid_data("Unknown JPEG scan component"))?;
+            frame.components[index].dc_table = (spec[1] >> 4 & 0x03) as usize;
+            frame.components[index].ac_table = (spec[1] & 0x03) as usize;
+            components.push(index);
+        }
+
+        let scan = Scan {
+            components,
+            start: parameters[0] as usize,
+            end: parameters[1] as usize,
+            high: parameters[2] >> 4,
+            low: parameters[2] & 0x0F,
+        };
+
+        let valid = match frame.progressive {
+            false => scan.start == 0 && scan.end == 63,
+            true if scan.start == 0 => scan.end == 0,
+            true => scan.start <= scan.end && scan.end < 64 && scan.components.len() == 1,
+        };
+        if !valid || scan.components.is_empty() || scan.low > 13 {
+            return Err(invalid_data("Invalid JPEG scan parameters"));
+        }
+        Ok(scan)
+    }
+
+    fn decode_baseline(&self, reader: &mut BitReader, component: &mut Component, block: usize) -> io::Result<()> {
+        let (dc_table, ac_table) = (table(&self.dc_tables, component.dc_table)?, table(&self.ac_tables, component.ac_table)?);
+        let coefficients = &mut component.coefficients[block];
+
+        let size = dc_size(reader.decode(dc_table)?)?;
+        component.dc_prediction = component.dc_prediction.wrapping_add(reader.receive_extend(size));
+        coefficients[0] = component.dc_prediction;
+
+        let mut k = 1;
+        while k < 64 {
+            let symbol = reader.decode(ac_table)?;
+            let (run, size) = ((symbol >> 4) as usize, symbol & 0x0F);
+            if size == 0 {
+                if run < 15 {
+                    break;
+                }
+                k += 16;
+                continue;
+            }
+            k += run;
+            if k > 63 {
+                return Err(invalid_data("Invalid JPEG coefficient run"));
+            }
+            coefficients[ZIGZAG[k]] = reader.receive_extend(ac_size(size)?);
+            k += 1;
+        }
+        Ok(())
+    }
+
+    fn decode_dc(&self, reader: &mut BitReader, component: &mut Component, block: usize, scan: &Scan) -> io::Result<()> {
+        if scan.high == 0 {
+            let size = dc_size(reader.decode(table(&self.dc_tables, component.dc_table)?)?)?;
+            component.dc_prediction = component.dc_prediction.wrapping_add(reader.receive_extend(size));
+            component.coefficients[block][0] = component.dc_prediction.wrapping_mul(1 << scan.low);
+        } else if reader.receive(1) == 1 {
+            component.coefficients[block][0] |= 1 << scan.low;
+        }
+        Ok(())
+    }
+
+    fn decode_ac_first(&mut self, reader: &mut BitReader, component: &mut Component, block: usize, scan: &Scan) -> io::Result<()> {
+        if self.eob_run > 0 {
+            self.eob_run -= 1;
+            return Ok(());
+        }
+
+        let ac_table = table(&self.ac_tables, component.ac_table)?;
+        let coefficients = &mut component.coefficients[block];
+        let mut k = scan.start;
+        while k <= scan.end {
+            let symbol = reader.decode(ac_table)?;
+            let (run, size) = (symbol >> 4, symbol & 0x0F);
+            if size == 0 {
+                if run < 15 {
+                    self.eob_run = (1 << run) - 1 + reader.receive(run);
+                    break;
+                }
+                k += 16;
+                continue;
+            }
+            k += run as usize;
+            if k > scan.end {
+                return Err(invalid_data("Invalid JPEG coefficient run"));
+            }
+            coefficients[ZIGZAG[k]] = reader.receive_extend(ac_size(size)?) * (1 << scan.low);
+            k += 1;
+        }
+        Ok(())
+    }
+
+    /// Adds a bit to nonzero coefficients and places new coefficients of magnitude one, see JPEG Annex G.1.2.3
+    fn decode_ac_refine(&mut self, reader: &mut BitReader, component: &mut Component, block: usize, scan: &Scan) -> io::Result<()> {
+        let ac_table = table(&self.ac_tables, component.ac_table)?;
+        let coefficients = &mut component.coefficients[block];
+        let bit = 1 << scan.low;
+
+        let refine = |reader: &mut BitReader, coefficient: &mut i32| {
+            if reader.receive(1) == 1 && *coefficient & bit == 0 {
+                *coefficient = coefficient.wrapping_add(if *coefficient >= 0 { bit } else { -bit });
+            }
+        };
+
+        let mut k = scan.start;
+        if self.eob_run == 0 {
+            while k <= scan.end {
+                let symbol = reader.decode(ac_table)?;
+                let (mut run, size) = ((symbol >> 4) as usize, symbol & 0x0F);
+                let mut value = 0;
+                if size == 0 {
+                    if run < 15 {
+                        self.eob_run = (1 << run) + reader.receive(run as u8);
+                        break;
+                    }
+                } else {
+                    value = if reader.receive(1) == 1 { bit } else { -bit };
+                }
+
+                // Skips `run` zero coefficients, refining the nonzero ones passed on the way
+                while k <= scan.end {
+                    let coefficient = &mut coefficients[ZIGZAG[k]];
+                    k += 1;
+                    if *coefficient != 0 {
+                        refine(reader, coefficient);
+                    } else if run == 0 {
+                        *coefficient = value;
+                        break;
+                    } else {
+                        run -= 1;
+                    }
+                }
+            }
+        }
+
+        if self.eob_run > 0 {
+            for &position in ZIGZAG[k..=scan.end].iter() {
+                if coefficients[position] != 0 {
+                    refine(reader, &mut coefficients[position]);
+                }
+            }
+            self.eob_run -= 1;
+        }
+        Ok(())
+    }
+
+    fn decode_block(&mut self, reader: &mut BitReader, frame: &mut Frame, index: usize, block: usize, scan: &Scan) -> io::Result<()> {
+        let component = &mut frame.components[index];
+        match (frame.progressive, scan.start, scan.high) {
+            (false, _, _) => self.decode_baseline(reader, component, block),
+            (true, 0, _) => self.decode_dc(reader, component, block, scan),
+            (true, _, 0) => self.decode_ac_first(reader, component, block, scan),
+            (true, _, _) => self.decode_ac_refine(reader, component, block, scan),
+        }
+    }
+
+    /// Decodes the entropy coded data starting at `position`, returns the position of the following marker
+    fn decode_scan(&mut self, data: &[u8], position: usize, frame: &mut Frame, scan: &Scan) -> io::Result<usize> {
+        let mut reader = BitReader { data, position, buffer: 0, bits: 0 };
+
+        // A scan of one component covers only its own blocks, one per MCU
+        let (units_per_line, units) = match scan.components[..] {
+            [index] => {
+                let component = &frame.components[index];
+                let per_line = component.width.div_ceil(8);
+                (per_line, per_line * component.height.div_ceil(8))
+            },
+            _ => (frame.mcus_per_line, frame.mcus_per_line * frame.mcus_per_column),
+        };
+
+        // Coefficients are allocated by the first scan of a component, which codes its DC coefficients in at least one bit per block
+        for &index in scan.components.iter() {
+            let component = &mut frame.components[index];
+            if component.coefficients.is_empty() {
+                let blocks = component.blocks_per_line * component.blocks_per_column;
+                if scan.start != 0 {
+                    return Err(invalid_data("JPEG AC scan before the DC scan"));
+                }
+                if blocks > (data.len() - position).saturating_mul(8) {
+                    return Err(invalid_data("JPEG frame is too large for its data"));
+                }
+                component.coefficients = vec![[0; 64]; blocks];
+            }
+        }
+
+        let reset = |frame: &mut Frame| frame.components.iter_mut().for_each(|component| component.dc_prediction = 0);
+        reset(frame);
+        self.eob_run = 0;
+
+        for unit in 0..units {
+            if self.restart_interval > 0 && unit > 0 && unit % self.restart_interval == 0 {
+                reader.restart();
+                reset(frame);
+                self.eob_run = 0;
+            }
+
+            let (x, y) = (unit % units_per_line, unit / units_per_line);
+            if let [index] = scan.components[..] {
+                let block = y * frame.components[index].blocks_per_line + x;
+                self.decode_block(&mut reader, frame, index, block, scan)?;
+                continue;
+            }
+
+            for &index in scan.components.iter() {
+                let (h, v, blocks_per_line) = (frame.components[index].h, frame.components[index].v, frame.components[index].blocks_per_line);
+                for row in 0..v {
+                    for column in 0..h {
+                        let block = (y * v + row) * blocks_per_line + x * h + column;
+                        self.decode_block(&mut reader, frame, index, block, scan)?;
+                    }
+                }
+            }
+        }
+
+        Ok(reader.next_marker())
+    }
+
+    /// Dequantized and transformed samples of the component, including MCU padding
+    fn samples(&self, component: &Component, dct: &DCT) -> io::Result<Vec<u8>> {
+        if component.coefficients.is_empty() {
+            return Err(invalid_data("JPEG component has no scan"));
+        }
+        let quant_table = self.quant_tables[component.quant_table].ok_or_else(|| invalid_data("Missing JPEG quantization table"))?;
+        let stride = component.blocks_per_line * 8;
+        let mut samples = vec![0u8; stride * component.blocks_per_column * 8];
+
+        for (block, coefficients) in component.coefficients.iter().enumerate() {
+            let mut dequantized = [0.0f32; 64];
+            for (value, (&coefficient, &quant)) in dequantized.iter_mut().zip(coefficients.iter().zip(quant_table.iter())) {
+                *value = coefficient.saturating_mul(quant as i32) as f32;
+            }
+
+            let (x, y) = (block % component.blocks_per_line * 8, block / component.blocks_per_line * 8);
+            for (i, value) in dct.inverse(&dequantized).iter().enumerate() {
+                samples[(y + i / 8) * stride + x + i % 8] = (value + 128.0).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+        Ok(samples)
+    }
+
+    fn to_image(&self, frame: &Frame) -> io::Result<Image> {
+        let dct = DCT::default();
+        let planes = frame.components.iter().map(|component| self.samples(component, &dct)).collect::<io::Result<Vec<_>>>()?;
+
+        // Subsampled components are interpolated linearly between sample centers
+        let sample = |index: usize, x: usize, y: usize| -> f32 {
+            let component = &frame.components[index];
+            let stride = component.blocks_per_line * 8;
+            if component.h == frame.max_h && component.v == frame.max_v {
+                return planes[index][y * stride + x] as f32;
+            }
+
+            let position = |value: usize, factor: usize, max: usize, size: usize| {
+                let position = ((value as f32 + 0.5) * factor as f32 / max as f32 - 0.5).clamp(0.0, (size - 1) as f32);
+                let low = position as usize;
+                (low, (low + 1).min(size - 1), position - low as f32)
+            };
+            let (x0, x1, fx) = position(x, component.h, frame.max_h, component.width);
+            let (y0, y1, fy) = position(y, component.v, frame.max_v, component.height);
+            let at = |x: usize, y: usize| planes[index][y * stride + x] as f32;
+            let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+            let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+            top * (1.0 - fy) + bottom * fy
+        };
+
+        let ids: Vec<u8> = frame.components.iter().map(|component| component.id).collect();
+        let rgb = self.adobe_transform == Some(0) || (!self.jfif && self.adobe_transform.is_none() && ids == b"RGB");
+
+        let pixels = (0..frame.height).map(|y| (0..frame.width).map(|x| match frame.components.len() {
+            1 => {
+                let gray = sample(0, x, y).round() as u8;
+                Ok(Color::from_rgb(gray, gray, gray))
+            },
+            3 if rgb => Ok(Color::from_rgb(sample(0, x, y).round() as u8, sample(1, x, y).round() as u8, sample(2, x, y).round() as u8)),
+            3 => {
+                let [r, g, b] = jpeg::ycbcr_to_rgb(sample(0, x, y), sample(1, x, y), sample(2, x, y));
+                Ok(Color::from_rgb(r, g, b))
+            },
+            _ => Err(unsupported("Only grayscale and three component JPEG is supported")),
+        }).collect()).collect::<io::Result<Vec<Vec<Color>>>>()?;
+
+        Ok(Image::from_mat(frame.width, frame.height, pixels))
+    }
+}
+
+impl JPEGReader {
+    fn orient(&self, image: Image, orientation: Orientation) -> Image {
+        if orientation == Orientation::Normal {
+            return image;
+        }
+
+        let (width, height) = (image.width(), image.height());
+        let (display_width, display_height) = if orientation.is_transposed() { (height, width) } else { (width, height) };
+        let pixels = (0..display_height).map(|y| (0..display_width).map(|x| {
+            let (source_x, source_y) = orientation.source(x, y, width, height);
+            image.pixels[source_y][source_x]
+        }).collect()).collect();
+
+        Image::from_mat(display_width, display_height, pixels)
+    }
+
+    /// Decodes baseline and progressive Huffman coded JPEG, rotated as the EXIF orientation requires
+    pub fn decode(&self, data: &[u8]) -> io::Result<Image> {
+        if data.get(..2) != Some(&[0xFF, jpeg::SOI]) {
+            return Err(invalid_data("Invalid JPEG file"));
+        }
+
+        let mut decoder = Decoder::default();
+        let mut frame: Option<Frame> = None;
+        let mut position = 2;
+
+        while position < data.len() {
+            if data[position] != 0xFF {
+                return Err(invalid_data("Invalid JPEG marker"));
+            }
+            while data.get(position) == Some(&0xFF) {
+                position += 1;
+            }
+            let marker = *data.get(position).ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete JPEG data"))?;
+            position += 1;
+
+            match marker {
+                jpeg::EOI => break,
+                jpeg::RST0..=jpeg::RST7 => continue,
+                _ => {},
+            }
+
+            let length = data.get(position..position + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete JPEG data"))?;
+            let segment = data.get(position + 2..position + length.max(2))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete JPEG segment"))?;
+            position += length;
+
+            match marker {
+                jpeg::DQT => decoder.read_quant_tables(segment)?,
+                jpeg::DHT => decoder.read_huffman_tables(segment)?,
+                jpeg::DRI if segment.len() >= 2 => decoder.restart_interval = u16::from_be_bytes([segment[0], segment[1]]) as usize,
+                jpeg::SOF0 | jpeg::SOF1 | jpeg::SOF2 => frame = Some(decoder.read_frame(segment, marker == jpeg::SOF2)?),
+                0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return Err(unsupported("Only Huffman coded DCT JPEG is supported")),
+                jpeg::SOS => {
+                    let frame = frame.as_mut().ok_or_else(|| invalid_data("JPEG scan before the frame header"))?;
+                    let scan = decoder.read_scan(segment, frame)?;
+                    position = decoder.decode_scan(data, position, frame, &scan)?;
+                },
+                jpeg::APP0 if segment.starts_with(jpeg::JFIF_IDENTIFIER) => decoder.jfif = true,
+                jpeg::APP1 if segment.starts_with(jpeg::EXIF_IDENTIFIER) => decoder.orientation = jpeg::exif_orientation(&segment[6..]),
+                jpeg::APP14 if segment.starts_with(b"Adobe") && segment.len() >= 12 => decoder.adobe_transform = Some(segment[11]),
+                _ => {},
+            }
+        }
+
+        let frame = frame.ok_or_else(|| invalid_data("JPEG file has no frame"))?;
+        let image = decoder.to_image(&frame)?;
+        Ok(self.orient(image, decoder.orientation.unwrap_or_default()))
+    }
+}
+
+impl Reader for JPEGReader {
+    fn read(&self, path: &str) -> io::Result<Image> {
+        println!("Reading JPEG file");
+
+        let data = fs::read(path)?;
+        self.decode(&data)
+    }
+}
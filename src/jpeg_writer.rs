@@ -0,0 +1,361 @@
+use std::fs;
+use std::io;
+
+use crate::common::*;
+use crate::huffman;
+use crate::jpeg::{self, HuffmanSpec, Orientation, DCT, ZIGZAG};
+
+pub use crate::png_writer::AlphaConversion;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Subsampling {
+    /// Chroma at full resolution
+    Chroma444,
+    /// Chroma halved horizontally
+    Chroma422,
+    /// Chroma halved in both directions
+    #[default]
+    Chroma420,
+}
+
+impl Subsampling {
+    /// Horizontal and vertical sampling factors of the luminance
+    fn factors(&self) -> (usize, usize) {
+        match self {
+            Subsampling::Chroma444 => (1, 1),
+            Subsampling::Chroma422 => (2, 1),
+            Subsampling::Chroma420 => (2, 2),
+        }
+    }
+}
+
+pub struct Settings {
+    /// From 1 to 100, scales the quantization tables of JPEG Annex K.1 as libjpeg does
+    pub quality: u8,
+    /// Ignored for grayscale images, which are written with a single component
+    pub subsampling: Subsampling,
+    /// Huffman tables built from the image instead of the typical tables of JPEG Annex K.3
+    pub optimize_huffman: bool,
+    pub alpha: AlphaConversion,
+    /// Writes a JFIF APP0 segment
+    pub jfif: bool,
+    /// Writes an EXIF APP1 segment with the orientation
+    pub orientation: Option<Orientation>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            quality: 90,
+            subsampling: Subsampling::default(),
+            optimize_huffman: false,
+            alpha: AlphaConversion::default(),
+            jfif: true,
+            orientation: None,
+        }
+    }
+}
+
+pub struct JPEGWriter {
+    pub settings: Settings
+}
+
+/// Code and code length of each symbol
+struct HuffmanCode {
+    codes: [u16; 256],
+    lengths: [u8; 256],
+}
+
+impl HuffmanCode {
+    fn new(spec: &HuffmanSpec) -> HuffmanCode {
+        let (mut codes, mut lengths) = ([0; 256], [0; 256]);
+        let mut symbols = spec.symbols.iter();
+        let mut code = 0u16;
+        for (length, &count) in (1..=16).zip(spec.counts.iter()) {
+            for &symbol in symbols.by_ref().take(count as usize) {
+                codes[symbol as usize] = code;
+                lengths[symbol as usize] = length;
+                code += 1;
+            }
+            code <<= 1;
+        }
+        HuffmanCode { codes, lengths }
+    }
+}
+
+/// Table for the symbol frequencies where no code consists of only ones, see JPEG Annex K.2
+fn optimized_spec(frequencies: &[u64; 256]) -> HuffmanSpec {
+    // A reserved symbol takes the all ones code, the last code of the longest length
+    let reserved = 256;
+    let mut lengths = huffman::code_lengths(&[frequencies.as_slice(), &[1]].concat(), 16);
+    let longest = lengths.iter().copied().max().unwrap_or(0);
+    if lengths[reserved] != longest {
+        let last = (0..reserved).rev().find(|&symbol| lengths[symbol] == longest).unwrap();
+        lengths.swap(last, reserved);
+    }
+
+    let mut symbols: Vec<u8> = (0..reserved).filter(|&symbol| lengths[symbol] > 0).map(|symbol| symbol as u8).collect();
+    symbols.sort_by_key(|&symbol| lengths[symbol as usize]);
+    let mut counts = [0u8; 16];
+    for &symbol in symbols.iter() {
+        counts[lengths[symbol as usize] as usize - 1] += 1;
+    }
+    HuffmanSpec { counts, symbols }
+}
+
+/// Packs codes most significant bit first, stuffing a zero byte after each 0xFF
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, length: u8) {
+        self.buffer = (self.buffer << length) | code as u32;
+        self.bits += length;
+        while self.bits >= 8 {
+            let byte = (self.buffer >> (self.bits - 8)) as u8;
+            self.output.push(byte);
+            if byte == 0xFF {
+                self.output.push(0x00);
+            }
+            self.bits -= 8;
+        }
+        self.buffer &= (1 << self.bits) - 1;
+    }
+
+    /// Pads the last byte with ones
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            let padding = 8 - self.bits;
+            self.write((1 << padding) - 1, padding);
+        }
+        self.output
+    }
+}
+
+/// Number of bits of the magnitude of the value
+fn category(value: i32) -> u8 {
+    (32 - value.unsigned_abs().leading_zeros()) as u8
+}
+
+/// Calls `emit` with whether the symbol is from the AC table, the symbol and the value following it
+fn block_symbols(block: &[i32; 64], previous_dc: i32, emit: &mut impl FnMut(bool, u8, i32)) {
+    let difference = block[0] - previous_dc;
+    emit(false, category(difference), difference);
+
+    let mut run = 0;
+    for &value in block[1..].iter() {
+        if value == 0 {
+            run += 1;
+            continue;
+        }
+        while run > 15 {
+            emit(true, 0xF0, 0);
+            run -= 16;
+        }
+        emit(true, (run << 4) | category(value), value);
+        run = 0;
+    }
+    if run > 0 {
+        emit(true, 0x00, 0);
+    }
+}
+
+fn scaled_quant_table(table: &[u16; 64], quality: u8) -> [u16; 64] {
+    let quality = quality.clamp(1, 100) as u32;
+    let scale = if quality < 50 { 5000 / quality } else { 200 - 2 * quality };
+    table.map(|value| ((value as u32 * scale + 50) / 100).clamp(1, 255) as u16)
+}
+
+/// Samples of one component with the luminance plane size padded to whole MCUs
+struct Plane {
+    width: usize,
+    height: usize,
+    samples: Vec<f32>,
+}
+
+impl Plane {
+    /// Averages blocks of `h` by `v` samples
+    fn downsample(&self, h: usize, v: usize) -> Plane {
+        let (width, height) = (self.width / h, self.height / v);
+        let samples = (0..width * height).map(|i| {
+            let (x, y) = (i % width * h, i / width * v);
+            let sum: f32 = (0..v).flat_map(|dy| (0..h).map(move |dx| (dx, dy)))
+                .map(|(dx, dy)| self.samples[(y + dy) * self.width + x + dx])
+                .sum();
+            sum / (h * v) as f32
+        }).collect();
+        Plane { width, height, samples }
+    }
+
+    /// Quantized coefficients of the block in zigzag order
+    fn block(&self, x: usize, y: usize, dct: &DCT, quant_table: &[u16; 64]) -> [i32; 64] {
+        let mut samples = [0.0f32; 64];
+        for (i, sample) in samples.iter_mut().enumerate() {
+            *sample = self.samples[(y * 8 + i / 8) * self.width + x * 8 + i % 8] - 128.0;
+        }
+        let coefficients = dct.forward(&samples);
+        ZIGZAG.map(|position| (coefficients[position] / quant_table[position] as f32).round() as i32)
+    }
+}
+
+fn segment(data: &mut Vec<u8>, marker: u8, payload: &[u8]) {
+    data.extend_from_slice(&[0xFF, marker]);
+    data.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+    data.extend_from_slice(payload);
+}
+
+impl JPEGWriter {
+    fn exif(&self, orientation: Orientation) -> Vec<u8> {
+        let mut exif = jpeg::EXIF_IDENTIFIER.to_vec();
+        // Big endian TIFF header and the first IFD with a single SHORT entry
+        exif.extend_from_slice(b"MM\0\x2A\0\0\0\x08\0\x01");
+        exif.extend_from_slice(&[0x01, 0x12, 0, 3, 0, 0, 0, 1]);
+        exif.extend_from_slice(&orientation.value().to_be_bytes());
+        exif.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        exif
+    }
+
+    /// Baseline JPEG with interleaved components in a single scan
+    pub fn encode(&self, image: &Image) -> io::Result<Vec<u8>> {
+        let (width, height) = (image.width(), image.height());
+        if width == 0 || height == 0 || width > u16::MAX as usize || height > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid image size for JPEG"));
+        }
+
+        let pixels: Vec<Color> = image.pixels.iter().flatten().map(|&color| match self.settings.alpha {
+            AlphaConversion::Discard => color,
+            AlphaConversion::Flatten(background) => color.flatten(background),
+        }).collect();
+        let gray = pixels.iter().all(Color::is_gray);
+
+        let (h, v) = if gray { (1, 1) } else { self.settings.subsampling.factors() };
+        let (mcus_per_line, mcus_per_column) = (width.div_ceil(8 * h), height.div_ceil(8 * v));
+        let (padded_width, padded_height) = (mcus_per_line * 8 * h, mcus_per_column * 8 * v);
+
+        // Edge pixels are repeated into the padding
+        let channels: Vec<[f32; 3]> = (0..padded_width * padded_height).map(|i| {
+            let (x, y) = ((i % padded_width).min(width - 1), (i / padded_width).min(height - 1));
+            let color = pixels[y * width + x];
+            jpeg::rgb_to_ycbcr(color.r, color.g, color.b)
+        }).collect();
+        let plane = |channel: usize| Plane { width: padded_width, height: padded_height, samples: channels.iter().map(|samples| samples[channel]).collect() };
+
+        let mut planes = vec![plane(0)];
+        if !gray {
+            planes.push(plane(1).downsample(h, v));
+            planes.push(plane(2).downsample(h, v));
+        }
+
+        let quant_tables = [
+            scaled_quant_table(&jpeg::LUMINANCE_QUANT_TABLE, self.settings.quality),
+            scaled_quant_table(&jpeg::CHROMINANCE_QUANT_TABLE, self.settings.quality),
+        ];
+
+        // Blocks in scan order with their component
+        let dct = DCT::default();
+        let mut blocks: Vec<(usize, [i32; 64])> = Vec::new();
+        for mcu_y in 0..mcus_per_column {
+            for mcu_x in 0..mcus_per_line {
+                for (component, plane) in planes.iter().enumerate() {
+                    let (component_h, component_v) = if component == 0 { (h, v) } else { (1, 1) };
+                    for y in 0..component_v {
+                        for x in 0..component_h {
+                            let block = plane.block(mcu_x * component_h + x, mcu_y * component_v + y, &dct, &quant_tables[component.min(1)]);
+                            blocks.push((component, block));
+                        }
+                    }
+                }
+            }
+        }
+
+        let each_symbol = |emit: &mut dyn FnMut(usize, bool, u8, i32)| {
+            let mut previous_dc = [0; 3];
+            for (component, block) in blocks.iter() {
+                block_symbols(block, previous_dc[*component], &mut |ac, symbol, value| emit(*component, ac, symbol, value));
+                previous_dc[*component] = block[0];
+            }
+        };
+
+        // Luminance DC, luminance AC, chrominance DC and chrominance AC
+        let specs: [HuffmanSpec; 4] = if self.settings.optimize_huffman {
+            let mut frequencies = [[0u64; 256]; 4];
+            each_symbol(&mut |component, ac, symbol, _| frequencies[component.min(1) * 2 + ac as usize][symbol as usize] += 1);
+            frequencies.each_ref().map(optimized_spec)
+        } else {
+            HuffmanSpec::standard()
+        };
+        let codes = specs.each_ref().map(HuffmanCode::new);
+
+        let mut writer = BitWriter { output: Vec::new(), buffer: 0, bits: 0 };
+        each_symbol(&mut |component, ac, symbol, value| {
+            let code = &codes[component.min(1) * 2 + ac as usize];
+            writer.write(code.codes[symbol as usize], code.lengths[symbol as usize]);
+
+            // Negative values are stored as the value minus one in `size` bits
+            let size = if ac { symbol & 0x0F } else { symbol };
+            if size > 0 {
+                let bits = if value < 0 { value - 1 } else { value };
+                writer.write((bits & ((1 << size) - 1)) as u16, size);
+            }
+        });
+        let entropy_data = writer.finish();
+
+        let mut data = vec![0xFF, jpeg::SOI];
+        if self.settings.jfif {
+            // Version 1.01 without units, a 1:1 pixel aspect ratio and no thumbnail
+            segment(&mut data, jpeg::APP0, &[jpeg::JFIF_IDENTIFIER.as_slice(), &[1, 1, 0, 0, 1, 0, 1, 0, 0]].concat());
+        }
+        if let Some(orientation) = self.settings.orientation {
+            segment(&mut data, jpeg::APP1, &self.exif(orientation));
+        }
+
+        let table_count = if gray { 1 } else { 2 };
+        for (id, table) in quant_tables.iter().take(table_count).enumerate() {
+            let payload = [&[id as u8], ZIGZAG.map(|position| table[position] as u8).as_slice()].concat();
+            segment(&mut data, jpeg::DQT, &payload);
+        }
+
+        let mut frame = vec![8];
+        frame.extend_from_slice(&(height as u16).to_be_bytes());
+        frame.extend_from_slice(&(width as u16).to_be_bytes());
+        frame.push(planes.len() as u8);
+        for component in 0..planes.len() {
+            let sampling = if component == 0 { (h << 4 | v) as u8 } else { 0x11 };
+            frame.extend_from_slice(&[component as u8 + 1, sampling, component.min(1) as u8]);
+        }
+        segment(&mut data, jpeg::SOF0, &frame);
+
+        for (i, spec) in specs.iter().enumerate().take(table_count * 2) {
+            let class_and_id = ((i as u8 % 2) << 4) | (i as u8 / 2);
+            segment(&mut data, jpeg::DHT, &[&[class_and_id], spec.counts.as_slice(), &spec.symbols].concat());
+        }
+
+        let mut scan = vec![planes.len() as u8];
+        for component in 0..planes.len() {
+            let tables = component.min(1) as u8;
+            scan.extend_from_slice(&[component as u8 + 1, tables << 4 | tables]);
+        }
+        scan.extend_from_slice(&[0, 63, 0]);
+        segment(&mut data, jpeg::SOS, &scan);
+
+        data.extend_from_slice(&entropy_data);
+        data.extend_from_slice(&[0xFF, jpeg::EOI]);
+        Ok(data)
+    }
+}
+
+impl Writer for JPEGWriter {
+    fn extension(&self) -> &str {
+        "jpg"
+    }
+
+    fn write(&self, image: Image, path: &str) {
+        println!("Writing JPEG file at path: {}", path);
+
+        let data = self.encode(&image).expect("Can't encode JPEG file");
+        fs::write(path, data).expect("Can't save output JPEG file");
+    }
+}
@@ -1,13 +1,72 @@
 pub mod common;
 pub mod ppm_reader;
 pub mod ppm_writer;
+pub mod pgm_reader;
+pub mod pgm_writer;
+pub mod pbm_reader;
+pub mod pbm_writer;
+pub mod pam_reader;
+pub mod pam_writer;
+pub mod pfm_reader;
+pub mod pfm_writer;
+
+pub mod bmp;
+pub mod bmp_reader;
+pub mod bmp_writer;
+
+pub mod tga;
+pub mod tga_reader;
+pub mod tga_writer;
+
+pub mod qoi;
+pub mod qoi_reader;
+pub mod qoi_writer;
+
+pub mod farbfeld;
+pub mod farbfeld_reader;
+pub mod farbfeld_writer;
+
+pub mod hdr;
+pub mod hdr_reader;
+pub mod hdr_writer;
+
+pub mod exr;
+pub mod exr_reader;
+pub mod exr_writer;
+
+pub mod gif;
+pub mod gif_reader;
+pub mod gif_writer;
+
+pub mod jpeg;
+pub mod jpeg_reader;
+pub mod jpeg_writer;
+
+pub mod ico;
+pub mod ico_reader;
+pub mod ico_writer;
+
+pub mod tiff;
+pub mod tiff_reader;
+pub mod tiff_writer;
+
+pub mod webp;
+pub mod webp_reader;
+
+pub mod dds;
+pub mod dds_reader;
 
 pub mod png;
 pub mod png_reader;
 pub mod png_writer;
+pub mod png_optimizer;
+pub mod png_validator;
 
+mod netpbm;
 mod read_to_string_exact;
 mod binary_serializable;
+mod huffman;
+mod zopfli;
 
 #[cfg(test)]
 mod tests {
@@ -18,7 +77,7 @@ mod tests {
     #[test]
     fn ppm_write() {
         let image = common::Image::from_mat(8, 8, vec![vec![common::Color::from_rgb(100, 0, 100); 8]; 8]);
-        let writer = ppm_writer::PPMWriter {};
+        let writer = ppm_writer::PPMWriter { settings: Default::default() };
         writer.write(image, "output/image.ppm");
     }
 
@@ -27,7 +86,7 @@ mod tests {
         let ppm_reader = ppm_reader::PPMReader {};
         let image = ppm_reader.read("resources/6pixels.ppm").unwrap();
 
-        let writer = ppm_writer::PPMWriter {};
+        let writer = ppm_writer::PPMWriter { settings: Default::default() };
         writer.write(image, "output/image.ppm");
     }
     
@@ -36,7 +95,7 @@ mod tests {
         let png_reader = png_reader::PNGReader {};
         let image = png_reader.read("resources/defiltered.png").unwrap();
 
-        let ppm_writer = ppm_writer::PPMWriter {};
+        let ppm_writer = ppm_writer::PPMWriter { settings: Default::default() };
         ppm_writer.write(image, "output/image.ppm");
     }
 
@@ -45,7 +104,7 @@ mod tests {
         let png_reader = png_reader::PNGReader {};
         let image = png_reader.read("resources/PNG_transparency_demonstration_1.png").unwrap();
 
-        let ppm_writer = ppm_writer::PPMWriter {};
+        let ppm_writer = ppm_writer::PPMWriter { settings: Default::default() };
         ppm_writer.write(image, "output/image.ppm");
     }
 
@@ -54,7 +113,7 @@ mod tests {
         let png_reader = png_reader::PNGReader {};
         let image = png_reader.read("resources/pnglogo-grr.png").unwrap();
 
-        let ppm_writer = ppm_writer::PPMWriter {};
+        let ppm_writer = ppm_writer::PPMWriter { settings: Default::default() };
         ppm_writer.write(image, "output/image.ppm");
     }
     
@@ -66,10 +125,1146 @@ mod tests {
         let writer = png_writer::PNGWriter {
             settings: png_writer::Settings {
                 bit_depth: 8,
-                color_type: png::ihdr::ColorType::RGB,
+                color_type: png::ihdr::ColorType::RGB.into(),
                 interlace_method: 0,
+                ..Default::default()
             }
         };
         writer.write(image, "output/image.png");
     }
+
+    #[test]
+    fn png_optimize() {
+        let png = png::PNG::from_file("resources/example5000.png").unwrap();
+
+        let optimizer = png_optimizer::PNGOptimizer {
+            settings: png_optimizer::Settings {
+                filters: vec![png_writer::FilterStrategy::Paeth, png_writer::FilterStrategy::MinSum],
+                compression_levels: vec![9],
+                zopfli: Some(2),
+                strip: png_optimizer::Strip::All,
+            }
+        };
+        let optimized = optimizer.optimize(&png).unwrap();
+        assert!(optimized.size() < png.size());
+
+        let png_reader = png_reader::PNGReader {};
+        assert!(png_reader.decode(&optimized).unwrap().pixels == png_reader.decode(&png).unwrap().pixels);
+    }
+
+    #[test]
+    fn png_optimize_color_type() {
+        let pixels = (0..8).map(|y| (0..8).map(|x| if (x + y) % 2 == 0 { common::Color::black() } else { common::Color::from_rgb(255, 255, 255) }).collect()).collect();
+        let image = common::Image::from_mat(8, 8, pixels);
+
+        let writer = png_writer::PNGWriter { settings: Default::default() };
+        let mut png = writer.encode(&image).unwrap();
+
+        // Unknown chunks are kept only when they are safe to copy
+        let iend = png.chunks.pop().unwrap();
+        png.chunks.push(png::Chunk::new(png::ChunkType::Other("prVt".to_string()), vec![1]));
+        png.chunks.push(png::Chunk::new(png::ChunkType::Other("prVT".to_string()), vec![2]));
+        png.chunks.push(iend);
+
+        let optimizer = png_optimizer::PNGOptimizer { settings: Default::default() };
+        let optimized = optimizer.optimize(&png).unwrap();
+        assert!(optimized.chunks.iter().any(|chunk| chunk.chunk_type.is("prVt")));
+        assert!(!optimized.chunks.iter().any(|chunk| chunk.chunk_type.is("prVT")));
+        let ihdr = optimized.ihdr().unwrap();
+        assert_eq!(ihdr.color_type, png::ihdr::ColorType::Grayscale);
+        assert_eq!(ihdr.bit_depth, 1);
+
+        let png_reader = png_reader::PNGReader {};
+        assert!(png_reader.decode(&optimized).unwrap().pixels == image.pixels);
+    }
+
+    #[test]
+    fn png_write_auto_color_type() {
+        let auto = png_writer::PNGWriter {
+            settings: png_writer::Settings {
+                color_type: png_writer::ColorMode::Auto,
+                ..Default::default()
+            }
+        };
+        let png_reader = png_reader::PNGReader {};
+
+        let cases = [
+            (common::Color::black(), common::Color::from_rgb(255, 255, 255), png::ihdr::ColorType::Grayscale, 1),
+            (common::Color::from_rgb(200, 10, 10), common::Color::new(0, 0, 0, 0), png::ihdr::ColorType::Palette, 1),
+            (common::Color::new(90, 90, 90, 128), common::Color::new(91, 91, 91, 255), png::ihdr::ColorType::Palette, 1),
+        ];
+
+        for (first, second, color_type, bit_depth) in cases {
+            let image = common::Image::from_mat(3, 2, vec![vec![first, second, first], vec![second, first, second]]);
+            let png = auto.encode(&image).unwrap();
+            let ihdr = png.ihdr().unwrap();
+
+            assert_eq!((ihdr.color_type, ihdr.bit_depth), (color_type, bit_depth));
+            assert!(png_reader.decode(&png).unwrap().pixels == image.pixels);
+        }
+
+        let gradient = (0..32).map(|y| (0..32).map(|x| common::Color::new(x * 8, y * 8, 0, 255 - x)).collect()).collect();
+        let image = common::Image::from_mat(32, 32, gradient);
+        let png = auto.encode(&image).unwrap();
+        assert_eq!(png.ihdr().unwrap().color_type, png::ihdr::ColorType::RGBA);
+        assert!(png_reader.decode(&png).unwrap().pixels == image.pixels);
+
+        let image = png_reader.read("resources/example5000.png").unwrap();
+        let png = auto.encode(&image).unwrap();
+        assert_eq!(png.ihdr().unwrap().color_type, png::ihdr::ColorType::Palette);
+        assert!(png_reader.decode(&png).unwrap().pixels == image.pixels);
+    }
+
+    #[test]
+    fn png_write_conversion() {
+        let png_reader = png_reader::PNGReader {};
+        let image = common::Image::from_mat(2, 1, vec![vec![common::Color::from_rgb(255, 0, 0), common::Color::new(0, 0, 255, 0)]]);
+
+        let grayscale = png_writer::PNGWriter {
+            settings: png_writer::Settings {
+                color_type: png::ihdr::ColorType::Grayscale.into(),
+                conversion: png_writer::Conversion {
+                    luminance: common::Luminance::Rec601,
+                    alpha: png_writer::AlphaConversion::Flatten(common::Color::from_rgb(255, 255, 255)),
+                    strict: false,
+                },
+                ..Default::default()
+            }
+        };
+        let decoded = png_reader.decode(&grayscale.encode(&image).unwrap()).unwrap();
+        assert_eq!(decoded.pixels[0], vec![common::Color::from_rgb(76, 76, 76), common::Color::from_rgb(255, 255, 255)]);
+
+        let png = png::PNG::from_file("resources/PNG_transparency_demonstration_1.png").unwrap();
+        let background = png.background().unwrap().unwrap();
+        let rgb = png_writer::PNGWriter {
+            settings: png_writer::Settings {
+                color_type: png::ihdr::ColorType::RGB.into(),
+                conversion: png_writer::Conversion {
+                    alpha: png_writer::AlphaConversion::Flatten(background),
+                    ..Default::default()
+                },
+                filter: png_writer::FilterStrategy::Paeth,
+                ..Default::default()
+            }
+        };
+        let decoded = png_reader.decode(&rgb.encode(&image).unwrap()).unwrap();
+        assert_eq!(decoded.pixels[0][1], background);
+
+        let strict = png_writer::PNGWriter {
+            settings: png_writer::Settings {
+                color_type: png::ihdr::ColorType::RGB.into(),
+                conversion: png_writer::Conversion { strict: true, ..Default::default() },
+                ..Default::default()
+            }
+        };
+        assert!(strict.encode(&image).is_err());
+    }
+
+    #[test]
+    fn zopfli_compress() {
+        use std::io::{Read, Write};
+
+        let png_reader = png_reader::PNGReader {};
+        let png = png::PNG::from_file("resources/example5000.png").unwrap();
+        let data = png_reader.image_data(&png).unwrap();
+
+        let compressed = zopfli::compress(&data, 5);
+        let mut decompressed = Vec::new();
+        flate2::read::ZlibDecoder::new(compressed.as_slice()).read_to_end(&mut decompressed).unwrap();
+        assert!(decompressed == data);
+
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(&data).unwrap();
+        assert!(compressed.len() < encoder.finish().unwrap().len());
+
+        for data in [vec![], vec![7u8], vec![0u8; 70000], (0..=255u8).collect::<Vec<u8>>()] {
+            let mut decompressed = Vec::new();
+            flate2::read::ZlibDecoder::new(zopfli::compress(&data, 3).as_slice()).read_to_end(&mut decompressed).unwrap();
+            assert!(decompressed == data);
+        }
+    }
+
+    #[test]
+    fn png_write_zopfli() {
+        let png_reader = png_reader::PNGReader {};
+        let image = png_reader.read("resources/example5000.png").unwrap();
+
+        let writer = png_writer::PNGWriter {
+            settings: png_writer::Settings {
+                color_type: png::ihdr::ColorType::RGB.into(),
+                filter: png_writer::FilterStrategy::MinSum,
+                deflate: png_writer::Deflate::Zopfli { iterations: 2 },
+                ..Default::default()
+            }
+        };
+        let png = writer.encode(&image).unwrap();
+        assert!(png_reader.decode(&png).unwrap().pixels == image.pixels);
+    }
+
+    #[test]
+    fn png_validate() {
+        use png_validator::Diagnostic;
+
+        let validator = png_validator::PNGValidator {};
+        for path in ["resources/defiltered.png", "resources/example5000.png", "resources/pnglogo-grr.png", "resources/PNG_transparency_demonstration_1.png"] {
+            assert!(validator.validate_file(path).unwrap().is_empty());
+        }
+
+        let bytes = std::fs::read("resources/example5000.png").unwrap();
+
+        let mut corrupted = bytes.clone();
+        corrupted[0] = 0;
+        assert_eq!(validator.validate_bytes(&corrupted), vec![Diagnostic::InvalidMagic]);
+
+        let mut corrupted = bytes.clone();
+        corrupted[29] ^= 0xff; // IHDR CRC
+        corrupted.extend_from_slice(b"trailing");
+        let diagnostics = validator.validate_bytes(&corrupted);
+        assert!(matches!(diagnostics[0], Diagnostic::TrailingData { bytes: 8 }));
+        assert!(matches!(diagnostics[1], Diagnostic::CrcMismatch { chunk: 0, .. }));
+        assert_eq!(diagnostics.len(), 2);
+
+        let diagnostics = validator.validate_bytes(&bytes[..bytes.len() - 100]);
+        assert_eq!(diagnostics, vec![Diagnostic::TruncatedChunk { chunk: 2 }, Diagnostic::MissingIDAT, Diagnostic::MissingIEND]);
+
+        let mut png = png::PNG::from_bytes(&bytes).unwrap();
+        let idat = &png.chunks[2];
+        png.chunks[2] = png::Chunk::new(png::ChunkType::IDAT, idat.data[..idat.data.len() / 2].to_vec());
+        let diagnostics = validator.validate(&png);
+        assert!(matches!(diagnostics[..], [Diagnostic::ZlibError { .. }, Diagnostic::NotEnoughImageData { .. }]));
+
+        // Inflating stops after the first byte the scanlines do not need
+        let ihdr = png.ihdr().unwrap();
+        let expected = (1 + ihdr.scanline_length(ihdr.width as usize)) * ihdr.height as usize;
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+        std::io::Write::write_all(&mut encoder, &vec![0; 64 * 1024 * 1024]).unwrap();
+        png.chunks[2] = png::Chunk::new(png::ChunkType::IDAT, encoder.finish().unwrap());
+        assert_eq!(validator.validate(&png), vec![Diagnostic::TooMuchImageData { expected }]);
+
+        let mut png = png::PNG::from_bytes(&bytes).unwrap();
+        let iend = png.chunks.pop().unwrap();
+        png.chunks.insert(1, png.chunks[2].clone());
+        png.chunks.push(iend.clone());
+        png.chunks.push(iend);
+        let diagnostics = validator.validate(&png);
+        assert!(diagnostics.contains(&Diagnostic::NonContiguousIDAT { chunk: 3 }));
+        assert!(diagnostics.contains(&Diagnostic::ChunkAfterIEND { chunk: 5 }));
+        let misplaced = diagnostics.iter().find(|diagnostic| matches!(diagnostic, Diagnostic::MisplacedChunk { chunk: 2, .. })).unwrap();
+        assert_eq!(misplaced.severity(), png_validator::Severity::Warning);
+    }
+
+    #[test]
+    fn png_recover() {
+        let png_reader = png_reader::PNGReader {};
+        let fill = common::Color::new(255, 0, 255, 255);
+
+        let bytes = std::fs::read("resources/defiltered.png").unwrap();
+        let original = png_reader.read("resources/defiltered.png").unwrap();
+
+        let (image, report) = png_reader.recover_bytes(&bytes, fill).unwrap();
+        assert!(report.is_complete() && report.bad_crcs.is_empty() && !report.truncated && !report.missing_iend);
+        assert!(image.pixels == original.pixels);
+
+        let mut truncated = bytes[..bytes.len() / 2].to_vec();
+        truncated[40] ^= 0xff; // gAMA CRC
+        assert!(png_reader.decode(&png::PNG::from_bytes(&truncated).unwrap()).is_err());
+        assert!(png::PNG::from_bytes(&bytes[..5]).is_err());
+
+        // Bit depths outside the PNG table are rejected before unfiltering
+        for bit_depth in [0, 3, 17] {
+            let mut png = png::PNG::from_bytes(&bytes).unwrap();
+            png.chunks[0].data[8] = bit_depth;
+            assert_eq!(png_reader.decode(&png).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+            assert_eq!(png_reader.recover_bytes(&png.to_bytes(), fill).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+        }
+
+        // The recovered image is bounded by the data, not by the IHDR size alone
+        let mut png = png::PNG::from_bytes(&bytes).unwrap();
+        png.chunks[0].data[..8].copy_from_slice(&[0, 0, 0xff, 0xff, 0, 0, 0xff, 0xff]);
+        assert_eq!(png_reader.recover_bytes(&png.to_bytes(), fill).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(png_reader.decode(&png).err().unwrap().kind(), std::io::ErrorKind::UnexpectedEof);
+
+        // A huge chunk length ends the chunk list without allocating it
+        let mut huge = png::MAGIC.to_vec();
+        huge.extend_from_slice(&[0x7f, 0xff, 0xff, 0xff, b'I', b'D', b'A', b'T', 0, 0]);
+        assert!(png::PNG::from_bytes(&huge).unwrap().chunks.is_empty());
+
+        let (image, report) = png_reader.recover_bytes(&truncated, fill).unwrap();
+        assert!(report.truncated && report.missing_iend && !report.is_complete());
+        assert_eq!(report.bad_crcs, vec![1]);
+        assert_eq!(report.total_scanlines, 256);
+        assert_eq!(report.complete_rows, (0..report.scanlines).collect::<Vec<_>>());
+        assert_eq!(report.decoded_pixels, report.scanlines * 256);
+        assert!(image.pixels[..report.scanlines] == original.pixels[..report.scanlines]);
+        assert!(image.pixels[report.scanlines..].iter().flatten().all(|&color| color == fill));
+
+        let bytes = std::fs::read("resources/pnglogo-grr.png").unwrap();
+        let (image, report) = png_reader.recover_bytes(&bytes[..bytes.len() / 2], fill).unwrap();
+        assert!(report.scanlines > 0 && report.complete_rows.len() < 768);
+        assert_eq!(image.pixels.iter().flatten().filter(|&&color| color != fill).count(), report.decoded_pixels);
+    }
+
+    #[test]
+    fn ppm_binary_write_read() {
+        let png_reader = png_reader::PNGReader {};
+        let image = png_reader.read("resources/defiltered.png").unwrap();
+        let pixels = image.pixels.clone();
+
+        let writer = ppm_writer::PPMWriter {
+            settings: ppm_writer::Settings { encoding: ppm_writer::Encoding::Binary }
+        };
+        writer.write(image, "output/binary.ppm");
+        assert_eq!(std::fs::metadata("output/binary.ppm").unwrap().len(), 15 + 256 * 256 * 3);
+
+        let ppm_reader = ppm_reader::PPMReader {};
+        let image = ppm_reader.read("output/binary.ppm").unwrap();
+        assert_eq!((image.width(), image.height()), (256, 256));
+        assert!(image.pixels == pixels);
+    }
+
+    #[test]
+    fn pgm_write_read() {
+        let png_reader = png_reader::PNGReader {};
+        let image = png_reader.read("resources/defiltered.png").unwrap();
+        let gray: Vec<u8> = image.pixels.iter().flatten().map(|color| color.luminance(common::Luminance::Rec601)).collect();
+
+        for encoding in [pgm_writer::Encoding::ASCII, pgm_writer::Encoding::Binary] {
+            let image = common::Image::from_mat(image.width(), image.height(), image.pixels.clone());
+            let writer = pgm_writer::PGMWriter {
+                settings: pgm_writer::Settings { encoding, luminance: common::Luminance::Rec601 }
+            };
+            writer.write(image, "output/image.pgm");
+
+            let image = pgm_reader::PGMReader {}.read("output/image.pgm").unwrap();
+            assert!(image.pixels.iter().flatten().map(|color| color.r).eq(gray.iter().copied()));
+        }
+    }
+
+    #[test]
+    fn pbm_write_read() {
+        let (white, black) = (common::Color::from_rgb(250, 240, 230), common::Color::from_rgb(100, 20, 10));
+        let pixels: Vec<Vec<common::Color>> = (0..5)
+            .map(|y| (0..11).map(|x| if (x + y) % 3 == 0 { black } else { white }).collect())
+            .collect();
+
+        for encoding in [pbm_writer::Encoding::ASCII, pbm_writer::Encoding::Binary] {
+            let writer = pbm_writer::PBMWriter {
+                settings: pbm_writer::Settings { encoding, ..Default::default() }
+            };
+            writer.write(common::Image::from_mat(11, 5, pixels.clone()), "output/image.pbm");
+
+            let image = pbm_reader::PBMReader {}.read("output/image.pbm").unwrap();
+            assert_eq!((image.width(), image.height()), (11, 5));
+            for (row, expected) in image.pixels.iter().zip(pixels.iter()) {
+                assert!(row.iter().zip(expected.iter()).all(|(color, expected)| (color.r == 0) == (*expected == black)));
+            }
+        }
+        assert_eq!(std::fs::metadata("output/image.pbm").unwrap().len(), 8 + 2 * 5);
+    }
+
+    #[test]
+    fn pam_write_read() {
+        let png_reader = png_reader::PNGReader {};
+        let image = png_reader.read("resources/PNG_transparency_demonstration_1.png").unwrap();
+        let pixels = image.pixels.clone();
+
+        for bit_depth in [8, 16] {
+            let writer = pam_writer::PAMWriter {
+                settings: pam_writer::Settings { bit_depth, ..Default::default() }
+            };
+            writer.write(common::Image::from_mat(image.width(), image.height(), pixels.clone()), "output/image.pam");
+
+            let image = pam_reader::PAMReader {}.read("output/image.pam").unwrap();
+            assert!(image.pixels == pixels);
+        }
+
+        let writer = pam_writer::PAMWriter {
+            settings: pam_writer::Settings { tuple_type: pam_writer::TupleType::BlackAndWhiteAlpha, ..Default::default() }
+        };
+        writer.write(image, "output/image.pam");
+
+        let image = pam_reader::PAMReader {}.read("output/image.pam").unwrap();
+        assert!(image.pixels.iter().flatten().all(|color| color.is_gray() && [0, 255].contains(&color.r) && [0, 255].contains(&color.a)));
+        assert!(image.pixels.iter().flatten().zip(pixels.iter().flatten()).all(|(color, source)| (color.a == 255) == (source.a >= 128)));
+    }
+
+    #[test]
+    fn netpbm_tokenizer() {
+        std::fs::write("output/comments.ppm", "P3 # comment\n2\n# size\n1 15\n0 7 15 15# sample\n15 15\n").unwrap();
+        let image = ppm_reader::PPMReader {}.read("output/comments.ppm").unwrap();
+        assert!(image.pixels[0] == [common::Color::from_rgb(0, 119, 255), common::Color::from_rgb(255, 255, 255)]);
+
+        let mut data = b"P5 2 1 1023\n".to_vec();
+        data.extend_from_slice(&[0x03, 0xFF, 0x02, 0x00]);
+        std::fs::write("output/16bit.pgm", &data).unwrap();
+        let image = pgm_reader::PGMReader {}.read("output/16bit.pgm").unwrap();
+        assert_eq!((image.pixels[0][0].r, image.pixels[0][1].r), (255, 128));
+
+        std::fs::write("output/short.pgm", &data[..data.len() - 1]).unwrap();
+        let error = pgm_reader::PGMReader {}.read("output/short.pgm").err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+
+        std::fs::write("output/short.pbm", "P1 3 2 0 1 0 1 1").unwrap();
+        let error = pbm_reader::PBMReader {}.read("output/short.pbm").err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+
+        // A comment may sit between the max value and the whitespace before the raster
+        std::fs::write("output/comment_raster.pgm", b"P5 2 1 255# comment\n\x10\x20").unwrap();
+        let image = pgm_reader::PGMReader {}.read("output/comment_raster.pgm").unwrap();
+        assert_eq!((image.pixels[0][0].r, image.pixels[0][1].r), (0x10, 0x20));
+
+        // Images without pixels are invalid rather than a height of empty rows
+        for (name, data) in [("empty.pbm", "P1 0 3\n"), ("empty.pgm", "P2 4 0 255\n")] {
+            std::fs::write(format!("output/{}", name), data).unwrap();
+        }
+        assert_eq!(pbm_reader::PBMReader {}.read("output/empty.pbm").err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(pgm_reader::PGMReader {}.read("output/empty.pgm").err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+
+        // Sizes whose sample count overflows are invalid
+        let huge = format!("P6 {} {} 255\n", usize::MAX / 2, 3);
+        std::fs::write("output/huge.ppm", huge).unwrap();
+        let error = ppm_reader::PPMReader {}.read("output/huge.ppm").err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn pfm_write_read() {
+        let data: Vec<f32> = (0..4 * 3 * 3).map(|i| i as f32 * 0.37 - 2.5).collect();
+        let image = common::FloatImage::from_data(4, 3, 3, data.clone());
+
+        for endianness in [pfm_writer::Endianness::Little, pfm_writer::Endianness::Big] {
+            let writer = pfm_writer::PFMWriter {
+                settings: pfm_writer::Settings { endianness, scale: 4.0 }
+            };
+            writer.write_float(&image, "output/image.pfm").unwrap();
+
+            let image = pfm_reader::PFMReader {}.read_float("output/image.pfm").unwrap();
+            assert_eq!((image.width(), image.height(), image.channels()), (4, 3, 3));
+            assert!(image.data.iter().zip(data.iter()).all(|(a, b)| (a - b).abs() < 1e-5));
+        }
+
+        // The first row in the file is the bottom one
+        let content = std::fs::read("output/image.pfm").unwrap();
+        let first = f32::from_be_bytes(content[content.len() - 4 * 3 * 3 * 4..][..4].try_into().unwrap());
+        assert_eq!(first * 4.0, data[4 * 3 * 2]);
+
+        let image = pfm_reader::PFMReader {}.read("output/image.pfm").unwrap();
+        assert_eq!(image.pixels[0][0], common::Color::from_rgb(0, 0, 0));
+        assert_eq!(image.pixels[2][3], common::Color::from_rgb(255, 255, 255));
+    }
+
+    #[test]
+    fn netpbm_streams() {
+        let frames: Vec<common::Image> = (0..3u8)
+            .map(|i| common::Image::from_mat(3, 2, vec![vec![common::Color::from_rgb(i * 50, 100, 200 - i); 3]; 2]))
+            .collect();
+
+        for encoding in [ppm_writer::Encoding::ASCII, ppm_writer::Encoding::Binary] {
+            let writer = ppm_writer::PPMWriter { settings: ppm_writer::Settings { encoding } };
+            let mut stream = Vec::new();
+            for frame in frames.iter() {
+                writer.write_frame(frame, &mut stream).unwrap();
+            }
+
+            let images: Vec<common::Image> = ppm_reader::PPMReader {}.images(stream.as_slice())
+                .collect::<std::io::Result<_>>().unwrap();
+            assert_eq!(images.len(), 3);
+            assert!(images.iter().zip(frames.iter()).all(|(image, frame)| image.pixels == frame.pixels));
+        }
+
+        let writer = pam_writer::PAMWriter { settings: Default::default() };
+        let mut stream = Vec::new();
+        writer.write_frame(&frames[0], &mut stream).unwrap();
+        writer.write_frame(&frames[1], &mut stream).unwrap();
+        stream.truncate(stream.len() - 1);
+
+        let mut images = pam_reader::PAMReader {}.images(stream.as_slice());
+        assert!(images.next().unwrap().unwrap().pixels == frames[0].pixels);
+        assert!(images.next().unwrap().is_err());
+        assert!(images.next().is_none());
+
+        // Frames are decoded as they arrive, before the stream has ended
+        struct Closed;
+        impl std::io::Read for Closed {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Stream closed"))
+            }
+        }
+        let mut stream = Vec::new();
+        writer.write_frame(&frames[2], &mut stream).unwrap();
+        let mut images = pam_reader::PAMReader {}.images(std::io::BufReader::new(std::io::Read::chain(stream.as_slice(), Closed)));
+        assert!(images.next().unwrap().unwrap().pixels == frames[2].pixels);
+        assert_eq!(images.next().unwrap().err().unwrap().kind(), std::io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn bmp_write_read() {
+        let png_reader = png_reader::PNGReader {};
+        let image = png_reader.read("resources/PNG_transparency_demonstration_1.png").unwrap();
+        let pixels = image.pixels.clone();
+
+        for pixel_format in [bmp_writer::PixelFormat::RGB, bmp_writer::PixelFormat::RGBA] {
+            let writer = bmp_writer::BMPWriter { settings: bmp_writer::Settings { pixel_format } };
+            writer.write(common::Image::from_mat(image.width(), image.height(), pixels.clone()), "output/image.bmp");
+
+            let image = bmp_reader::BMPReader {}.read("output/image.bmp").unwrap();
+            let alpha = pixel_format == bmp_writer::PixelFormat::RGBA;
+            assert!(image.pixels.iter().flatten().zip(pixels.iter().flatten())
+                .all(|(color, source)| (color.r, color.g, color.b) == (source.r, source.g, source.b) && color.a == if alpha { source.a } else { 255 }));
+        }
+    }
+
+    #[test]
+    fn bmp_read_rle8() {
+        let (red, blue) = (common::Color::from_rgb(255, 0, 0), common::Color::from_rgb(0, 0, 255));
+
+        let mut dib = vec![40, 0, 0, 0, 5, 0, 0, 0, 2, 0, 0, 0, 1, 0, 8, 0, 1, 0, 0, 0];
+        dib.extend_from_slice(&[0; 12]);
+        dib.extend_from_slice(&[2, 0, 0, 0, 0, 0, 0, 0]);
+        dib.extend_from_slice(&[0, 0, 255, 0, 255, 0, 0, 0]);
+        // Bottom row: run of 4 reds and end of line, top row: absolute 1 0 1 then delta to the end
+        dib.extend_from_slice(&[4, 0, 0, 0, 0, 3, 1, 0, 1, 0, 0, 2, 1, 0, 0, 1]);
+
+        let image = bmp_reader::BMPReader {}.decode_dib(&dib, None).unwrap();
+        assert_eq!((image.width(), image.height()), (5, 2));
+        assert!(image.pixels[0][..3] == [blue, red, blue]);
+        assert_eq!(image.pixels[0][4].a, 0);
+        assert!(image.pixels[1][..4] == [red; 4]);
+
+        // A bit count of 0 is rejected with the header
+        let mut header = dib[..40].to_vec();
+        header[14] = 0;
+        assert_eq!(bmp_reader::BMPReader {}.decode_dib(&header, None).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+
+        // RLE sizes far beyond what the data can reach are rejected before allocating rows
+        let mut large = dib.clone();
+        large[4..8].copy_from_slice(&i32::MAX.to_le_bytes());
+        large[8..12].copy_from_slice(&i32::MAX.to_le_bytes());
+        assert_eq!(bmp_reader::BMPReader {}.decode_dib(&large, None).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn tga_write_read() {
+        let png_reader = png_reader::PNGReader {};
+        let image = png_reader.read("resources/PNG_transparency_demonstration_1.png").unwrap();
+        let pixels = image.pixels.clone();
+        let raw_size = 18 + image.width() * image.height() * 4 + 26;
+
+        for rle in [false, true] {
+            let writer = tga_writer::TGAWriter { settings: tga_writer::Settings { rle, ..Default::default() } };
+            writer.write(common::Image::from_mat(image.width(), image.height(), pixels.clone()), "output/image.tga");
+
+            let size = std::fs::metadata("output/image.tga").unwrap().len() as usize;
+            assert!(if rle { size < raw_size } else { size == raw_size });
+
+            let image = tga_reader::TGAReader {}.read("output/image.tga").unwrap();
+            assert!(image.pixels == pixels);
+        }
+    }
+
+    #[test]
+    fn tga_read_color_mapped() {
+        let (red, green) = (common::Color::from_rgb(255, 0, 0), common::Color::from_rgb(0, 255, 0));
+
+        // RLE color mapped, bottom-left origin, 15 bit color map entries starting at index 5
+        let mut data = vec![0, 1, 9, 5, 0, 2, 0, 15, 0, 0, 0, 0, 3, 0, 2, 0, 8, 0];
+        data.extend_from_slice(&[0x00, 0x7C, 0xE0, 0x03]);
+        data.extend_from_slice(&[0x82, 5, 0x02, 6, 5, 6]);
+
+        let image = tga_reader::TGAReader {}.decode(&data).unwrap();
+        assert!(image.pixels == [vec![green, red, green], vec![red; 3]]);
+
+        // The largest RLE image with a single packet runs out of data without reserving its full size
+        let mut data = vec![0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 32, 8];
+        data.extend_from_slice(&[0xFF, 1, 2, 3, 4]);
+        let error = tga_reader::TGAReader {}.decode(&data).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn qoi_write_read() {
+        let png_reader = png_reader::PNGReader {};
+        let image = png_reader.read("resources/PNG_transparency_demonstration_1.png").unwrap();
+        let pixels = image.pixels.clone();
+
+        let writer = qoi_writer::QOIWriter { settings: Default::default() };
+        writer.write(image, "output/image.qoi");
+        let image = qoi_reader::QOIReader {}.read("output/image.qoi").unwrap();
+        assert!(image.pixels == pixels);
+
+        // Streaming in uneven pieces gives the same data as encoding the whole image
+        let data = std::fs::read("output/image.qoi").unwrap();
+        let colors: Vec<common::Color> = pixels.iter().flatten().copied().collect();
+        let mut encoder = qoi_writer::QOIEncoder::new(
+            Vec::new(), image.width() as u32, image.height() as u32, qoi_writer::Channels::RGBA, qoi_writer::ColorSpace::SRGB
+        ).unwrap();
+        for piece in colors.chunks(1000) {
+            encoder.write_pixels(piece).unwrap();
+        }
+        assert!(encoder.finish().unwrap() == data);
+
+        let writer = qoi_writer::QOIWriter {
+            settings: qoi_writer::Settings { channels: qoi_writer::Channels::RGB, color_space: qoi_writer::ColorSpace::Linear }
+        };
+        let data = writer.encode(&image).unwrap();
+        let header = <qoi::Header as binary_serializable::BinarySerializable>::read(&mut data.as_slice()).unwrap();
+        assert_eq!((header.channels, header.color_space), (qoi_writer::Channels::RGB, qoi_writer::ColorSpace::Linear));
+
+        let image = qoi_reader::QOIReader {}.decode(&data).unwrap();
+        assert!(image.pixels.iter().flatten().zip(colors.iter()).all(|(color, source)| *color == common::Color { a: 255, ..*source }));
+
+        // A leading run puts the initial opaque black into the index, which OP_INDEX then refers to
+        let hash = qoi::hash(common::Color::black()) as u8;
+        let mut data = b"qoif\0\0\0\x04\0\0\0\x01\x04\0".to_vec();
+        data.extend([qoi::OP_RUN | 1, qoi::OP_RGB, 10, 20, 30, qoi::OP_INDEX | hash]);
+        data.extend(qoi::END_MARKER);
+        let image = qoi_reader::QOIReader {}.decode(&data).unwrap();
+        assert!(image.pixels[0][3] == common::Color::black());
+    }
+
+    #[test]
+    fn farbfeld_write_read() {
+        let png_reader = png_reader::PNGReader {};
+        let image = png_reader.read("resources/PNG_transparency_demonstration_1.png").unwrap();
+
+        let mut stream = Vec::new();
+        farbfeld_writer::FarbfeldWriter {}.write_to(&image, &mut stream).unwrap();
+        assert_eq!(stream.len(), 16 + image.width() * image.height() * 8);
+
+        let decoded = farbfeld_reader::FarbfeldReader {}.read_from(&mut stream.as_slice()).unwrap();
+        assert!(decoded.pixels == image.pixels);
+
+        // A huge width in the header fails at the end of the data instead of allocating the row up front
+        let mut header = stream[..16].to_vec();
+        header[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        let error = farbfeld_reader::FarbfeldReader {}.read_from(&mut header.as_slice()).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+
+        // Zero width rows need no data, so the size itself is rejected
+        header[8..16].copy_from_slice(&[0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+        let error = farbfeld_reader::FarbfeldReader {}.read_from(&mut header.as_slice()).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        // 16 bit samples survive a float round trip
+        let data: Vec<f32> = (0..2 * 2 * 4).map(|i| (i * 4099) as f32 / 65535.0).collect();
+        let float = common::FloatImage::from_data(2, 2, 4, data.clone());
+        farbfeld_writer::FarbfeldWriter {}.write_float(&float, "output/image.ff").unwrap();
+        let float = farbfeld_reader::FarbfeldReader {}.read_float("output/image.ff").unwrap();
+        assert!(float.data == data);
+    }
+
+    #[test]
+    fn hdr_write_read() {
+        // Smooth gradient with flat areas so both run and literal packets are used
+        let (width, height) = (40, 6);
+        let data: Vec<f32> = (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = ((i % width) as f32, (i / width) as f32);
+                if x < 12.0 { [0.25, 0.5, 4.0] } else { [x * 0.1, y * 13.7, 0.001 * x] }
+            })
+            .collect();
+        let image = common::FloatImage::from_data(width, height, 3, data.clone());
+        // RGBE shares the exponent, so the error is relative to the largest channel
+        let close = |decoded: &[f32]| decoded.chunks(3).zip(data.chunks(3)).all(|(a, b)| {
+            let max = b.iter().copied().fold(0.0, f32::max);
+            a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() <= max / 128.0)
+        });
+
+        for (rle, exposure) in [(true, 1.0), (true, 0.5), (false, 2.0)] {
+            let writer = hdr_writer::HDRWriter { settings: hdr_writer::Settings { rle, exposure } };
+            let encoded = writer.encode(&image).unwrap();
+            if rle {
+                assert!(encoded.len() < 100 + width * height * 4);
+            }
+
+            let (header, decoded) = hdr_reader::HDRReader {}.decode(&encoded).unwrap();
+            assert_eq!((header.exposure, header.width, header.height), (exposure, width, height));
+            assert!(close(&decoded.data));
+        }
+
+        // Old style runs repeat the previous pixel, consecutive runs shift the count
+        let mut encoded = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 260\n".to_vec();
+        encoded.extend_from_slice(&[128, 64, 32, 129, 1, 1, 1, 3, 1, 1, 1, 1]);
+        let (_, decoded) = hdr_reader::HDRReader {}.decode(&encoded).unwrap();
+        assert!(decoded.data.chunks(3).all(|rgb| rgb == [128.5 / 128.0, 64.5 / 128.0, 32.5 / 128.0]));
+
+        // A fifth consecutive run would shift the count past 32 bits
+        let mut encoded = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 100\n".to_vec();
+        encoded.extend_from_slice(&[128, 64, 32, 129]);
+        (0..5).for_each(|_| encoded.extend_from_slice(&[1, 1, 1, 0]));
+        assert_eq!(hdr_reader::HDRReader {}.decode(&encoded).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+
+        // Zero sizes, sizes the data cannot fill and non-positive exposures are invalid
+        for header in ["-Y 4000000000 +X 0", "-Y 4000000000 +X 1", "-Y 0 +X 1"] {
+            let mut encoded = format!("#?RADIANCE\n\n{}\n", header).into_bytes();
+            encoded.extend_from_slice(&[128, 64, 32, 129, 1, 1, 1, 255]);
+            assert_eq!(hdr_reader::HDRReader {}.decode(&encoded).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+        }
+        for exposure in ["0", "-1", "inf"] {
+            let mut encoded = format!("#?RADIANCE\nEXPOSURE={}\n\n-Y 1 +X 1\n", exposure).into_bytes();
+            encoded.extend_from_slice(&[128, 64, 32, 129]);
+            assert_eq!(hdr_reader::HDRReader {}.decode(&encoded).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn exr_write_read() {
+        let (width, height) = (37, 21);
+        let gradient = |scale: f32| (0..width * height).map(|i| ((i % width) as f32 * scale).sin() * 100.0 + (i / width) as f32).collect::<Vec<f32>>();
+
+        let channels = vec![
+            exr::Channel { name: "Z".to_string(), pixel_type: exr::PixelType::Float, linear: false },
+            exr::Channel { name: "N.x".to_string(), pixel_type: exr::PixelType::Half, linear: false },
+            exr::Channel { name: "id".to_string(), pixel_type: exr::PixelType::UInt, linear: false },
+        ];
+        let ids: Vec<f32> = (0..width * height).map(|i| (i / 100) as f32).collect();
+
+        for compression in [exr::Compression::None, exr::Compression::RLE, exr::Compression::ZIPS, exr::Compression::ZIP] {
+            let mut header = exr::Header::new(width, height, channels.clone(), compression);
+            header.data_window = exr::Box2i { x_min: -3, y_min: 5, x_max: width as i32 - 4, y_max: height as i32 + 4 };
+            let image = exr::EXRImage { header, samples: vec![gradient(0.1), gradient(0.3), ids.clone()] };
+
+            let data = exr_writer::EXRWriter { settings: Default::default() }.encode(&image).unwrap();
+            let decoded = exr_reader::EXRReader {}.decode(&data).unwrap();
+
+            assert_eq!(decoded.header.data_window, image.header.data_window);
+            assert_eq!(decoded.header.compression, compression);
+            assert!(decoded.channel("Z").unwrap() == gradient(0.1));
+            assert!(decoded.channel("N.x").unwrap().iter().zip(gradient(0.3)).all(|(a, b)| (a - b).abs() <= b.abs() / 1024.0));
+            assert!(decoded.channel("id").unwrap() == ids);
+        }
+
+        // A data window far larger than the file can hold is rejected before allocating samples
+        let image = exr::EXRImage { header: exr::Header::new(width, height, channels.clone(), exr::Compression::ZIP), samples: vec![gradient(0.1), gradient(0.3), ids.clone()] };
+        let mut data = exr_writer::EXRWriter { settings: Default::default() }.encode(&image).unwrap();
+        let window = data.windows(17).position(|name| name == b"dataWindow\0box2i\0").unwrap() + 17 + 4;
+        data[window + 8..window + 12].copy_from_slice(&i32::MAX.to_le_bytes());
+        let error = exr_reader::EXRReader {}.decode(&data).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        // Without channels there is nothing to bound the data window by
+        let image = exr::EXRImage { header: exr::Header::new(width, height, Vec::new(), exr::Compression::None), samples: Vec::new() };
+        let data = exr_writer::EXRWriter { settings: Default::default() }.encode(&image).unwrap();
+        let error = exr_reader::EXRReader {}.decode(&data).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        let png_reader = png_reader::PNGReader {};
+        let image = png_reader.read("resources/PNG_transparency_demonstration_1.png").unwrap();
+        let pixels = image.pixels.clone();
+        exr_writer::EXRWriter { settings: Default::default() }.write(image, "output/image.exr");
+        let image = exr_reader::EXRReader {}.read("output/image.exr").unwrap();
+        assert!(image.pixels == pixels);
+
+        assert_eq!(exr::f32_to_half(1.0), 0x3C00);
+        assert_eq!(exr::f32_to_half(65520.0), 0x7C00);
+        assert_eq!(exr::f32_to_half(5.96e-8), 0x0001);
+        assert_eq!(exr::half_to_f32(0xC000), -2.0);
+    }
+
+    #[test]
+    fn gif_write_read() {
+        let (width, height) = (40, 30);
+        let transparent = common::Color::new(0, 0, 0, 0);
+        let frames: Vec<gif::Frame> = (0..3).map(|i| {
+            let pixels = (0..height).map(|y| (0..width).map(|x| match (x + i * 5) / 10 {
+                _ if y < 5 => transparent,
+                0 => common::Color::from_rgb(255, 0, 0),
+                1 => common::Color::from_rgb(0, 255, 0),
+                2 => common::Color::from_rgb(0, 0, 255),
+                _ => common::Color::from_rgb(x as u8 % 8 * 30, y as u8 % 8 * 30, 7),
+            }).collect()).collect();
+            gif::Frame { image: common::Image::from_mat(width, height, pixels), delay: 10 * (i as u16 + 1), disposal: gif::Disposal::Background }
+        }).collect();
+        let animation = gif::GIF { width, height, loop_count: Some(0), frames };
+
+        let gif_writer = gif_writer::GIFWriter { settings: Default::default() };
+        gif_writer.write_gif(&animation, "output/animation.gif").unwrap();
+        let decoded = gif_reader::GIFReader {}.read_gif("output/animation.gif").unwrap();
+
+        assert_eq!(decoded.loop_count, Some(0));
+        assert_eq!(decoded.frames.len(), 3);
+        for (frame, original) in decoded.frames.iter().zip(animation.frames.iter()) {
+            assert_eq!(frame.delay, original.delay);
+            assert_eq!(frame.disposal, gif::Disposal::Background);
+            assert!(frame.image.pixels == original.image.pixels);
+        }
+
+        // Kept frames followed by transparent pixels still decode to the frames that were written
+        let red = common::Image::from_mat(2, 2, vec![vec![common::Color::from_rgb(255, 0, 0); 2]; 2]);
+        let cleared = common::Image::from_mat(2, 2, vec![vec![transparent; 2], vec![common::Color::from_rgb(0, 0, 255); 2]]);
+        let frames = [red, cleared].into_iter().map(|image| gif::Frame { image, delay: 5, disposal: gif::Disposal::Keep }).collect();
+        let animation = gif::GIF { width: 2, height: 2, loop_count: None, frames };
+        let decoded = gif_reader::GIFReader {}.decode(&gif_writer.encode(&animation).unwrap()).unwrap();
+        for (frame, original) in decoded.frames.iter().zip(animation.frames.iter()) {
+            assert!(frame.image.pixels == original.image.pixels);
+        }
+        assert_eq!(decoded.frames[1].disposal, gif::Disposal::Keep);
+
+        // Previous disposal restores the frame area to what it was before the frame
+        let mut data = gif::MAGIC_89A.to_vec();
+        data.extend_from_slice(&[3, 0, 1, 0, 0x80, 0, 0, 255, 0, 0, 0, 0, 255]);
+        for (disposal, transparent_flag, left, frame_width) in [(3, 0, 1u8, 2u8), (1, 0, 0, 1), (1, 1, 0, 1)] {
+            data.extend_from_slice(&[gif::EXTENSION, gif::GRAPHIC_CONTROL, 4, disposal << 2 | transparent_flag, 0, 0, 1, 0]);
+            data.extend_from_slice(&[gif::IMAGE_DESCRIPTOR, left, 0, 0, 0, frame_width, 0, 1, 0, 0, 2]);
+            let indices = gif::lzw_encode(&vec![1; frame_width as usize], 2);
+            data.push(indices.len() as u8);
+            data.extend_from_slice(&indices);
+            data.push(0);
+        }
+        data.push(gif::TRAILER);
+        let decoded = gif_reader::GIFReader {}.decode(&data).unwrap();
+        let blue = common::Color::from_rgb(0, 0, 255);
+        assert!(decoded.frames[0].image.pixels[0] == [transparent, blue, blue]);
+        assert!(decoded.frames[1].image.pixels[0] == [blue, transparent, transparent]);
+        assert!(decoded.frames[2].image.pixels[0] == [blue, transparent, transparent]);
+
+        // The canvas is not allocated from the header alone
+        let mut data = gif::MAGIC_89A.to_vec();
+        data.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, gif::TRAILER]);
+        assert!(gif_reader::GIFReader {}.decode(&data).unwrap().frames.is_empty());
+        data.pop();
+        data.extend_from_slice(&[gif::IMAGE_DESCRIPTOR, 0, 0, 0, 0, 1, 0, 1, 0, 0x80, 0, 0, 0, 0, 0, 0, 2, 0, gif::TRAILER]);
+        assert_eq!(gif_reader::GIFReader {}.decode(&data).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+
+        // Too many colors are quantized, long runs fill the LZW table
+        let png_reader = png_reader::PNGReader {};
+        let image = png_reader.read("resources/PNG_transparency_demonstration_1.png").unwrap();
+        let pixels = image.pixels.clone();
+        gif_writer.write(image, "output/image.gif");
+        let image = gif_reader::GIFReader {}.read("output/image.gif").unwrap();
+        let close = pixels.iter().flatten().zip(image.pixels.iter().flatten()).filter(|(a, b)| {
+            (a.a < 128 && b.a == 0) || (a.a >= 128 && b.a == 255 && a.r.abs_diff(b.r) < 48 && a.g.abs_diff(b.g) < 48 && a.b.abs_diff(b.b) < 48)
+        }).count();
+        assert!(close * 100 >= pixels.len() * 95);
+
+        let indices: Vec<u8> = (0..100_000u32).map(|i| (i.wrapping_mul(2654435761) >> 28) as u8).collect();
+        assert!(gif::lzw_decode(&gif::lzw_encode(&indices, 4), 4, indices.len()).unwrap() == indices);
+    }
+
+    #[test]
+    fn jpeg_read() {
+        let ppm_reader = ppm_reader::PPMReader {};
+        let pgm_reader = pgm_reader::PGMReader {};
+        let jpeg_reader = jpeg_reader::JPEGReader {};
+
+        // References were decoded by libjpeg with its default integer IDCT and fancy upsampling
+        for (name, expected) in [
+            ("python", ppm_reader.read("resources/python_jpeg.ppm").unwrap()),
+            ("jpeg_progressive", ppm_reader.read("resources/jpeg_progressive.ppm").unwrap()),
+            ("jpeg_restart_422", ppm_reader.read("resources/jpeg_restart_422.ppm").unwrap()),
+            ("jpeg_gray_restart", pgm_reader.read("resources/jpeg_gray_restart.pgm").unwrap()),
+        ] {
+            let image = jpeg_reader.read(&format!("resources/{}.jpg", name)).unwrap();
+            assert_eq!((image.width(), image.height()), (expected.width(), expected.height()));
+
+            let differences: Vec<u8> = image.pixels.iter().flatten().zip(expected.pixels.iter().flatten())
+                .flat_map(|(a, b)| [a.r.abs_diff(b.r), a.g.abs_diff(b.g), a.b.abs_diff(b.b)])
+                .collect();
+            let mean = differences.iter().map(|&difference| difference as f32).sum::<f32>() / differences.len() as f32;
+            assert!(mean < 0.5 && differences.iter().all(|&difference| difference <= 3));
+        }
+
+        // DC magnitude categories beyond 11 bits are rejected
+        let mut data = std::fs::read("resources/python.jpg").unwrap();
+        let dht = (0..data.len() - 4).find(|&i| data[i..i + 2] == [0xFF, 0xC4] && data[i + 4] == 0x00).unwrap();
+        let count: usize = data[dht + 5..dht + 21].iter().map(|&count| count as usize).sum();
+        data[dht + 21..dht + 21 + count].fill(40);
+        assert_eq!(jpeg_reader.decode(&data).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+
+        // Coefficients are not allocated from the frame header alone
+        let mut data = std::fs::read("resources/python.jpg").unwrap();
+        let sof = (0..data.len() - 1).find(|&i| data[i..i + 2] == [0xFF, 0xC0]).unwrap();
+        data[sof + 5..sof + 9].fill(0xFF);
+        assert_eq!(jpeg_reader.decode(&data).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+
+        let image = jpeg_reader.read("resources/python.jpg").unwrap();
+
+        // EXIF orientation 6 rotates the stored image clockwise
+        let exif = [b"Exif\0\0MM\0\x2A\0\0\0\x08\0\x01".as_slice(), &[0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, 0, 0, 0, 0]].concat();
+        let data = std::fs::read("resources/python.jpg").unwrap();
+        let rotated = [&data[..2], &[0xFF, 0xE1, 0, exif.len() as u8 + 2], &exif, &data[2..]].concat();
+        let rotated = jpeg_reader.decode(&rotated).unwrap();
+        assert!((0..16).all(|y| (0..16).all(|x| rotated.pixels[y][x] == image.pixels[15 - x][y])));
+    }
+
+    #[test]
+    fn jpeg_write_read() {
+        let (width, height) = (61, 37);
+        let pixels = (0..height).map(|y| (0..width).map(|x| common::Color::from_rgb((x * 4) as u8, (y * 6) as u8, ((x + y) * 2) as u8)).collect()).collect();
+        let image = common::Image::from_mat(width, height, pixels);
+        let jpeg_reader = jpeg_reader::JPEGReader {};
+
+        let mean_error = |decoded: &common::Image| {
+            let error: u32 = image.pixels.iter().flatten().zip(decoded.pixels.iter().flatten())
+                .map(|(a, b)| a.r.abs_diff(b.r) as u32 + a.g.abs_diff(b.g) as u32 + a.b.abs_diff(b.b) as u32)
+                .sum();
+            error as f32 / (width * height * 3) as f32
+        };
+
+        for subsampling in [jpeg_writer::Subsampling::Chroma444, jpeg_writer::Subsampling::Chroma422, jpeg_writer::Subsampling::Chroma420] {
+            let mut sizes = Vec::new();
+            for optimize_huffman in [false, true] {
+                let settings = jpeg_writer::Settings { subsampling, optimize_huffman, ..Default::default() };
+                let data = jpeg_writer::JPEGWriter { settings }.encode(&image).unwrap();
+                let decoded = jpeg_reader.decode(&data).unwrap();
+                assert_eq!((decoded.width(), decoded.height()), (width, height));
+                assert!(mean_error(&decoded) < 2.0);
+                sizes.push(data.len());
+            }
+            assert!(sizes[1] < sizes[0]);
+        }
+
+        let settings = jpeg_writer::Settings { quality: 20, orientation: Some(jpeg::Orientation::Rotate270), ..Default::default() };
+        jpeg_writer::JPEGWriter { settings }.write(image, "output/image.jpg");
+        let rotated = jpeg_reader.read("output/image.jpg").unwrap();
+        assert_eq!((rotated.width(), rotated.height()), (height, width));
+
+        // Gray images have a single component
+        let gray = common::Image::from_mat(9, 9, vec![vec![common::Color::from_rgb(90, 90, 90); 9]; 9]);
+        let data = jpeg_writer::JPEGWriter { settings: Default::default() }.encode(&gray).unwrap();
+        assert!(jpeg_reader.decode(&data).unwrap().pixels.iter().flatten().all(|color| *color == common::Color::from_rgb(90, 90, 90)));
+    }
+
+    #[test]
+    fn ico_write_read() {
+        let icon = |size: usize| {
+            let pixels = (0..size).map(|y| (0..size).map(|x| match (x + y) % 3 {
+                0 => common::Color::new(0, 0, 0, 0),
+                1 => common::Color::new((x * 255 / size) as u8, (y * 255 / size) as u8, 200, 128),
+                _ => common::Color::from_rgb(10, 20, 30),
+            }).collect()).collect();
+            common::Image::from_mat(size, size, pixels)
+        };
+        let sizes = [16, 33, 256];
+        let entries = sizes.iter().map(|&size| ico::Entry { image: icon(size), hotspot: (size as u16 / 2, 3) }).collect();
+        let cursor = ico::ICO { resource_type: ico::ResourceType::Cursor, entries };
+
+        let ico_writer = ico_writer::ICOWriter { settings: Default::default() };
+        ico_writer.write_ico(&cursor, "output/cursor.cur").unwrap();
+        let ico_reader = ico_reader::ICOReader {};
+        let decoded = ico_reader.read_ico("output/cursor.cur").unwrap();
+
+        assert_eq!(decoded.resource_type, ico::ResourceType::Cursor);
+        for (entry, &size) in decoded.entries.iter().zip(sizes.iter()) {
+            assert!(entry.image.pixels == icon(size).pixels);
+            assert_eq!(entry.hotspot, (size as u16 / 2, 3));
+        }
+        assert!(ico_reader.read_size("output/cursor.cur", 33, 33).unwrap().pixels == icon(33).pixels);
+        assert!(ico_reader.read_size("output/cursor.cur", 32, 32).is_err());
+        assert!(decoded.entry(33, 33).unwrap().image.pixels == icon(33).pixels);
+        assert_eq!(decoded.largest().unwrap().image.width(), 256);
+
+        // Picking an entry by its directory size leaves the other entries undecoded
+        let mut data = std::fs::read("output/cursor.cur").unwrap();
+        data[6 + 8..6 + 12].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write("output/cursor_broken.cur", &data).unwrap();
+        assert!(ico_reader.decode(&data).is_err());
+        assert!(ico_reader.read_size("output/cursor_broken.cur", 33, 33).unwrap().pixels == icon(33).pixels);
+        assert!(ico_reader.read("output/cursor_broken.cur").unwrap().pixels == icon(256).pixels);
+
+        // Icons without alpha rely on the AND mask
+        ico_writer.write(icon(8), "output/icon.ico");
+        let mut data = std::fs::read("output/icon.ico").unwrap();
+        let color_offset = 6 + 16 + 40;
+        (0..64).for_each(|i| data[color_offset + i * 4 + 3] = 0);
+        let image = ico_reader.decode(&data).unwrap().entries.remove(0).image;
+        assert!(image.pixels.iter().flatten().zip(icon(8).pixels.iter().flatten()).all(|(a, b)| a.a == if b.a == 0 { 0 } else { 255 }));
+    }
+
+    #[test]
+    fn tiff_write_read() {
+        let (width, height) = (45, 30);
+        let pixels = (0..height).map(|y| (0..width).map(|x| common::Color::new((x * 5) as u8, 200 - (y * 6) as u8, if x < 20 { 40 } else { 41 }, (x * y) as u8)).collect()).collect();
+        let image = common::Image::from_mat(width, height, pixels);
+        let tiff_reader = tiff_reader::TIFFReader {};
+
+        let metadata = tiff::Metadata {
+            resolution: Some((300.0, 118.11)),
+            resolution_unit: tiff::ResolutionUnit::Centimeter,
+            software: Some("pixel_renderer".to_string()),
+            date_time: Some("2024:05:01 12:30:00".to_string()),
+        };
+
+        for byte_order in [tiff::ByteOrder::LittleEndian, tiff::ByteOrder::BigEndian] {
+            for compression in [tiff::Compression::None, tiff::Compression::PackBits, tiff::Compression::LZW, tiff::Compression::Deflate] {
+                for (bit_depth, predictor) in [(8, false), (8, true), (16, true)] {
+                    for pixel_format in [tiff_writer::PixelFormat::Grayscale, tiff_writer::PixelFormat::RGB, tiff_writer::PixelFormat::RGBA] {
+                        let settings = tiff_writer::Settings {
+                            byte_order, pixel_format, bit_depth, compression, predictor,
+                            rows_per_strip: Some(7),
+                            metadata: metadata.clone(),
+                            ..Default::default()
+                        };
+                        let data = tiff_writer::TIFFWriter { settings }.encode(&image).unwrap();
+                        let (decoded, decoded_metadata) = tiff_reader.decode(&data).unwrap();
+
+                        assert_eq!(decoded_metadata, metadata);
+                        for (a, b) in decoded.pixels.iter().flatten().zip(image.pixels.iter().flatten()) {
+                            match pixel_format {
+                                tiff_writer::PixelFormat::RGBA => assert_eq!(a, b),
+                                tiff_writer::PixelFormat::RGB => assert_eq!(*a, common::Color::from_rgb(b.r, b.g, b.b)),
+                                _ => assert_eq!(a.r, b.luminance(Default::default())),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Long runs of repeated strings fill the LZW table
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i / 7 % 13 + i / 1000) as u8).collect();
+        assert!(tiff::lzw_decode(&tiff::lzw_encode(&data), data.len()).unwrap() == data);
+        assert!(tiff::packbits_decode(&tiff::packbits_encode(&data), data.len()).unwrap() == data);
+
+        // Files written by libtiff, LZW with the horizontal predictor and big endian LZW with table resets
+        let ppm_reader = ppm_reader::PPMReader {};
+        let image = tiff_reader.read("resources/tiff_lzw.tiff").unwrap();
+        assert!(image.pixels == ppm_reader.read("resources/tiff_lzw.ppm").unwrap().pixels);
+        let image = tiff_reader.read("resources/tiff_lzw_gray.tiff").unwrap();
+        assert!(image.pixels == pgm_reader::PGMReader {}.read("resources/tiff_lzw_gray.pgm").unwrap().pixels);
+
+        // BitsPerSample without values
+        let mut data = tiff_writer::TIFFWriter { settings: Default::default() }.encode(&image).unwrap();
+        let ifd = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let entry = (0..u16::from_le_bytes([data[ifd], data[ifd + 1]]) as usize).map(|i| ifd + 2 + i * 12)
+            .find(|&entry| data[entry..entry + 2] == tiff::BITS_PER_SAMPLE.to_le_bytes()).unwrap();
+        data[entry + 4..entry + 8].fill(0);
+        assert_eq!(tiff_reader.decode(&data).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+
+        // Zero width makes every size 0, it must not pass as an image of empty rows
+        let mut data = tiff_writer::TIFFWriter { settings: Default::default() }.encode(&image).unwrap();
+        let entry = (0..u16::from_le_bytes([data[ifd], data[ifd + 1]]) as usize).map(|i| ifd + 2 + i * 12)
+            .find(|&entry| data[entry..entry + 2] == tiff::IMAGE_WIDTH.to_le_bytes()).unwrap();
+        data[entry + 8..entry + 12].fill(0);
+        assert_eq!(tiff_reader.decode(&data).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+
+        let expected = ppm_reader.read("resources/python.ppm").unwrap();
+        let image = tiff_reader.read("resources/python.tiff").unwrap();
+        assert!(image.pixels.iter().flatten().zip(expected.pixels.iter().flatten()).all(|(a, b)| (a.r, a.g, a.b) == (b.r, b.g, b.b)));
+    }
+
+    #[test]
+    fn webp_read() {
+        let webp_reader = webp_reader::WebPReader {};
+        assert_eq!(webp_reader.read("resources/python.webp").err().unwrap().kind(), std::io::ErrorKind::Unsupported);
+
+        // The alpha of the lossy file is a VP8L stream without its header
+        let data = std::fs::read("resources/python.webp").unwrap();
+        let alpha = webp::chunks(&data).unwrap().into_iter().find(|chunk| &chunk.id == webp::ALPH).unwrap().data;
+        assert_eq!(alpha[0] & 0b11, 1);
+        let mut vp8l = vec![webp::VP8L_SIGNATURE];
+        vp8l.extend_from_slice(&(15u32 | 15 << 14).to_le_bytes());
+        vp8l.extend_from_slice(&alpha[1..]);
+
+        let mut file = webp::RIFF_MAGIC.to_vec();
+        file.extend_from_slice(&(4 + 8 + vp8l.len() as u32).to_le_bytes());
+        file.extend_from_slice(webp::WEBP_MAGIC);
+        file.extend_from_slice(webp::VP8L);
+        file.extend_from_slice(&(vp8l.len() as u32).to_le_bytes());
+        file.extend_from_slice(&vp8l);
+
+        let image = webp_reader.decode(&file).unwrap();
+        let expected = png_reader::PNGReader {}.read("resources/python.png").unwrap();
+        assert_eq!((image.width(), image.height()), (16, 16));
+        assert!(image.pixels.iter().flatten().zip(expected.pixels.iter().flatten()).all(|(a, b)| a.g == b.a));
+
+        let image = webp_reader.read("resources/gradient.webp").unwrap();
+        let expected = pam_reader::PAMReader {}.read("resources/gradient.pam").unwrap();
+        assert_eq!((image.width(), image.height()), (128, 96));
+        assert!(image.pixels.iter().flatten().zip(expected.pixels.iter().flatten()).all(|(a, b)| a == b));
+    }
+
+    #[test]
+    fn dds_read() {
+        use binary_serializable::BinarySerializable;
+
+        let dds_reader = dds_reader::DDSReader {};
+        let pam_reader = pam_reader::PAMReader {};
+        let close = |a: u8, b: u8| a.abs_diff(b) <= 2;
+
+        // Reference images were decoded by another BCn implementation, which rounds slightly differently
+        for name in ["texture_bc1", "texture_bc2", "texture_bc3"] {
+            let image = dds_reader.read(&format!("resources/{}.dds", name)).unwrap();
+            let expected = pam_reader.read(&format!("resources/{}.pam", name)).unwrap();
+            assert_eq!((image.width(), image.height()), (37, 23));
+            assert!(image.pixels.iter().flatten().zip(expected.pixels.iter().flatten())
+                .all(|(a, b)| close(a.r, b.r) && close(a.g, b.g) && close(a.b, b.b) && (name == "texture_bc1" || close(a.a, b.a))));
+        }
+
+        // Punch-through alpha, blocks with color0 <= color1 make index 3 transparent black
+        let image = dds_reader.read("resources/texture_bc1_alpha.dds").unwrap();
+        let expected = pam_reader.read("resources/texture_bc1_alpha.pam").unwrap();
+        assert!(image.pixels.iter().flatten().any(|color| color.a == 0));
+        assert!(image.pixels.iter().flatten().zip(expected.pixels.iter().flatten())
+            .all(|(a, b)| close(a.r, b.r) && close(a.g, b.g) && close(a.b, b.b) && a.a == b.a));
+
+        let texture = dds_reader.read_dds("resources/texture_bc3.dds").unwrap();
+        assert_eq!(texture.format, dds::Format::BC3);
+        let sizes: Vec<_> = texture.layers[0].mips.iter().map(|mip| (mip.width(), mip.height())).collect();
+        assert_eq!(sizes, [(37, 23), (18, 11), (9, 5), (4, 2), (2, 1), (1, 1)]);
+
+        // Legacy BGRA cubemap with two mip levels and the negative Y face left out
+        let faces: Vec<_> = dds::CubeFace::ALL.into_iter().filter(|&face| face != dds::CubeFace::NegativeY).collect();
+        let mut data = dds::MAGIC.to_vec();
+        dds::Header {
+            flags: dds::DDSD_CAPS | dds::DDSD_HEIGHT | dds::DDSD_WIDTH | dds::DDSD_PIXEL_FORMAT | dds::DDSD_MIP_MAP_COUNT,
+            width: 4,
+            height: 4,
+            mip_map_count: 2,
+            pixel_format: dds::PixelFormat {
+                flags: dds::DDPF_RGB | dds::DDPF_ALPHA_PIXELS,
+                rgb_bit_count: 32,
+                masks: [0xFF0000, 0xFF00, 0xFF, 0xFF000000],
+                ..Default::default()
+            },
+            caps: dds::DDSCAPS_TEXTURE | dds::DDSCAPS_COMPLEX | dds::DDSCAPS_MIP_MAP,
+            caps2: faces.iter().fold(dds::DDSCAPS2_CUBEMAP, |caps2, face| caps2 | face.caps2()),
+            ..Default::default()
+        }.write(&mut data).unwrap();
+        for (index, _) in faces.iter().enumerate() {
+            for level in 0..2u8 {
+                for _ in 0..(16 >> (level * 2)) {
+                    data.extend_from_slice(&[level, index as u8 * 40, 200, 128]);
+                }
+            }
+        }
+        let cubemap = dds_reader.decode(&data).unwrap();
+        assert!(cubemap.is_cubemap());
+        assert_eq!(cubemap.layers.len(), 5);
+        assert!(cubemap.face(dds::CubeFace::NegativeY).is_none());
+        let face = cubemap.face(dds::CubeFace::PositiveZ).unwrap();
+        assert_eq!((face.mips[1].width(), face.mips[1].height()), (2, 2));
+        assert!(face.mips[1].pixels.iter().flatten().all(|&color| color == common::Color::new(200, 120, 1, 128)));
+
+        // DX10 BC4 blocks and signed BC5 blocks
+        let block_data = |dxgi_format: u32, block: &[u8]| {
+            let mut data = dds::MAGIC.to_vec();
+            dds::Header {
+                width: 4,
+                height: 4,
+                pixel_format: dds::PixelFormat { flags: dds::DDPF_FOUR_CC, four_cc: *b"DX10", ..Default::default() },
+                ..Default::default()
+            }.write(&mut data).unwrap();
+            dds::HeaderDX10 { dxgi_format, resource_dimension: 3, array_size: 1, ..Default::default() }.write(&mut data).unwrap();
+            data.extend_from_slice(block);
+            data
+        };
+        let block_file = |dxgi_format: u32, block: &[u8]| dds_reader.decode(&block_data(dxgi_format, block)).unwrap().layers.remove(0).mips.remove(0);
+        // Indices 0, 1, 2 and 7 along the first row, the rest 0
+        let channel = [200, 60, 0b10_001_000, 0b0000_1110, 0, 0, 0, 0];
+        let image = block_file(dds::DXGI_FORMAT_BC4_UNORM, &channel);
+        assert_eq!(image.pixels[0].iter().map(|color| color.r).collect::<Vec<_>>(), [200, 60, 180, 80]);
+        assert!(image.pixels[0].iter().all(|color| color.is_gray()));
+
+        // Endpoints in increasing order select the six value mode with the -1 and 1 extremes
+        let (decreasing, increasing) = ([127, 129, 0b10_001_000, 0b0000_1110, 0, 0, 0, 0], [129, 127, 0b10_001_000, 0b0000_1110, 0, 0, 0, 0]);
+        let image = block_file(dds::DXGI_FORMAT_BC5_SNORM, &[decreasing, increasing].concat());
+        assert_eq!(image.pixels[0].iter().map(|color| (color.r, color.g, color.b)).collect::<Vec<_>>(), [(255, 0, 0), (0, 255, 0), (218, 51, 0), (36, 255, 0)]);
+
+        // An array size far beyond the data is rejected before the layers are listed
+        let mut data = block_data(dds::DXGI_FORMAT_BC4_UNORM, &channel);
+        let array_size = dds::MAGIC.len() + dds::Header::SIZE as usize + 12;
+        data[array_size..array_size + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(dds_reader.decode(&data).err().unwrap().kind(), std::io::ErrorKind::UnexpectedEof);
+    }
 }
@@ -0,0 +1,300 @@
+use std::io::{self, BufRead, Read};
+
+use crate::common::Image;
+
+/// Sample encoding of the Netpbm formats
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Encoding {
+    /// Plain format, samples as decimal text
+    #[default]
+    ASCII,
+    /// Raw format, samples as bytes
+    Binary,
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn unexpected_eof() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "Not enough data")
+}
+
+/// Splits Netpbm headers and plain samples into tokens, skipping whitespace and comments
+pub(crate) struct Tokenizer<'a> {
+    reader: Box<dyn BufRead + 'a>,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new<R: BufRead + 'a>(reader: R) -> Self {
+        Tokenizer { reader: Box::new(reader) }
+    }
+
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.reader.fill_buf()?.first().copied())
+    }
+
+    pub fn is_empty(&mut self) -> io::Result<bool> {
+        self.skip_whitespace()?;
+        Ok(self.peek()?.is_none())
+    }
+
+    /// Comments run from `#` to the end of the line
+    fn skip_whitespace(&mut self) -> io::Result<()> {
+        while let Some(byte) = self.peek()? {
+            match byte {
+                b'#' => {
+                    while self.peek()?.is_some_and(|byte| byte != b'\n' && byte != b'\r') {
+                        self.reader.consume(1);
+                    }
+                },
+                _ if byte.is_ascii_whitespace() => self.reader.consume(1),
+                _ => break,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn token(&mut self) -> io::Result<Vec<u8>> {
+        self.skip_whitespace()?;
+        let mut token = Vec::new();
+        while let Some(byte) = self.peek()?.filter(|&byte| !byte.is_ascii_whitespace() && byte != b'#') {
+            token.push(byte);
+            self.reader.consume(1);
+        }
+
+        if token.is_empty() {
+            return Err(unexpected_eof());
+        }
+        Ok(token)
+    }
+
+    pub fn number(&mut self) -> io::Result<usize> {
+        let token = self.token()?;
+        if !token.iter().all(u8::is_ascii_digit) {
+            return Err(invalid_data("Invalid number"));
+        }
+        std::str::from_utf8(&token).unwrap().parse().map_err(|_| invalid_data("Number is too large"))
+    }
+
+    /// Two byte magic number at the start of an image
+    pub fn magic(&mut self) -> io::Result<[u8; 2]> {
+        self.skip_whitespace()?;
+        let magic = self.bytes(2)?;
+        if magic[0] != b'P' {
+            return Err(invalid_data("Invalid magic number"));
+        }
+        Ok([magic[0], magic[1]])
+    }
+
+    /// Plain bitmap samples are single digits and need no whitespace between them
+    pub fn bit(&mut self) -> io::Result<bool> {
+        self.skip_whitespace()?;
+        let bit = match self.peek()? {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            Some(_) => return Err(invalid_data("Invalid bitmap sample")),
+            None => return Err(unexpected_eof()),
+        };
+        self.reader.consume(1);
+        Ok(bit)
+    }
+
+    /// Rest of the current line, without the line break
+    pub fn line(&mut self) -> io::Result<Vec<u8>> {
+        let mut line = Vec::new();
+        self.reader.read_until(b'\n', &mut line)?;
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        Ok(line)
+    }
+
+    /// The header ends with a single whitespace character before the raw samples, a comment may come first
+    pub fn end_header(&mut self) -> io::Result<()> {
+        if self.peek()? == Some(b'#') {
+            while self.peek()?.is_some_and(|byte| byte != b'\n' && byte != b'\r') {
+                self.reader.consume(1);
+            }
+        }
+        match self.peek()? {
+            Some(byte) if byte.is_ascii_whitespace() => {
+                self.reader.consume(1);
+                Ok(())
+            },
+            Some(_) => Err(invalid_data("Missing whitespace after header")),
+            None => Err(unexpected_eof()),
+        }
+    }
+
+    /// The buffer grows with the data read, so a bad size in a header only fails at the end of the input
+    pub fn bytes(&mut self, count: usize) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        (&mut self.reader).take(count as u64).read_to_end(&mut bytes)?;
+        if bytes.len() < count {
+            return Err(unexpected_eof());
+        }
+        Ok(bytes)
+    }
+}
+
+/// Images concatenated in one stream, read one at a time, decoding stops at the first error
+pub struct Images<'a> {
+    tokenizer: Tokenizer<'a>,
+    decode: fn(&mut Tokenizer) -> io::Result<Image>,
+    failed: bool,
+}
+
+impl<'a> Images<'a> {
+    pub(crate) fn new<R: BufRead + 'a>(stream: R, decode: fn(&mut Tokenizer) -> io::Result<Image>) -> Images<'a> {
+        Images { tokenizer: Tokenizer::new(stream), decode, failed: false }
+    }
+}
+
+impl Iterator for Images<'_> {
+    type Item = io::Result<Image>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let image = match self.tokenizer.is_empty() {
+            Ok(true) => return None,
+            Ok(false) => (self.decode)(&mut self.tokenizer),
+            Err(error) => Err(error),
+        };
+        self.failed = image.is_err();
+        Some(image)
+    }
+}
+
+pub(crate) struct Header {
+    pub magic: String,
+    pub width: usize,
+    pub height: usize,
+    /// 1 for bitmaps, which have no max value
+    pub max_value: u16,
+}
+
+/// Reads the magic number, size and optional max value up to the raster
+pub(crate) fn read_header(tokenizer: &mut Tokenizer, has_max_value: bool) -> io::Result<Header> {
+    let magic = String::from_utf8_lossy(&tokenizer.magic()?).to_string();
+    let (width, height) = (tokenizer.number()?, tokenizer.number()?);
+    if width == 0 || height == 0 {
+        return Err(invalid_data("Invalid size"));
+    }
+
+    let max_value = if has_max_value { tokenizer.number()? } else { 1 };
+    if max_value == 0 || max_value > u16::MAX as usize {
+        return Err(invalid_data("Invalid max value"));
+    }
+
+    tokenizer.end_header()?;
+
+    Ok(Header { magic, width, height, max_value: max_value as u16 })
+}
+
+/// Number of samples in an image, sizes that overflow are invalid
+pub(crate) fn sample_count(width: usize, height: usize, channels: usize) -> io::Result<usize> {
+    width.checked_mul(height).and_then(|count| count.checked_mul(channels)).ok_or_else(|| invalid_data("Image is too large"))
+}
+
+/// Reads samples scaled to 8 bits, raw samples take two big endian bytes when the max value is above 255
+pub(crate) fn read_samples(tokenizer: &mut Tokenizer, encoding: Encoding, count: usize, max_value: u16) -> io::Result<Vec<u8>> {
+    let scale = |sample: u16| scale_sample(sample, max_value);
+
+    match encoding {
+        Encoding::ASCII => (0..count)
+            .map(|_| match tokenizer.number()? {
+                sample if sample <= max_value as usize => Ok(scale(sample as u16)),
+                _ => Err(invalid_data("Sample is larger than max value")),
+            })
+            .collect(),
+        Encoding::Binary if max_value > u8::MAX as u16 => Ok(tokenizer.bytes(sample_count(count, 2, 1)?)?
+            .chunks_exact(2)
+            .map(|sample| scale(u16::from_be_bytes([sample[0], sample[1]])))
+            .collect()),
+        Encoding::Binary => Ok(tokenizer.bytes(count)?.iter().map(|&sample| scale(sample as u16)).collect()),
+    }
+}
+
+pub(crate) fn header(magic: &str, width: usize, height: usize, max_value: Option<usize>) -> String {
+    match max_value {
+        Some(max_value) => format!("{}\n{} {}\n{}\n", magic, width, height, max_value),
+        None => format!("{}\n{} {}\n", magic, width, height),
+    }
+}
+
+/// Writes samples as decimal text, `per_line` samples per line
+pub(crate) fn write_ascii_samples(data: &mut Vec<u8>, samples: &[u8], per_line: usize) {
+    for line in samples.chunks(per_line.max(1)) {
+        let line = line.iter().map(u8::to_string).collect::<Vec<String>>().join(" ");
+        data.extend_from_slice(line.as_bytes());
+        data.push(b'\n');
+    }
+}
+
+/// Tuple types of the PAM format
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TupleType {
+    BlackAndWhite,
+    Grayscale,
+    RGB,
+    BlackAndWhiteAlpha,
+    GrayscaleAlpha,
+    #[default]
+    RGBAlpha,
+}
+
+impl TupleType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TupleType::BlackAndWhite => "BLACKANDWHITE",
+            TupleType::Grayscale => "GRAYSCALE",
+            TupleType::RGB => "RGB",
+            TupleType::BlackAndWhiteAlpha => "BLACKANDWHITE_ALPHA",
+            TupleType::GrayscaleAlpha => "GRAYSCALE_ALPHA",
+            TupleType::RGBAlpha => "RGB_ALPHA",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<TupleType> {
+        [TupleType::BlackAndWhite, TupleType::Grayscale, TupleType::RGB,
+            TupleType::BlackAndWhiteAlpha, TupleType::GrayscaleAlpha, TupleType::RGBAlpha]
+            .into_iter()
+            .find(|tuple_type| tuple_type.name() == name)
+    }
+
+    /// Tuple type of the standard layout with this many samples per pixel
+    pub fn from_depth(depth: usize) -> Option<TupleType> {
+        match depth {
+            1 => Some(TupleType::Grayscale),
+            2 => Some(TupleType::GrayscaleAlpha),
+            3 => Some(TupleType::RGB),
+            4 => Some(TupleType::RGBAlpha),
+            _ => None,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        match self {
+            TupleType::BlackAndWhite | TupleType::Grayscale => 1,
+            TupleType::BlackAndWhiteAlpha | TupleType::GrayscaleAlpha => 2,
+            TupleType::RGB => 3,
+            TupleType::RGBAlpha => 4,
+        }
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        matches!(self, TupleType::BlackAndWhiteAlpha | TupleType::GrayscaleAlpha | TupleType::RGBAlpha)
+    }
+}
+
+/// Scales a sample in 0..=max_value to 0..=255
+pub(crate) fn scale_sample(value: u16, max_value: u16) -> u8 {
+    if max_value == u8::MAX as u16 {
+        return value.min(max_value) as u8;
+    }
+    ((value.min(max_value) as u32 * 255 + max_value as u32 / 2) / max_value as u32) as u8
+}
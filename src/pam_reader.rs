@@ -0,0 +1,115 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+use crate::common::*;
+use crate::netpbm::{self, Encoding, TupleType, Tokenizer};
+
+pub use crate::netpbm::Images;
+
+pub struct PAMReader {
+
+}
+
+struct PAMHeader {
+    width: usize,
+    height: usize,
+    depth: usize,
+    max_value: u16,
+    tuple_type: Option<String>,
+}
+
+impl PAMReader {
+    /// Reads the header up to ENDHDR
+    fn read_header(&self, tokenizer: &mut Tokenizer) -> io::Result<PAMHeader> {
+        if &tokenizer.magic()? != b"P7" {
+            return Err(netpbm::invalid_data("Invalid PAM file"));
+        }
+
+        let (mut width, mut height, mut depth, mut max_value) = (None, None, None, None);
+        let mut tuple_type: Option<String> = None;
+
+        loop {
+            match tokenizer.token()?.as_slice() {
+                b"WIDTH" => width = Some(tokenizer.number()?),
+                b"HEIGHT" => height = Some(tokenizer.number()?),
+                b"DEPTH" => depth = Some(tokenizer.number()?),
+                b"MAXVAL" => max_value = Some(tokenizer.number()?),
+                // Multiple TUPLTYPE lines are concatenated
+                b"TUPLTYPE" => {
+                    let value = String::from_utf8_lossy(&tokenizer.line()?).trim().to_string();
+                    tuple_type = Some(match tuple_type {
+                        Some(tuple_type) => tuple_type + " " + &value,
+                        None => value,
+                    });
+                },
+                b"ENDHDR" => {
+                    tokenizer.line()?;
+                    break;
+                },
+                _ => return Err(netpbm::invalid_data("Unknown PAM header line")),
+            }
+        }
+
+        let missing = || netpbm::invalid_data("Missing PAM header value");
+        let max_value = max_value.ok_or_else(missing)?;
+        if max_value == 0 || max_value > u16::MAX as usize {
+            return Err(netpbm::invalid_data("Invalid PAM max value"));
+        }
+
+        let (width, height) = (width.ok_or_else(missing)?, height.ok_or_else(missing)?);
+        if width == 0 || height == 0 {
+            return Err(netpbm::invalid_data("Invalid PAM size"));
+        }
+
+        Ok(PAMHeader {
+            width,
+            height,
+            depth: depth.ok_or_else(missing)?,
+            max_value: max_value as u16,
+            tuple_type,
+        })
+    }
+
+    fn decode(&self, tokenizer: &mut Tokenizer) -> io::Result<Image> {
+        let header = self.read_header(tokenizer)?;
+
+        // Unknown tuple types are read by their depth
+        let tuple_type = header.tuple_type.as_deref()
+            .and_then(TupleType::from_name)
+            .or_else(|| TupleType::from_depth(header.depth))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "Unsupported PAM tuple type"))?;
+        if tuple_type.depth() != header.depth {
+            return Err(netpbm::invalid_data("PAM depth does not match tuple type"));
+        }
+
+        let (width, height) = (header.width, header.height);
+        let samples = netpbm::read_samples(tokenizer, Encoding::Binary, netpbm::sample_count(width, height, header.depth)?, header.max_value)?;
+
+        let colors: Vec<Color> = samples.chunks_exact(header.depth)
+            .map(|tuple| match *tuple {
+                [gray] => Color::from_rgb(gray, gray, gray),
+                [gray, alpha] => Color::new(gray, gray, gray, alpha),
+                [r, g, b] => Color::from_rgb(r, g, b),
+                [r, g, b, a] => Color::new(r, g, b, a),
+                _ => unreachable!(),
+            })
+            .collect();
+
+        let pixels = colors.chunks(width.max(1)).map(<[Color]>::to_vec).collect();
+
+        Ok(Image::from_mat(width, height, pixels))
+    }
+
+    /// Reads all images concatenated in the stream
+    pub fn images<'a, R: BufRead + 'a>(&self, stream: R) -> Images<'a> {
+        Images::new(stream, |tokenizer| PAMReader {}.decode(tokenizer))
+    }
+}
+
+impl Reader for PAMReader {
+    fn read(&self, path: &str) -> io::Result<Image> {
+        println!("Reading PAM file");
+
+        self.decode(&mut Tokenizer::new(BufReader::new(File::open(path)?)))
+    }
+}
@@ -0,0 +1,98 @@
+use std::fs;
+use std::io::{self, Write};
+
+use crate::common::*;
+
+pub use crate::netpbm::TupleType;
+
+pub struct Settings {
+    pub tuple_type: TupleType,
+    /// 8 or 16, black and white tuples always use a max value of 1
+    pub bit_depth: u8,
+    /// Conversion of color pixels to gray levels
+    pub luminance: Luminance,
+    /// Gray levels below the threshold are written as black in black and white tuples
+    pub threshold: u8,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            tuple_type: TupleType::default(),
+            bit_depth: 8,
+            luminance: Luminance::default(),
+            threshold: 128,
+        }
+    }
+}
+
+pub struct PAMWriter {
+    pub settings: Settings
+}
+
+impl PAMWriter {
+    fn max_value(&self) -> u16 {
+        match self.settings.tuple_type {
+            TupleType::BlackAndWhite | TupleType::BlackAndWhiteAlpha => 1,
+            _ if self.settings.bit_depth == 16 => u16::MAX,
+            _ => u8::MAX as u16,
+        }
+    }
+
+    fn tuple(&self, color: &Color) -> Vec<u16> {
+        let scale = |sample: u8| if self.settings.bit_depth == 16 { sample as u16 * 257 } else { sample as u16 };
+        let gray = color.luminance(self.settings.luminance);
+        let black_and_white = |sample: u8| (sample >= self.settings.threshold) as u16;
+
+        match self.settings.tuple_type {
+            TupleType::BlackAndWhite => vec![black_and_white(gray)],
+            TupleType::Grayscale => vec![scale(gray)],
+            TupleType::RGB => vec![scale(color.r), scale(color.g), scale(color.b)],
+            TupleType::BlackAndWhiteAlpha => vec![black_and_white(gray), black_and_white(color.a)],
+            TupleType::GrayscaleAlpha => vec![scale(gray), scale(color.a)],
+            TupleType::RGBAlpha => vec![scale(color.r), scale(color.g), scale(color.b), scale(color.a)],
+        }
+    }
+
+    pub fn encode(&self, image: &Image) -> Vec<u8> {
+        let tuple_type = self.settings.tuple_type;
+        let max_value = self.max_value();
+
+        let header = format!(
+            "P7\nWIDTH {}\nHEIGHT {}\nDEPTH {}\nMAXVAL {}\nTUPLTYPE {}\nENDHDR\n",
+            image.width(), image.height(), tuple_type.depth(), max_value, tuple_type.name()
+        );
+        let sample_size = if max_value > u8::MAX as u16 { 2 } else { 1 };
+        let mut data = Vec::with_capacity(header.len() + image.width() * image.height() * tuple_type.depth() * sample_size);
+        data.extend_from_slice(header.as_bytes());
+
+        for color in image.pixels.iter().flatten() {
+            for sample in self.tuple(color) {
+                if sample_size == 2 {
+                    data.extend_from_slice(&sample.to_be_bytes());
+                } else {
+                    data.push(sample as u8);
+                }
+            }
+        }
+
+        data
+    }
+
+    /// Appends the image to a stream of concatenated images
+    pub fn write_frame<W: Write>(&self, image: &Image, stream: &mut W) -> io::Result<()> {
+        stream.write_all(&self.encode(image))
+    }
+}
+
+impl Writer for PAMWriter {
+    fn extension(&self) -> &str {
+        "pam"
+    }
+
+    fn write(&self, image: Image, path: &str) {
+        println!("Writing PAM file at path: {}", path);
+
+        fs::write(path, self.encode(&image)).expect("Can't save output PAM file");
+    }
+}
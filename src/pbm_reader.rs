@@ -0,0 +1,54 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+use crate::common::*;
+use crate::netpbm::{self, Tokenizer};
+
+pub use crate::netpbm::Images;
+
+pub struct PBMReader {
+
+}
+
+impl PBMReader {
+    /// Raw bitmap rows are packed most significant bit first and padded to whole bytes
+    fn read_binary(&self, tokenizer: &mut Tokenizer, width: usize, height: usize) -> io::Result<Vec<bool>> {
+        let row_length = width.div_ceil(8);
+        let data = tokenizer.bytes(netpbm::sample_count(row_length, height, 1)?)?;
+
+        Ok(data.chunks(row_length.max(1))
+            .flat_map(|row| (0..width).map(move |x| row[x / 8] & (0x80 >> (x % 8)) != 0))
+            .collect())
+    }
+
+    fn decode(&self, tokenizer: &mut Tokenizer) -> io::Result<Image> {
+        let header = netpbm::read_header(tokenizer, false)?;
+        let (width, height) = (header.width, header.height);
+
+        let bits = match header.magic.as_str() {
+            "P1" => (0..netpbm::sample_count(width, height, 1)?).map(|_| tokenizer.bit()).collect::<io::Result<Vec<bool>>>()?,
+            "P4" => self.read_binary(tokenizer, width, height)?,
+            _ => return Err(netpbm::invalid_data("Invalid PBM file")),
+        };
+
+        // 1 is black
+        let pixels = bits.chunks(width.max(1))
+            .map(|row| row.iter().map(|&black| if black { Color::black() } else { Color::from_rgb(255, 255, 255) }).collect())
+            .collect();
+
+        Ok(Image::from_mat(width, height, pixels))
+    }
+
+    /// Reads all images concatenated in the stream
+    pub fn images<'a, R: BufRead + 'a>(&self, stream: R) -> Images<'a> {
+        Images::new(stream, |tokenizer| PBMReader {}.decode(tokenizer))
+    }
+}
+
+impl Reader for PBMReader {
+    fn read(&self, path: &str) -> io::Result<Image> {
+        println!("Reading PBM file");
+
+        self.decode(&mut Tokenizer::new(BufReader::new(File::open(path)?)))
+    }
+}
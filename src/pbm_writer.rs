@@ -0,0 +1,86 @@
+use std::fs;
+use std::io::{self, Write};
+
+use crate::common::*;
+use crate::netpbm;
+
+pub use crate::netpbm::Encoding;
+
+pub struct Settings {
+    pub encoding: Encoding,
+    /// Conversion of color pixels to gray levels
+    pub luminance: Luminance,
+    /// Gray levels below the threshold are written as black
+    pub threshold: u8,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            encoding: Encoding::default(),
+            luminance: Luminance::default(),
+            threshold: 128,
+        }
+    }
+}
+
+pub struct PBMWriter {
+    pub settings: Settings
+}
+
+impl PBMWriter {
+    fn write_ascii(&self, data: &mut Vec<u8>, bits: &[Vec<bool>]) {
+        for row in bits.iter() {
+            let line: Vec<u8> = row.iter().map(|&black| if black { b'1' } else { b'0' }).collect();
+            // Plain bitmap lines should not be longer than 70 characters
+            for part in line.chunks(70) {
+                data.extend_from_slice(part);
+                data.push(b'\n');
+            }
+        }
+    }
+
+    fn write_binary(&self, data: &mut Vec<u8>, bits: &[Vec<bool>]) {
+        for row in bits.iter() {
+            for byte in row.chunks(8) {
+                data.push(byte.iter().enumerate().fold(0, |byte, (i, &black)| byte | ((black as u8) << (7 - i))));
+            }
+        }
+    }
+
+    pub fn encode(&self, image: &Image) -> Vec<u8> {
+        let bits: Vec<Vec<bool>> = image.pixels.iter()
+            .map(|row| row.iter().map(|color| color.luminance(self.settings.luminance) < self.settings.threshold).collect())
+            .collect();
+
+        let magic = match self.settings.encoding {
+            Encoding::ASCII => "P1",
+            Encoding::Binary => "P4",
+        };
+        let mut data = netpbm::header(magic, image.width(), image.height(), None).into_bytes();
+
+        match self.settings.encoding {
+            Encoding::ASCII => self.write_ascii(&mut data, &bits),
+            Encoding::Binary => self.write_binary(&mut data, &bits),
+        }
+
+        data
+    }
+
+    /// Appends the image to a stream of concatenated images
+    pub fn write_frame<W: Write>(&self, image: &Image, stream: &mut W) -> io::Result<()> {
+        stream.write_all(&self.encode(image))
+    }
+}
+
+impl Writer for PBMWriter {
+    fn extension(&self) -> &str {
+        "pbm"
+    }
+
+    fn write(&self, image: Image, path: &str) {
+        println!("Writing PBM file at path: {}", path);
+
+        fs::write(path, self.encode(&image)).expect("Can't save output PBM file");
+    }
+}
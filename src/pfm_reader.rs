@@ -0,0 +1,58 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+
+use crate::common::*;
+use crate::netpbm::{self, Tokenizer};
+
+pub struct PFMReader {
+
+}
+
+impl PFMReader {
+    fn decode(&self, tokenizer: &mut Tokenizer) -> io::Result<FloatImage> {
+        let channels = match &tokenizer.magic()? {
+            b"PF" => 3,
+            b"Pf" => 1,
+            _ => return Err(netpbm::invalid_data("Invalid PFM file")),
+        };
+        let (width, height) = (tokenizer.number()?, tokenizer.number()?);
+        if width == 0 || height == 0 {
+            return Err(netpbm::invalid_data("Invalid PFM size"));
+        }
+
+        // Negative scale means little endian, its magnitude scales the samples
+        let scale: f32 = std::str::from_utf8(&tokenizer.token()?).ok()
+            .and_then(|scale| scale.parse().ok())
+            .filter(|scale: &f32| scale.is_finite() && *scale != 0.0)
+            .ok_or_else(|| netpbm::invalid_data("Invalid PFM scale"))?;
+        tokenizer.end_header()?;
+
+        let row_length = netpbm::sample_count(width, channels, 1)?;
+        let data = tokenizer.bytes(netpbm::sample_count(row_length, height, 4)?)?;
+        let samples: Vec<f32> = data.chunks_exact(4)
+            .map(|bytes| {
+                let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+                if scale < 0.0 { f32::from_le_bytes(bytes) } else { f32::from_be_bytes(bytes) }
+            })
+            .map(|sample| sample * scale.abs())
+            .collect();
+
+        // Rows are stored from bottom to top
+        let data = samples.chunks(row_length.max(1)).rev().flatten().copied().collect();
+
+        Ok(FloatImage::from_data(width, height, channels, data))
+    }
+
+    pub fn read_float(&self, path: &str) -> io::Result<FloatImage> {
+        println!("Reading PFM file");
+
+        self.decode(&mut Tokenizer::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl Reader for PFMReader {
+    /// Samples are clamped to 0..=1
+    fn read(&self, path: &str) -> io::Result<Image> {
+        Ok(self.read_float(path)?.to_image())
+    }
+}
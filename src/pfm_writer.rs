@@ -0,0 +1,82 @@
+use std::fs;
+use std::io;
+
+use crate::common::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
+pub struct Settings {
+    pub endianness: Endianness,
+    /// Written in the header, samples are divided by it
+    pub scale: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings { endianness: Endianness::default(), scale: 1.0 }
+    }
+}
+
+pub struct PFMWriter {
+    pub settings: Settings
+}
+
+impl PFMWriter {
+    /// One and two channel images are written as grayscale, alpha is dropped
+    pub fn encode(&self, image: &FloatImage) -> io::Result<Vec<u8>> {
+        let scale = self.settings.scale.abs();
+        if !scale.is_finite() || scale == 0.0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid PFM scale"));
+        }
+
+        let (magic, channels) = match image.channels() {
+            1 | 2 => ("Pf", 1),
+            3 | 4 => ("PF", 3),
+            _ => return Err(io::Error::new(io::ErrorKind::Unsupported, "Unsupported number of channels")),
+        };
+        let signed_scale = match self.settings.endianness {
+            Endianness::Little => -scale,
+            Endianness::Big => scale,
+        };
+
+        let header = format!("{}\n{} {}\n{}\n", magic, image.width(), image.height(), signed_scale);
+        let mut data = Vec::with_capacity(header.len() + image.width() * image.height() * channels * 4);
+        data.extend_from_slice(header.as_bytes());
+
+        // Rows are stored from bottom to top
+        for y in (0..image.height()).rev() {
+            for x in 0..image.width() {
+                for &sample in image.pixel(x, y)[..channels].iter() {
+                    let sample = sample / scale;
+                    data.extend_from_slice(&match self.settings.endianness {
+                        Endianness::Little => sample.to_le_bytes(),
+                        Endianness::Big => sample.to_be_bytes(),
+                    });
+                }
+            }
+        }
+
+        Ok(data)
+    }
+
+    pub fn write_float(&self, image: &FloatImage, path: &str) -> io::Result<()> {
+        println!("Writing PFM file at path: {}", path);
+
+        fs::write(path, self.encode(image)?)
+    }
+}
+
+impl Writer for PFMWriter {
+    fn extension(&self) -> &str {
+        "pfm"
+    }
+
+    fn write(&self, image: Image, path: &str) {
+        self.write_float(&FloatImage::from_image(&image), path).expect("Can't save output PFM file");
+    }
+}
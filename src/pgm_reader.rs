@@ -0,0 +1,44 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+use crate::common::*;
+use crate::netpbm::{self, Encoding, Tokenizer};
+
+pub use crate::netpbm::Images;
+
+pub struct PGMReader {
+
+}
+
+impl PGMReader {
+    fn decode(&self, tokenizer: &mut Tokenizer) -> io::Result<Image> {
+        let header = netpbm::read_header(tokenizer, true)?;
+        let encoding = match header.magic.as_str() {
+            "P2" => Encoding::ASCII,
+            "P5" => Encoding::Binary,
+            _ => return Err(netpbm::invalid_data("Invalid PGM file")),
+        };
+
+        let (width, height) = (header.width, header.height);
+        let samples = netpbm::read_samples(tokenizer, encoding, netpbm::sample_count(width, height, 1)?, header.max_value)?;
+
+        let pixels = samples.chunks(width.max(1))
+            .map(|row| row.iter().map(|&value| Color::from_rgb(value, value, value)).collect())
+            .collect();
+
+        Ok(Image::from_mat(width, height, pixels))
+    }
+
+    /// Reads all images concatenated in the stream
+    pub fn images<'a, R: BufRead + 'a>(&self, stream: R) -> Images<'a> {
+        Images::new(stream, |tokenizer| PGMReader {}.decode(tokenizer))
+    }
+}
+
+impl Reader for PGMReader {
+    fn read(&self, path: &str) -> io::Result<Image> {
+        println!("Reading PGM file");
+
+        self.decode(&mut Tokenizer::new(BufReader::new(File::open(path)?)))
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use std::io::{self, Write};
+
+use crate::common::*;
+use crate::netpbm;
+
+pub use crate::netpbm::Encoding;
+
+#[derive(Default)]
+pub struct Settings {
+    pub encoding: Encoding,
+    /// Conversion of color pixels to gray levels
+    pub luminance: Luminance,
+}
+
+pub struct PGMWriter {
+    pub settings: Settings
+}
+
+impl PGMWriter {
+    pub fn encode(&self, image: &Image) -> Vec<u8> {
+        let samples: Vec<u8> = image.pixels.iter().flatten()
+            .map(|color| color.luminance(self.settings.luminance))
+            .collect();
+
+        let magic = match self.settings.encoding {
+            Encoding::ASCII => "P2",
+            Encoding::Binary => "P5",
+        };
+        let mut data = netpbm::header(magic, image.width(), image.height(), Some(u8::MAX as usize)).into_bytes();
+
+        match self.settings.encoding {
+            Encoding::ASCII => netpbm::write_ascii_samples(&mut data, &samples, image.width()),
+            Encoding::Binary => data.extend_from_slice(&samples),
+        }
+
+        data
+    }
+
+    /// Appends the image to a stream of concatenated images
+    pub fn write_frame<W: Write>(&self, image: &Image, stream: &mut W) -> io::Result<()> {
+        stream.write_all(&self.encode(image))
+    }
+}
+
+impl Writer for PGMWriter {
+    fn extension(&self) -> &str {
+        "pgm"
+    }
+
+    fn write(&self, image: Image, path: &str) {
+        println!("Writing PGM file at path: {}", path);
+
+        fs::write(path, self.encode(&image)).expect("Can't save output PGM file");
+    }
+}
@@ -1,17 +1,29 @@
 pub mod ihdr;
 pub mod idat;
 pub mod plte;
+pub mod trns;
+pub mod bkgd;
 
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
+use std::io::{self, BufReader, BufWriter, Read, Write};
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use crc::{Crc, CRC_32_ISO_HDLC};
 
 use crate::binary_serializable::*;
+use crate::common::Color;
+use crate::png_reader::scale_sample;
 use crate::read_to_string_exact::ReadToStringExact;
+use bkgd::BKGD;
+use ihdr::IHDR;
+use plte::PLTE;
 
-const MAGIC: [u8; 8] = [0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a];
+pub const MAGIC: [u8; 8] = [0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a];
 
+/// Chunk lengths are limited to 2^31 - 1 bytes
+pub const MAX_CHUNK_LENGTH: u32 = 0x7FFF_FFFF;
+
+#[derive(Clone, Default)]
 pub struct PNG {
     pub chunks: Vec<Chunk>,
 }
@@ -23,48 +35,151 @@ impl PNG {
         }
     }
 
-    pub fn from_file(path: &str) -> PNG {
-        let mut png = PNG::new();
-
-        let file = File::open(path).expect("Unable to read png file");
+    pub fn from_file(path: &str) -> io::Result<PNG> {
+        let file = File::open(path)?;
         let mut reader = BufReader::new(file);
 
-        let _magic = reader.read_to_string_lossy_exact(8).expect("Can't read file magic");
+        PNG::read_from(&mut reader)
+    }
 
-        loop {
-            match Chunk::read(&mut reader) {
-                Ok(chunk) => {
-                    let end = chunk.chunk_type == ChunkType::IEND;
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<PNG> {
+        PNG::read_from(&mut io::Cursor::new(bytes))
+    }
 
-                    println!("Chunk: {:?}", chunk);
-                    png.chunks.push(chunk);
+    /// Chunks up to IEND, a chunk that can't be read ends the file early
+    fn read_from<R: Read>(reader: &mut R) -> io::Result<PNG> {
+        let mut png = PNG::new();
 
-                    if end { break; }
-                }
-                Err(e) => {
-                    println!("Can't read chunk: {:?}", e);
-                    break;
-                }
-            }
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid PNG signature"));
         }
-        
-        png
+
+        while let Ok(chunk) = Chunk::read(reader) {
+            let end = chunk.chunk_type == ChunkType::IEND;
+            png.chunks.push(chunk);
+
+            if end { break; }
+        }
+
+        Ok(png)
     }
 
     pub fn to_file(&self, path: &str) {
         let file = File::create(path).expect("Unable to read png file");
         let mut writer = BufWriter::new(file);
 
-        let _ = writer.write(&MAGIC);
+        self.write_to(&mut writer).expect("Can't write chunk");
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.size());
+        self.write_to(&mut bytes).expect("Can't write chunk");
+        bytes
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
 
         for chunk in self.chunks.iter() {
-            chunk.write(&mut writer).expect("Can't write chunk");
+            chunk.write(writer)?;
         }
+
+        Ok(())
+    }
+
+    /// Size of the encoded file in bytes
+    pub fn size(&self) -> usize {
+        MAGIC.len() + self.chunks.iter().map(|chunk| 12 + chunk.data.len()).sum::<usize>()
+    }
+
+    pub fn chunk(&self, chunk_type: &ChunkType) -> Option<&Chunk> {
+        self.chunks.iter().find(|chunk| chunk.chunk_type == *chunk_type)
+    }
+
+    pub fn ihdr(&self) -> io::Result<IHDR> {
+        let chunk = self.chunk(&ChunkType::IHDR)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing IHDR chunk"))?;
+        IHDR::read(&mut io::Cursor::new(&chunk.data))
     }
+
+    /// Background color from the bKGD chunk
+    pub fn background(&self) -> io::Result<Option<Color>> {
+        let Some(chunk) = self.chunks.iter().find(|chunk| chunk.chunk_type.is("bKGD")) else {
+            return Ok(None);
+        };
+
+        let ihdr = self.ihdr()?;
+        ihdr.check_bit_depth()?;
+        let scale = |sample: u16| scale_sample(sample, ihdr.bit_depth);
+
+        let color = match BKGD::from_data(&chunk.data, ihdr.color_type)? {
+            BKGD::Gray(gray) => Color::from_rgb(scale(gray), scale(gray), scale(gray)),
+            BKGD::RGB(r, g, b) => Color::from_rgb(scale(r), scale(g), scale(b)),
+            BKGD::Palette(index) => self.chunk(&ChunkType::PLTE)
+                .and_then(|chunk| PLTE::from_data(&chunk.data).colors.get(index as usize).copied())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "bKGD palette index out of range"))?,
+        };
+
+        Ok(Some(color))
+    }
+}
+
+/// Chunks of a possibly damaged file
+pub(crate) struct ChunkScan {
+    pub chunks: Vec<Chunk>,
+    /// Chunk the file ends in, holding whatever data is present and a zero CRC
+    pub partial: Option<Chunk>,
+    /// The file ends inside a chunk
+    pub truncated: bool,
+    /// Bytes after IEND
+    pub trailing: usize,
+}
+
+/// Splits the bytes following the signature into chunks without failing on damage
+pub(crate) fn scan_chunks(bytes: &[u8]) -> ChunkScan {
+    let mut scan = ChunkScan { chunks: Vec::new(), partial: None, truncated: false, trailing: 0 };
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        if bytes.len() - offset < 8 {
+            scan.truncated = true;
+            break;
+        }
+
+        let length = u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let chunk_type = ChunkType::from_name(&String::from_utf8_lossy(&bytes[offset + 4..offset + 8]));
+        let data_start = offset + 8;
+        let data_end = data_start.saturating_add(length as usize);
+
+        if data_end.saturating_add(4) > bytes.len() {
+            let data = bytes[data_start..data_end.min(bytes.len())].to_vec();
+            scan.partial = Some(Chunk { length, chunk_type, data, crc: 0 });
+            scan.truncated = true;
+            break;
+        }
+
+        let end = chunk_type == ChunkType::IEND;
+        scan.chunks.push(Chunk {
+            length,
+            chunk_type,
+            data: bytes[data_start..data_end].to_vec(),
+            crc: u32::from_be_bytes(bytes[data_end..data_end + 4].try_into().unwrap()),
+        });
+        offset = data_end + 4;
+
+        if end {
+            scan.trailing = bytes.len() - offset;
+            break;
+        }
+    }
+
+    scan
 }
 
 pub fn paeth_predictor(a: u8, b: u8, c: u8) -> u8 {
-    let p = (a as i16 + b as i16 - c as i16) as i16;
+    let p = a as i16 + b as i16 - c as i16;
     let pa = (p - a as i16).abs();
     let pb = (p - b as i16).abs();
     let pc = (p - c as i16).abs();
@@ -73,6 +188,17 @@ pub fn paeth_predictor(a: u8, b: u8, c: u8) -> u8 {
     else { c }
 }
 
+pub fn crc(chunk_type: &[u8], chunk_data: &[u8]) -> u32 {
+    let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+    let mut digest = crc.digest();
+    
+    digest.update(chunk_type);
+    digest.update(chunk_data);
+    
+    digest.finalize()
+}
+
+#[derive(Clone)]
 pub struct Chunk {
     pub length: u32,
     pub chunk_type: ChunkType,
@@ -80,6 +206,19 @@ pub struct Chunk {
     pub crc: u32,
 }
 
+impl Chunk {
+    /// Creates a chunk with length and CRC computed from the data
+    pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
+        let crc = crc(chunk_type.name().as_bytes(), &data);
+        Chunk {
+            length: data.len() as u32,
+            chunk_type,
+            data,
+            crc,
+        }
+    }
+}
+
 impl std::fmt::Debug for Chunk {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Chunk")
@@ -93,37 +232,32 @@ impl std::fmt::Debug for Chunk {
 impl BinarySerializable for Chunk {
     fn read<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> where Self: Sized {
         let length = reader.read_u32::<BigEndian>()?;
-        let chunk_type = match reader.read_to_string_lossy_exact(4)?.as_str() {
-            "IHDR" => ChunkType::IHDR,
-            "PLTE" => ChunkType::PLTE,
-            "IDAT" => ChunkType::IDAT,
-            "IEND" => ChunkType::IEND,
-            str => ChunkType::Other(str.to_string())
-        };
+        let chunk_type = ChunkType::from_name(&reader.read_to_string_exact(4)?);
 
-        let mut data = vec![0u8; length as usize]; 
-        reader.read_exact(&mut data).expect("msg");
+        if length > MAX_CHUNK_LENGTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "PNG chunk length out of range"));
+        }
+
+        // The length is untrusted, so the data only grows as far as the input goes
+        let mut data = Vec::new();
+        reader.take(length as u64).read_to_end(&mut data)?;
+        if data.len() != length as usize {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete PNG chunk"));
+        }
 
         let crc = reader.read_u32::<BigEndian>()?;
         
         Ok(Chunk {
             length,
             chunk_type,
-            data: data,
+            data,
             crc,
         })
     }
 
     fn write<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
         writer.write_u32::<BigEndian>(self.length)?;
-        match self.chunk_type {
-            ChunkType::IHDR => writer.write_all(b"IHDR")?,
-            ChunkType::PLTE => writer.write_all(b"PLTE")?,
-            ChunkType::IDAT => writer.write_all(b"IDAT")?,
-            ChunkType::IEND => writer.write_all(b"IEND")?,
-            ChunkType::Other(ref s) => writer.write_all(s.as_bytes())?
-        }
-
+        writer.write_all(self.chunk_type.name().as_bytes())?;
         writer.write_all(&self.data)?;
         writer.write_u32::<BigEndian>(self.crc)?;
         
@@ -131,7 +265,7 @@ impl BinarySerializable for Chunk {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum ChunkType {
     IHDR,
     PLTE,
@@ -139,4 +273,40 @@ pub enum ChunkType {
     IEND,
     
     Other(String),
-}
\ No newline at end of file
+}
+
+impl ChunkType {
+    pub fn from_name(name: &str) -> ChunkType {
+        match name {
+            "IHDR" => ChunkType::IHDR,
+            "PLTE" => ChunkType::PLTE,
+            "IDAT" => ChunkType::IDAT,
+            "IEND" => ChunkType::IEND,
+            str => ChunkType::Other(str.to_string())
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            ChunkType::IHDR => "IHDR",
+            ChunkType::PLTE => "PLTE",
+            ChunkType::IDAT => "IDAT",
+            ChunkType::IEND => "IEND",
+            ChunkType::Other(ref s) => s,
+        }
+    }
+
+    /// Critical chunks have an uppercase first letter, everything else may be dropped by decoders
+    pub fn is_critical(&self) -> bool {
+        self.name().starts_with(|c: char| c.is_ascii_uppercase())
+    }
+
+    /// Chunks with a lowercase fourth letter may be copied by editors which do not know them, even after critical chunks change
+    pub fn is_safe_to_copy(&self) -> bool {
+        self.name().as_bytes().get(3).is_some_and(|c| c.is_ascii_lowercase())
+    }
+
+    pub fn is(&self, name: &str) -> bool {
+        self.name() == name
+    }
+}
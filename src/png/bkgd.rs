@@ -0,0 +1,33 @@
+use std::io;
+
+use crate::png::ihdr::ColorType;
+
+/// Background color chunk, its layout depends on the image color type
+#[derive(Debug, Clone, PartialEq)]
+pub enum BKGD {
+    Gray(u16),
+    RGB(u16, u16, u16),
+    /// Index into the palette
+    Palette(u8),
+}
+
+impl BKGD {
+    pub fn from_data(data: &[u8], color_type: ColorType) -> io::Result<BKGD> {
+        let sample = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+
+        match color_type {
+            ColorType::Grayscale | ColorType::GrayscaleAlpha if data.len() == 2 => Ok(BKGD::Gray(sample(0))),
+            ColorType::RGB | ColorType::RGBA if data.len() == 6 => Ok(BKGD::RGB(sample(0), sample(2), sample(4))),
+            ColorType::Palette if data.len() == 1 => Ok(BKGD::Palette(data[0])),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid bKGD chunk for color type")),
+        }
+    }
+
+    pub fn to_data(&self) -> Vec<u8> {
+        match self {
+            BKGD::Gray(gray) => gray.to_be_bytes().to_vec(),
+            BKGD::RGB(r, g, b) => [r.to_be_bytes(), g.to_be_bytes(), b.to_be_bytes()].concat(),
+            BKGD::Palette(index) => vec![*index],
+        }
+    }
+}
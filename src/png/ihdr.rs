@@ -1,7 +1,7 @@
 use crate::binary_serializable::BinarySerializable;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IHDR {
     pub width: u32,
     pub height: u32,
@@ -13,21 +13,31 @@ pub struct IHDR {
 }
 
 impl IHDR {
+    /// Filter unit in bytes, rounded up to one byte for bit depths below 8
     pub fn bytes_per_pixel(&self) -> usize {
-        match self.color_type {
-            ColorType::Grayscale => self.bit_depth as usize / 8,
-            ColorType::RGB => 3 * self.bit_depth as usize / 8,
-            ColorType::Palette => self.bit_depth as usize / 8,
-            ColorType::GrayscaleAlpha => 2 * self.bit_depth as usize / 8,
-            ColorType::RGBA => 4 * self.bit_depth as usize / 8,
+        self.bits_per_pixel().div_ceil(8)
+    }
+
+    pub fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize
+    }
+
+    /// Length of a scanline of the given width without the filter type byte
+    pub fn scanline_length(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// Fails unless the bit depth is allowed for the color type, packed samples can only be read at these depths
+    pub fn check_bit_depth(&self) -> std::io::Result<()> {
+        if !self.color_type.allowed_bit_depths().contains(&self.bit_depth) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid bit depth {} for color type {:?}", self.bit_depth, self.color_type)));
         }
+
+        Ok(())
     }
 
     pub fn has_alpha(&self) -> bool {
-        match self.color_type {
-            ColorType::GrayscaleAlpha | ColorType::RGBA => true,
-            _ => false,
-        }
+        matches!(self.color_type, ColorType::GrayscaleAlpha | ColorType::RGBA)
     }
 }
 
@@ -36,7 +46,8 @@ impl BinarySerializable for IHDR {
         let width = reader.read_u32::<BigEndian>()?;
         let height = reader.read_u32::<BigEndian>()?;
         let bit_depth = reader.read_u8()?;
-        let color_type =  ColorType::try_from(reader.read_u8()?).unwrap();
+        let color_type = ColorType::try_from(reader.read_u8()?)
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "Unknown color type"))?;
         let compression_method = reader.read_u8()?;
         let filter_method = reader.read_u8()?;
         let interlace_method = reader.read_u8()?;
@@ -65,7 +76,7 @@ impl BinarySerializable for IHDR {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum ColorType {
     Grayscale = 0,
@@ -75,6 +86,26 @@ pub enum ColorType {
     RGBA = 6,
 }
 
+impl ColorType {
+    pub fn channels(&self) -> usize {
+        match self {
+            ColorType::Grayscale => 1,
+            ColorType::RGB => 3,
+            ColorType::Palette => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::RGBA => 4,
+        }
+    }
+
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Palette => &[1, 2, 4, 8],
+            _ => &[8, 16],
+        }
+    }
+}
+
 impl std::convert::TryFrom<u8> for ColorType {
     type Error = ();
 
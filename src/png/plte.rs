@@ -1,5 +1,18 @@
 use crate::common::Color;
 
+#[derive(Debug, Clone, Default)]
 pub struct PLTE {
-    colors: Vec<Color>,
-}
\ No newline at end of file
+    pub colors: Vec<Color>,
+}
+
+impl PLTE {
+    pub fn from_data(data: &[u8]) -> PLTE {
+        PLTE {
+            colors: data.chunks_exact(3).map(|rgb| Color::from_rgb(rgb[0], rgb[1], rgb[2])).collect(),
+        }
+    }
+
+    pub fn to_data(&self) -> Vec<u8> {
+        self.colors.iter().flat_map(|color| [color.r, color.g, color.b]).collect()
+    }
+}
@@ -0,0 +1,35 @@
+use std::io;
+
+use crate::png::ihdr::ColorType;
+
+/// Transparency chunk, its layout depends on the image color type
+#[derive(Debug, Clone, PartialEq)]
+pub enum TRNS {
+    /// Grayscale sample value treated as fully transparent
+    Gray(u16),
+    /// RGB sample values treated as fully transparent
+    RGB(u16, u16, u16),
+    /// Alpha for the leading palette entries, the rest are opaque
+    Palette(Vec<u8>),
+}
+
+impl TRNS {
+    pub fn from_data(data: &[u8], color_type: ColorType) -> io::Result<TRNS> {
+        let sample = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+
+        match color_type {
+            ColorType::Grayscale if data.len() == 2 => Ok(TRNS::Gray(sample(0))),
+            ColorType::RGB if data.len() == 6 => Ok(TRNS::RGB(sample(0), sample(2), sample(4))),
+            ColorType::Palette => Ok(TRNS::Palette(data.to_vec())),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid tRNS chunk for color type")),
+        }
+    }
+
+    pub fn to_data(&self) -> Vec<u8> {
+        match self {
+            TRNS::Gray(gray) => gray.to_be_bytes().to_vec(),
+            TRNS::RGB(r, g, b) => [r.to_be_bytes(), g.to_be_bytes(), b.to_be_bytes()].concat(),
+            TRNS::Palette(alphas) => alphas.clone(),
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::io;
+
+use crate::binary_serializable::BinarySerializable;
+use crate::common::*;
+use crate::png::{Chunk, ChunkType, PNG};
+use crate::png::ihdr::{ColorType, IHDR};
+use crate::png::trns::TRNS;
+use crate::png_reader::PNGReader;
+use crate::png_writer::{self, ColorAnalysis, Deflate, FilterStrategy, PNGWriter, pack_scanline, palette_chunks};
+
+/// Ancillary chunks that must precede PLTE
+const BEFORE_PLTE: [&str; 5] = ["cHRM", "gAMA", "iCCP", "sBIT", "sRGB"];
+
+/// Ancillary chunks whose contents depend on the color type, bit depth or palette
+const COLOR_DEPENDENT: [&str; 3] = ["bKGD", "hIST", "sBIT"];
+
+/// Registered ancillary chunks, which stay valid when the critical chunks are rewritten apart from COLOR_DEPENDENT ones
+const KNOWN: [&str; 17] = ["cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCV", "cLLI", "bKGD", "hIST", "eXIf", "pHYs", "sPLT", "tIME", "tEXt", "zTXt", "iTXt"];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Strip {
+    /// Keep all ancillary chunks, except unknown ones which are not safe to copy
+    None,
+    /// Keep only chunks affecting how colors are displayed
+    Safe,
+    /// Drop all ancillary chunks
+    All,
+}
+
+pub struct Settings {
+    pub filters: Vec<FilterStrategy>,
+    pub compression_levels: Vec<u32>,
+    /// Recompresses the smallest result with this many Zopfli iterations
+    pub zopfli: Option<u32>,
+    pub strip: Strip,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            filters: FilterStrategy::ALL.to_vec(),
+            compression_levels: (0..=9).collect(),
+            zopfli: None,
+            strip: Strip::None,
+        }
+    }
+}
+
+pub struct PNGOptimizer {
+    pub settings: Settings
+}
+
+/// Lossless encoding of the image data to try
+struct Candidate {
+    color_type: ColorType,
+    bit_depth: u8,
+    scanlines: Vec<Vec<u8>>,
+    /// PLTE and tRNS chunks
+    chunks: Vec<Chunk>,
+}
+
+impl Candidate {
+    fn from_image(image: &Image, color_type: ColorType, bit_depth: u8, transparency: Option<TRNS>) -> Candidate {
+        let scanlines = image.pixels.iter()
+            .map(|row| pack_scanline(row, color_type, bit_depth, None))
+            .collect();
+        let chunks = transparency
+            .map(|trns| vec![Chunk::new(ChunkType::Other("tRNS".to_string()), trns.to_data())])
+            .unwrap_or_default();
+
+        Candidate { color_type, bit_depth, scanlines, chunks }
+    }
+
+    fn from_palette(image: &Image, colors: &[Color]) -> Candidate {
+        let bit_depth = ColorAnalysis::palette_bit_depth(colors);
+        let indices: HashMap<Color, u8> = colors.iter().enumerate().map(|(i, color)| (*color, i as u8)).collect();
+        let scanlines = image.pixels.iter()
+            .map(|row| pack_scanline(row, ColorType::Palette, bit_depth, Some(&indices)))
+            .collect();
+
+        Candidate { color_type: ColorType::Palette, bit_depth, scanlines, chunks: palette_chunks(colors) }
+    }
+}
+
+impl PNGOptimizer {
+    fn candidates(&self, image: &Image) -> Vec<Candidate> {
+        let analysis = ColorAnalysis::new(image);
+        let keyed = analysis.opaque || analysis.key.is_some();
+
+        let mut candidates = Vec::new();
+
+        if analysis.gray && keyed {
+            let depth = analysis.gray_bit_depth;
+            let trns = analysis.key_transparency(ColorType::Grayscale, depth);
+            candidates.push(Candidate::from_image(image, ColorType::Grayscale, depth, trns));
+        } else if analysis.gray {
+            candidates.push(Candidate::from_image(image, ColorType::GrayscaleAlpha, 8, None));
+        }
+
+        if let Some(colors) = analysis.palette.as_ref() {
+            candidates.push(Candidate::from_palette(image, colors));
+        }
+
+        if keyed {
+            let trns = analysis.key_transparency(ColorType::RGB, 8);
+            candidates.push(Candidate::from_image(image, ColorType::RGB, 8, trns));
+        } else {
+            candidates.push(Candidate::from_image(image, ColorType::RGBA, 8, None));
+        }
+
+        candidates
+    }
+
+    /// 16 bit samples can be reduced to 8 bits when both bytes of every sample are equal
+    fn is_16_bit_reducible(&self, scanlines: &[Vec<u8>]) -> bool {
+        scanlines.iter().all(|scanline| scanline.chunks_exact(2).all(|sample| sample[0] == sample[1]))
+    }
+
+    fn ancillary_chunks<'a>(&self, png: &'a PNG, color_changed: bool) -> Vec<&'a Chunk> {
+        png.chunks.iter()
+            .filter(|chunk| !chunk.chunk_type.is_critical() && !chunk.chunk_type.is("tRNS"))
+            .filter(|chunk| !(color_changed && COLOR_DEPENDENT.contains(&chunk.chunk_type.name())))
+            .filter(|chunk| KNOWN.contains(&chunk.chunk_type.name()) || chunk.chunk_type.is_safe_to_copy())
+            .filter(|chunk| match self.settings.strip {
+                Strip::None => true,
+                Strip::Safe => BEFORE_PLTE.contains(&chunk.chunk_type.name()),
+                Strip::All => false,
+            })
+            .collect()
+    }
+
+    /// Smallest IDAT data over all filter strategies and compression levels
+    fn compress(&self, candidate: &Candidate, width: usize) -> Vec<u8> {
+        let ihdr = self.ihdr(candidate, width);
+        let bpp = ihdr.bytes_per_pixel();
+        let mut best: Option<(Vec<u8>, Vec<u8>)> = None; // Compressed and filtered data
+
+        for &filter in self.settings.filters.iter() {
+            let mut filtered = None;
+
+            for &compression_level in self.settings.compression_levels.iter() {
+                let writer = PNGWriter {
+                    settings: png_writer::Settings { filter, compression_level, ..Default::default() }
+                };
+
+                // Only brute force filtering depends on the compression level
+                if filtered.is_none() || filter == FilterStrategy::BruteForce {
+                    filtered = Some(writer.filter_scanlines(&candidate.scanlines, bpp));
+                }
+
+                let compressed = writer.compress(filtered.as_ref().unwrap());
+                if best.as_ref().is_none_or(|(best, _)| compressed.len() < best.len()) {
+                    best = Some((compressed, filtered.clone().unwrap()));
+                }
+            }
+        }
+
+        let (compressed, filtered) = best.expect("At least one filter strategy and compression level is required");
+
+        match self.settings.zopfli {
+            Some(iterations) => {
+                let writer = PNGWriter {
+                    settings: png_writer::Settings { deflate: Deflate::Zopfli { iterations }, ..Default::default() }
+                };
+                let recompressed = writer.compress(&filtered);
+                if recompressed.len() < compressed.len() { recompressed } else { compressed }
+            }
+            None => compressed,
+        }
+    }
+
+    fn ihdr(&self, candidate: &Candidate, width: usize) -> IHDR {
+        IHDR {
+            width: width as u32,
+            height: candidate.scanlines.len() as u32,
+            bit_depth: candidate.bit_depth,
+            color_type: candidate.color_type,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: 0,
+        }
+    }
+
+    /// Re-encodes the PNG losslessly, returns the original if the result is not smaller
+    pub fn optimize(&self, png: &PNG) -> io::Result<PNG> {
+        if png.chunks.iter().any(|chunk| chunk.chunk_type.is("acTL")) {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "Animated PNGs are not supported"));
+        }
+
+        let source = png.ihdr()?;
+        let reader = PNGReader {};
+        let scanlines = reader.scanlines(png, &source)?;
+        let width = source.width as usize;
+
+        let candidates = if source.bit_depth == 16 && !self.is_16_bit_reducible(&scanlines) {
+            let trns = png.chunks.iter().find(|chunk| chunk.chunk_type.is("tRNS")).cloned();
+            vec![Candidate { color_type: source.color_type, bit_depth: 16, scanlines, chunks: trns.into_iter().collect() }]
+        } else {
+            let image = reader.decode(png)?;
+            self.candidates(&image)
+        };
+
+        let mut best: Option<PNG> = None;
+
+        for candidate in candidates.iter() {
+            let ihdr = self.ihdr(candidate, width);
+            let color_changed = ihdr.color_type != source.color_type
+                || ihdr.bit_depth != source.bit_depth
+                || ihdr.color_type == ColorType::Palette;
+
+            let mut ihdr_data = Vec::new();
+            ihdr.write(&mut ihdr_data)?;
+
+            let ancillary = self.ancillary_chunks(png, color_changed);
+            let first_idat = png.chunks.iter().position(|chunk| chunk.chunk_type == ChunkType::IDAT).unwrap_or(png.chunks.len());
+            let before_idat = |chunk: &&Chunk| png.chunks[..first_idat].iter().any(|c| std::ptr::eq(c, *chunk));
+
+            let mut chunks = vec![Chunk::new(ChunkType::IHDR, ihdr_data)];
+            chunks.extend(ancillary.iter().filter(|chunk| BEFORE_PLTE.contains(&chunk.chunk_type.name())).map(|chunk| (*chunk).clone()));
+            chunks.extend(candidate.chunks.iter().cloned());
+            chunks.extend(ancillary.iter().filter(|chunk| !BEFORE_PLTE.contains(&chunk.chunk_type.name()) && before_idat(chunk)).map(|chunk| (*chunk).clone()));
+            chunks.push(Chunk::new(ChunkType::IDAT, self.compress(candidate, width)));
+            chunks.extend(ancillary.iter().filter(|chunk| !BEFORE_PLTE.contains(&chunk.chunk_type.name()) && !before_idat(chunk)).map(|chunk| (*chunk).clone()));
+            chunks.push(Chunk::new(ChunkType::IEND, vec![]));
+
+            let optimized = PNG { chunks };
+            if best.as_ref().is_none_or(|best| optimized.size() < best.size()) {
+                best = Some(optimized);
+            }
+        }
+
+        match best {
+            Some(best) if best.size() < png.size() => Ok(best),
+            _ => Ok(png.clone()),
+        }
+    }
+}
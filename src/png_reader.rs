@@ -1,16 +1,42 @@
-use std::io::{Cursor, Read};
+use std::fs;
+use std::io;
 
-use flate2::read::ZlibDecoder;
+use flate2::{Decompress, FlushDecompress, Status};
 
-use crate::binary_serializable::BinarySerializable;
 use crate::common::*;
-use crate::png::{PNG, ChunkType, paeth_predictor};
-use crate::png::ihdr::IHDR;
+use crate::png::{crc, scan_chunks, ChunkType, MAGIC, PNG, paeth_predictor};
+use crate::png::ihdr::{ColorType, IHDR};
+use crate::png::plte::PLTE;
+use crate::png::trns::TRNS;
 
 pub struct PNGReader {
 
 }
 
+/// What a recovering read managed to decode
+#[derive(Debug, Clone, Default)]
+pub struct RecoveryReport {
+    /// Complete filtered scanlines decoded, counting scanlines of all interlace passes
+    pub scanlines: usize,
+    pub total_scanlines: usize,
+    /// Image rows with every pixel decoded
+    pub complete_rows: Vec<usize>,
+    pub decoded_pixels: usize,
+    /// Indices of chunks whose CRC does not match their data
+    pub bad_crcs: Vec<usize>,
+    /// The file ends inside a chunk
+    pub truncated: bool,
+    pub missing_iend: bool,
+    /// Why inflating or unfiltering the image data stopped early
+    pub error: Option<String>,
+}
+
+impl RecoveryReport {
+    pub fn is_complete(&self) -> bool {
+        self.scanlines == self.total_scanlines && self.error.is_none()
+    }
+}
+
 const ADAM7: [(usize, usize, usize, usize); 7] = [
     (0, 0, 8, 8), // Pass 1
     (4, 0, 8, 8), // Pass 2
@@ -21,8 +47,77 @@ const ADAM7: [(usize, usize, usize, usize); 7] = [
     (0, 1, 1, 2), // Pass 7
 ];
 
+/// How much larger than its inflated data a recovered image may be, the rest of it is filled
+const MAX_RECOVERED_PIXELS_PER_BYTE: usize = 8 * 1024;
+
+/// Number of pixels of a reduced image along one axis for the given Adam7 pass start and step
+fn pass_size(size: usize, start: usize, step: usize) -> usize {
+    if size > start { (size - start).div_ceil(step) } else { 0 }
+}
+
+/// Result of inflating a zlib stream which may be damaged
+pub(crate) struct Inflated {
+    /// Everything inflated before the end of the stream, the first error or the limit
+    pub data: Vec<u8>,
+    /// Compressed bytes consumed
+    pub consumed: usize,
+    /// Why the stream did not end properly
+    pub error: Option<String>,
+}
+
+/// Inflates until the stream ends or more than `limit` bytes are inflated, which is not an error
+pub(crate) fn inflate(compressed: &[u8], limit: usize) -> Inflated {
+    let mut decompress = Decompress::new(true);
+    let mut data = Vec::with_capacity(compressed.len().saturating_mul(4).min(limit));
+
+    let error = loop {
+        if data.len() == data.capacity() {
+            if data.len() >= limit {
+                break None;
+            }
+            data.reserve_exact((32 * 1024).min(limit - data.len()));
+        }
+
+        let consumed = decompress.total_in() as usize;
+        let produced = data.len();
+        match decompress.decompress_vec(&compressed[consumed..], &mut data, FlushDecompress::None) {
+            Ok(Status::StreamEnd) => break None,
+            Ok(_) if decompress.total_in() as usize == consumed && data.len() == produced && data.len() < data.capacity() => {
+                break Some("Unexpected end of zlib stream".to_string());
+            }
+            Ok(_) => {}
+            Err(e) => break Some(e.to_string()),
+        }
+    };
+
+    Inflated { data, consumed: decompress.total_in() as usize, error }
+}
+
+/// Reads `index`-th sample of a packed scanline
+pub(crate) fn read_sample(scanline: &[u8], index: usize, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 => u16::from_be_bytes([scanline[index * 2], scanline[index * 2 + 1]]),
+        8 => scanline[index] as u16,
+        _ => {
+            let bit = index * bit_depth as usize;
+            let shift = 8 - bit_depth as usize - bit % 8;
+            let mask = (1u16 << bit_depth) - 1;
+            (scanline[bit / 8] as u16 >> shift) & mask
+        }
+    }
+}
+
+/// Scales a sample of the given bit depth to 8 bits
+pub(crate) fn scale_sample(sample: u16, bit_depth: u8) -> u8 {
+    match bit_depth {
+        16 => ((sample as u32 * 255 + 32767) / 65535) as u8,
+        8 => sample as u8,
+        _ => (sample as u32 * 255 / ((1u32 << bit_depth) - 1)) as u8,
+    }
+}
+
 impl PNGReader {
-    fn unfilter_scanline(&self, filter_type: u8, scanline: &[u8], prev_scanline: Option<&[u8]>, bpp: usize) -> Vec<u8> {
+    fn unfilter_scanline(&self, filter_type: u8, scanline: &[u8], prev_scanline: Option<&[u8]>, bpp: usize) -> io::Result<Vec<u8>> {
         let mut unfiltered = Vec::with_capacity(scanline.len());
     
         match filter_type {
@@ -55,95 +150,288 @@ impl PNGReader {
                     unfiltered.push(scanline[i].wrapping_add(paeth_predictor(left, above, above_left)));
                 }
             }
-            _ => panic!("Unknown filter type"),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unknown filter type {}", filter_type))),
         }
     
-        unfiltered
+        Ok(unfiltered)
     }
-}
 
-impl Reader for PNGReader {
-    fn read(&self, path: &str) -> std::io::Result<Image> {
-        println!("Reading PNG file at: {}", path);
-        let png = PNG::from_file(path);
-        
-        let ihdr_chunk = png.chunks.iter().find(|chunk| chunk.chunk_type == ChunkType::IHDR).unwrap();
-        let mut cursor = Cursor::new(&ihdr_chunk.data);
-        let ihdr = IHDR::read(&mut cursor).unwrap();
-        println!("{:?}", ihdr);
+    /// Unfilters `height` scanlines of `scanline_length` bytes starting at `offset`, stops at the first broken one
+    fn unfilter_scanlines(&self, data: &[u8], offset: &mut usize, height: usize, scanline_length: usize, bpp: usize, scanlines: &mut Vec<Vec<u8>>) -> io::Result<()> {
+        for _ in 0..height {
+            if *offset + 1 + scanline_length > data.len() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Not enough image data"));
+            }
+
+            let filter_type = data[*offset];
+            let scanline = &data[*offset + 1..*offset + 1 + scanline_length];
+
+            let unfiltered = self.unfilter_scanline(filter_type, scanline, scanlines.last().map(Vec::as_slice), bpp)?;
+            scanlines.push(unfiltered);
+            *offset += 1 + scanline_length;
+        }
+
+        Ok(())
+    }
 
+    /// Inflated image data from all IDAT chunks, at most one byte more than the IHDR size needs
+    pub(crate) fn image_data(&self, png: &PNG) -> io::Result<Vec<u8>> {
         let mut concatenated = Vec::<u8>::new();
-        for chunk in png.chunks {
-            match chunk.chunk_type {
-                ChunkType::IDAT => {
-                    concatenated.extend(&chunk.data);
-                }
-                _ => {}
+        for chunk in png.chunks.iter() {
+            if chunk.chunk_type == ChunkType::IDAT {
+                concatenated.extend(&chunk.data);
             }
         }
 
-        let mut zlibdecoder = ZlibDecoder::<&[u8]>::new_with_buf(&concatenated, vec![0; 32 * 1024]);
-        let mut decompressed = Vec::<u8>::new();
-        zlibdecoder.read_to_end(&mut decompressed).expect("Can't decode");
+        let inflated = inflate(&concatenated, self.image_data_size(&png.ihdr()?).saturating_add(1));
+        match inflated.error {
+            Some(error) => Err(io::Error::new(io::ErrorKind::InvalidData, error)),
+            None => Ok(inflated.data),
+        }
+    }
 
+    /// Sizes of the reduced images the scanlines are stored in, a single one for non interlaced images
+    pub(crate) fn passes(&self, ihdr: &IHDR) -> Vec<(usize, usize, usize, usize, usize, usize)> {
         let width = ihdr.width as usize;
         let height = ihdr.height as usize;
+
+        if ihdr.interlace_method != 1 {
+            return vec![(0, 0, 1, 1, width, height)];
+        }
+
+        ADAM7.iter()
+            .map(|&(x_start, y_start, x_step, y_step)| (x_start, y_start, x_step, y_step, pass_size(width, x_start, x_step), pass_size(height, y_start, y_step)))
+            .filter(|&(.., pass_width, pass_height)| pass_width > 0 && pass_height > 0)
+            .collect()
+    }
+
+    /// Bytes of filtered scanlines of all passes the IHDR size needs
+    pub(crate) fn image_data_size(&self, ihdr: &IHDR) -> usize {
+        self.passes(ihdr).iter()
+            .map(|&(.., pass_width, pass_height)| (1 + ihdr.scanline_length(pass_width)).saturating_mul(pass_height))
+            .fold(0, usize::saturating_add)
+    }
+
+    /// Unfilters and deinterlaces as many scanlines as the data holds, samples stay packed at the IHDR bit depth.
+    /// Returns the scanlines, the number of complete filtered scanlines and the error stopping the decoding.
+    fn decode_scanlines(&self, data: &[u8], ihdr: &IHDR) -> (Vec<Vec<u8>>, usize, Option<io::Error>) {
+        let width = ihdr.width as usize;
         let bytes_per_pixel = ihdr.bytes_per_pixel();
+        let mut offset = 0;
+
+        if ihdr.interlace_method != 1 {
+            let mut scanlines = Vec::with_capacity((ihdr.height as usize).min(data.len() / (1 + ihdr.scanline_length(width))));
+            let result = self.unfilter_scanlines(data, &mut offset, ihdr.height as usize, ihdr.scanline_length(width), bytes_per_pixel, &mut scanlines);
+            let decoded = scanlines.len();
+            return (scanlines, decoded, result.err());
+        }
 
-        let scanline_length = 1 + width * bytes_per_pixel;
-
-        let mut pixels: Vec<Vec<Color>> = Vec::with_capacity(height);
-        let mut prev_scanline: Option<Vec<u8>> = None;
-
-        if ihdr.interlace_method == 1 {
-            pixels = vec![vec![Color::new(0, 0, 0, 0); width]; height];
-            
-            let mut offset = 0;
-
-            for &(x_start, y_start, x_step, y_step) in ADAM7.iter() {
-                let pass_width = (width - x_start + x_step - 1) / x_step;
-                let pass_height = (height - y_start + y_step - 1) / y_step;
-        
-                for y in 0..pass_height {
-                    let filter_type = decompressed[offset];
-                    offset += 1;
-        
-                    let scanline = &decompressed[offset..offset + pass_width * bytes_per_pixel];
-                    offset += pass_width * bytes_per_pixel;
-        
-                    let unfiltered_scanline = self.unfilter_scanline(filter_type, scanline, prev_scanline.as_deref(), bytes_per_pixel);
-        
-                    for x in 0..pass_width {
-                        let pixel_index = x * bytes_per_pixel;
-                        let output_x = x_start + x * x_step;
-                        let output_y = y_start + y * y_step;
-
-                        if output_x < width && output_y < height {
-                            let color = Color::from_slice(&unfiltered_scanline[pixel_index..pixel_index + 3]);
-                            pixels[output_y][output_x] = color;
-                        }
+        let bits_per_pixel = ihdr.bits_per_pixel();
+        let mut scanlines = vec![vec![0u8; ihdr.scanline_length(width)]; ihdr.height as usize];
+        let mut decoded = 0;
+
+        for (x_start, y_start, x_step, y_step, pass_width, pass_height) in self.passes(ihdr) {
+            let mut pass = Vec::with_capacity(pass_height);
+            let result = self.unfilter_scanlines(data, &mut offset, pass_height, ihdr.scanline_length(pass_width), bytes_per_pixel, &mut pass);
+            decoded += pass.len();
+
+            for (y, pass_scanline) in pass.iter().enumerate() {
+                let scanline = &mut scanlines[y_start + y * y_step];
+
+                for x in 0..pass_width {
+                    let output_x = x_start + x * x_step;
+
+                    if bits_per_pixel >= 8 {
+                        let src = x * bytes_per_pixel;
+                        let dst = output_x * bytes_per_pixel;
+                        scanline[dst..dst + bytes_per_pixel].copy_from_slice(&pass_scanline[src..src + bytes_per_pixel]);
+                    } else {
+                        let sample = read_sample(pass_scanline, x, ihdr.bit_depth) as u8;
+                        let bit = output_x * bits_per_pixel;
+                        scanline[bit / 8] |= sample << (8 - bits_per_pixel - bit % 8);
                     }
-        
-                    prev_scanline = Some(unfiltered_scanline);
                 }
             }
-        } else {
-            for scanline_start in (0..decompressed.len()).step_by(scanline_length) {
-                let filter_type = decompressed[scanline_start];
 
-                let scanline = &decompressed[scanline_start + 1..scanline_start + scanline_length];
-                let unfiltered_scanline = self.unfilter_scanline(filter_type, scanline, prev_scanline.as_deref(), bytes_per_pixel);
-                let mut row: Vec<Color> = Vec::with_capacity(width);
-    
-                for chunk in unfiltered_scanline.chunks(bytes_per_pixel) {
-                    row.push(Color::from_slice(chunk));
+            if let Err(e) = result {
+                return (scanlines, decoded, Some(e));
+            }
+        }
+
+        (scanlines, decoded, None)
+    }
+
+    /// Unfiltered and deinterlaced scanlines, samples stay packed at the IHDR bit depth
+    pub(crate) fn scanlines(&self, png: &PNG, ihdr: &IHDR) -> io::Result<Vec<Vec<u8>>> {
+        ihdr.check_bit_depth()?;
+        let decompressed = self.image_data(png)?;
+        if decompressed.len() < self.image_data_size(ihdr) {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Not enough image data"));
+        }
+
+        match self.decode_scanlines(&decompressed, ihdr) {
+            (_, _, Some(e)) => Err(e),
+            (scanlines, _, None) => Ok(scanlines),
+        }
+    }
+
+    /// Converts a deinterlaced scanline to colors
+    pub(crate) fn scanline_colors(&self, scanline: &[u8], ihdr: &IHDR, palette: Option<&PLTE>, transparency: Option<&TRNS>) -> io::Result<Vec<Color>> {
+        let width = ihdr.width as usize;
+        let depth = ihdr.bit_depth;
+        let channels = ihdr.color_type.channels();
+        let mut row = Vec::with_capacity(width);
+
+        for x in 0..width {
+            let sample = |channel: usize| read_sample(scanline, x * channels + channel, depth);
+
+            let color = match ihdr.color_type {
+                ColorType::Grayscale => {
+                    let gray = scale_sample(sample(0), depth);
+                    let alpha = if transparency == Some(&TRNS::Gray(sample(0))) { 0 } else { 255 };
+                    Color::new(gray, gray, gray, alpha)
+                }
+                ColorType::RGB => {
+                    let key = TRNS::RGB(sample(0), sample(1), sample(2));
+                    let alpha = if transparency == Some(&key) { 0 } else { 255 };
+                    Color::new(scale_sample(sample(0), depth), scale_sample(sample(1), depth), scale_sample(sample(2), depth), alpha)
+                }
+                ColorType::Palette => {
+                    let index = sample(0) as usize;
+                    let mut color = *palette
+                        .and_then(|palette| palette.colors.get(index))
+                        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Palette index out of range"))?;
+                    if let Some(TRNS::Palette(alphas)) = transparency {
+                        color.a = alphas.get(index).copied().unwrap_or(255);
+                    }
+                    color
+                }
+                ColorType::GrayscaleAlpha => {
+                    let gray = scale_sample(sample(0), depth);
+                    Color::new(gray, gray, gray, scale_sample(sample(1), depth))
+                }
+                ColorType::RGBA => Color::new(
+                    scale_sample(sample(0), depth),
+                    scale_sample(sample(1), depth),
+                    scale_sample(sample(2), depth),
+                    scale_sample(sample(3), depth),
+                ),
+            };
+
+            row.push(color);
+        }
+
+        Ok(row)
+    }
+
+    pub fn recover(&self, path: &str, fill: Color) -> io::Result<(Image, RecoveryReport)> {
+        println!("Recovering PNG file at: {}", path);
+        self.recover_bytes(&fs::read(path)?, fill)
+    }
+
+    /// Decodes whatever a damaged or truncated file holds, pixels which could not be decoded get the fill color
+    pub fn recover_bytes(&self, bytes: &[u8], fill: Color) -> io::Result<(Image, RecoveryReport)> {
+        if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid PNG signature"));
+        }
+
+        let scan = scan_chunks(&bytes[MAGIC.len()..]);
+        let mut report = RecoveryReport {
+            bad_crcs: scan.chunks.iter().enumerate()
+                .filter(|(_, chunk)| crc(chunk.chunk_type.name().as_bytes(), &chunk.data) != chunk.crc)
+                .map(|(index, _)| index)
+                .collect(),
+            truncated: scan.truncated,
+            missing_iend: !scan.chunks.iter().any(|chunk| chunk.chunk_type == ChunkType::IEND),
+            ..Default::default()
+        };
+
+        let mut png = PNG { chunks: scan.chunks };
+        png.chunks.extend(scan.partial.filter(|chunk| chunk.chunk_type == ChunkType::IDAT));
+
+        let ihdr = png.ihdr()?;
+        ihdr.check_bit_depth()?;
+        let width = ihdr.width as usize;
+        let height = ihdr.height as usize;
+
+        let palette = png.chunk(&ChunkType::PLTE).map(|chunk| PLTE::from_data(&chunk.data));
+        let transparency = png.chunks.iter()
+            .find(|chunk| chunk.chunk_type.is("tRNS"))
+            .and_then(|chunk| TRNS::from_data(&chunk.data, ihdr.color_type).ok());
+
+        let compressed: Vec<u8> = png.chunks.iter()
+            .filter(|chunk| chunk.chunk_type == ChunkType::IDAT)
+            .flat_map(|chunk| chunk.data.iter().copied())
+            .collect();
+        let inflated = inflate(&compressed, self.image_data_size(&ihdr).saturating_add(1));
+        if width * height > (inflated.data.len() + 1).saturating_mul(MAX_RECOVERED_PIXELS_PER_BYTE) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "PNG size is too large for its data"));
+        }
+
+        let (scanlines, decoded, error) = self.decode_scanlines(&inflated.data, &ihdr);
+
+        report.error = inflated.error.or(error.map(|e| e.to_string()));
+        report.scanlines = decoded;
+        report.total_scanlines = self.passes(&ihdr).iter().map(|&(.., pass_height)| pass_height).sum();
+
+        // Pixels are decoded pass by pass, scanline by scanline
+        let mut mask = vec![vec![false; width]; height];
+        let mut remaining = decoded;
+        for (x_start, y_start, x_step, y_step, pass_width, pass_height) in self.passes(&ihdr) {
+            for y in 0..pass_height.min(remaining) {
+                for x in 0..pass_width {
+                    mask[y_start + y * y_step][x_start + x * x_step] = true;
                 }
-        
-                prev_scanline = Some(unfiltered_scanline);
-                pixels.push(row);
             }
+            remaining = remaining.saturating_sub(pass_height);
         }
 
-        Result::Ok(Image::from_mat(width, height, pixels))
+        let mut pixels = Vec::with_capacity(height);
+        for (y, row_mask) in mask.iter_mut().enumerate() {
+            let colors = scanlines.get(y)
+                .and_then(|scanline| self.scanline_colors(scanline, &ihdr, palette.as_ref(), transparency.as_ref()).ok());
+
+            let row = match colors {
+                Some(colors) => colors.into_iter().zip(row_mask.iter()).map(|(color, &decoded)| if decoded { color } else { fill }).collect(),
+                None => {
+                    row_mask.fill(false);
+                    vec![fill; width]
+                }
+            };
+            pixels.push(row);
+        }
+
+        report.complete_rows = (0..height).filter(|&y| mask[y].iter().all(|&decoded| decoded)).collect();
+        report.decoded_pixels = mask.iter().flatten().filter(|&&decoded| decoded).count();
+
+        Ok((Image::from_mat(width, height, pixels), report))
     }
-}
\ No newline at end of file
+
+    pub fn decode(&self, png: &PNG) -> io::Result<Image> {
+        let ihdr = png.ihdr()?;
+
+        let palette = png.chunk(&ChunkType::PLTE).map(|chunk| PLTE::from_data(&chunk.data));
+        let transparency = match png.chunks.iter().find(|chunk| chunk.chunk_type.is("tRNS")) {
+            Some(chunk) => Some(TRNS::from_data(&chunk.data, ihdr.color_type)?),
+            None => None,
+        };
+
+        let scanlines = self.scanlines(png, &ihdr)?;
+
+        let mut pixels: Vec<Vec<Color>> = Vec::with_capacity(scanlines.len());
+        for scanline in scanlines.iter() {
+            pixels.push(self.scanline_colors(scanline, &ihdr, palette.as_ref(), transparency.as_ref())?);
+        }
+
+        Result::Ok(Image::from_mat(ihdr.width as usize, ihdr.height as usize, pixels))
+    }
+}
+
+impl Reader for PNGReader {
+    fn read(&self, path: &str) -> std::io::Result<Image> {
+        println!("Reading PNG file at: {}", path);
+        let png = PNG::from_file(path)?;
+
+        self.decode(&png)
+    }
+}
@@ -0,0 +1,344 @@
+use std::fmt;
+use std::fs;
+use std::io;
+
+use crate::png::{crc, scan_chunks, ChunkType, MAGIC, PNG};
+use crate::png::ihdr::{ColorType, IHDR};
+use crate::png_reader::{inflate, PNGReader};
+
+/// Ancillary chunks that must precede PLTE
+const BEFORE_PLTE: [&str; 5] = ["cHRM", "gAMA", "iCCP", "sBIT", "sRGB"];
+
+/// Ancillary chunks that must come after PLTE
+const AFTER_PLTE: [&str; 3] = ["bKGD", "hIST", "tRNS"];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    /// The file decodes but does not follow the specification
+    Warning,
+    /// The file is broken or decoders may reject it
+    Error,
+}
+
+/// Problem found in a PNG, `chunk` fields are chunk indices
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diagnostic {
+    InvalidMagic,
+    /// The file ends inside a chunk
+    TruncatedChunk { chunk: usize },
+    TrailingData { bytes: usize },
+    InvalidChunkType { chunk: usize, name: String },
+    UnknownCriticalChunk { chunk: usize, name: String },
+    LengthMismatch { chunk: usize, declared: u32, actual: usize },
+    CrcMismatch { chunk: usize, name: String, stored: u32, computed: u32 },
+
+    MissingIHDR,
+    IHDRNotFirst,
+    InvalidIHDR { reason: String },
+    InvalidColorTypeBitDepth { color_type: u8, bit_depth: u8 },
+
+    MissingPLTE,
+    UnexpectedPLTE,
+    InvalidPLTE { entries: usize },
+    /// The chunk must come before or after PLTE, or before IDAT
+    MisplacedChunk { chunk: usize, name: String, reason: &'static str },
+    DuplicateChunk { chunk: usize, name: String },
+
+    MissingIDAT,
+    NonContiguousIDAT { chunk: usize },
+    MissingIEND,
+    NonEmptyIEND,
+    ChunkAfterIEND { chunk: usize },
+
+    ZlibError { message: String },
+    TrailingZlibData { bytes: usize },
+    NotEnoughImageData { expected: usize, actual: usize },
+    /// Inflating stops after the first excess byte
+    TooMuchImageData { expected: usize },
+    InvalidFilterType { scanline: usize, filter_type: u8 },
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Diagnostic::TrailingData { .. }
+            | Diagnostic::InvalidChunkType { .. }
+            | Diagnostic::NonEmptyIEND
+            | Diagnostic::TrailingZlibData { .. }
+            | Diagnostic::TooMuchImageData { .. } => Severity::Warning,
+            Diagnostic::MisplacedChunk { name, .. }
+            | Diagnostic::DuplicateChunk { name, .. } if !ChunkType::from_name(name).is_critical() => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity() == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::InvalidMagic => write!(f, "Invalid PNG signature"),
+            Diagnostic::TruncatedChunk { chunk } => write!(f, "File ends inside chunk {}", chunk),
+            Diagnostic::TrailingData { bytes } => write!(f, "{} bytes of data after IEND", bytes),
+            Diagnostic::InvalidChunkType { chunk, name } => write!(f, "Chunk {} has an invalid type {:?}", chunk, name),
+            Diagnostic::UnknownCriticalChunk { chunk, name } => write!(f, "Chunk {} is an unknown critical chunk {}", chunk, name),
+            Diagnostic::LengthMismatch { chunk, declared, actual } => write!(f, "Chunk {} declares length {} but has {} bytes", chunk, declared, actual),
+            Diagnostic::CrcMismatch { chunk, name, stored, computed } => write!(f, "Chunk {} {} has CRC {:08x}, computed {:08x}", chunk, name, stored, computed),
+            Diagnostic::MissingIHDR => write!(f, "Missing IHDR chunk"),
+            Diagnostic::IHDRNotFirst => write!(f, "IHDR is not the first chunk"),
+            Diagnostic::InvalidIHDR { reason } => write!(f, "Invalid IHDR: {}", reason),
+            Diagnostic::InvalidColorTypeBitDepth { color_type, bit_depth } => write!(f, "Bit depth {} is not allowed for color type {}", bit_depth, color_type),
+            Diagnostic::MissingPLTE => write!(f, "Missing PLTE chunk for palette color type"),
+            Diagnostic::UnexpectedPLTE => write!(f, "PLTE chunk is not allowed for grayscale color types"),
+            Diagnostic::InvalidPLTE { entries } => write!(f, "PLTE chunk with {} entries or a length not divisible by 3", entries),
+            Diagnostic::MisplacedChunk { chunk, name, reason } => write!(f, "Chunk {} {} must come {}", chunk, name, reason),
+            Diagnostic::DuplicateChunk { chunk, name } => write!(f, "Chunk {} {} may appear only once", chunk, name),
+            Diagnostic::MissingIDAT => write!(f, "Missing IDAT chunk"),
+            Diagnostic::NonContiguousIDAT { chunk } => write!(f, "IDAT chunk {} is separated from the previous IDAT", chunk),
+            Diagnostic::MissingIEND => write!(f, "Missing IEND chunk"),
+            Diagnostic::NonEmptyIEND => write!(f, "IEND chunk has data"),
+            Diagnostic::ChunkAfterIEND { chunk } => write!(f, "Chunk {} comes after IEND", chunk),
+            Diagnostic::ZlibError { message } => write!(f, "Image data is not a valid zlib stream: {}", message),
+            Diagnostic::TrailingZlibData { bytes } => write!(f, "{} bytes after the end of the zlib stream", bytes),
+            Diagnostic::NotEnoughImageData { expected, actual } => write!(f, "Expected {} bytes of image data, got {}", expected, actual),
+            Diagnostic::TooMuchImageData { expected } => write!(f, "Expected {} bytes of image data, got more", expected),
+            Diagnostic::InvalidFilterType { scanline, filter_type } => write!(f, "Scanline {} has invalid filter type {}", scanline, filter_type),
+        }
+    }
+}
+
+pub struct PNGValidator {
+
+}
+
+impl PNGValidator {
+    pub fn validate_file(&self, path: &str) -> io::Result<Vec<Diagnostic>> {
+        Ok(self.validate_bytes(&fs::read(path)?))
+    }
+
+    /// Checks the signature and chunk framing, then everything `validate` does
+    pub fn validate_bytes(&self, bytes: &[u8]) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+            diagnostics.push(Diagnostic::InvalidMagic);
+            return diagnostics;
+        }
+
+        let scan = scan_chunks(&bytes[MAGIC.len()..]);
+
+        for (index, chunk) in scan.chunks.iter().enumerate() {
+            let name = chunk.chunk_type.name();
+            if name.len() != 4 || !name.bytes().all(|byte| byte.is_ascii_alphabetic()) {
+                diagnostics.push(Diagnostic::InvalidChunkType { chunk: index, name: name.to_string() });
+            }
+        }
+        if scan.truncated {
+            diagnostics.push(Diagnostic::TruncatedChunk { chunk: scan.chunks.len() });
+        }
+        if scan.trailing > 0 {
+            diagnostics.push(Diagnostic::TrailingData { bytes: scan.trailing });
+        }
+
+        let png = PNG { chunks: scan.chunks };
+        diagnostics.extend(self.validate(&png));
+        diagnostics
+    }
+
+    /// Checks chunk ordering, CRCs, header values and the image data stream
+    pub fn validate(&self, png: &PNG) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        self.validate_chunks(png, &mut diagnostics);
+        if let Some(ihdr) = self.validate_ihdr(png, &mut diagnostics) {
+            self.validate_order(png, &ihdr, &mut diagnostics);
+            self.validate_image_data(png, &ihdr, &mut diagnostics);
+        }
+
+        diagnostics
+    }
+
+    fn validate_chunks(&self, png: &PNG, diagnostics: &mut Vec<Diagnostic>) {
+        for (index, chunk) in png.chunks.iter().enumerate() {
+            let name = chunk.chunk_type.name();
+
+            if chunk.length as usize != chunk.data.len() {
+                diagnostics.push(Diagnostic::LengthMismatch { chunk: index, declared: chunk.length, actual: chunk.data.len() });
+            }
+
+            let computed = crc(name.as_bytes(), &chunk.data);
+            if computed != chunk.crc {
+                diagnostics.push(Diagnostic::CrcMismatch { chunk: index, name: name.to_string(), stored: chunk.crc, computed });
+            }
+
+            if chunk.chunk_type.is_critical() && matches!(chunk.chunk_type, ChunkType::Other(_)) {
+                diagnostics.push(Diagnostic::UnknownCriticalChunk { chunk: index, name: name.to_string() });
+            }
+        }
+    }
+
+    fn validate_ihdr(&self, png: &PNG, diagnostics: &mut Vec<Diagnostic>) -> Option<IHDR> {
+        let Some(chunk) = png.chunk(&ChunkType::IHDR) else {
+            diagnostics.push(Diagnostic::MissingIHDR);
+            return None;
+        };
+
+        if png.chunks[0].chunk_type != ChunkType::IHDR {
+            diagnostics.push(Diagnostic::IHDRNotFirst);
+        }
+
+        let invalid = |reason: &str| Diagnostic::InvalidIHDR { reason: reason.to_string() };
+
+        if chunk.data.len() != 13 {
+            diagnostics.push(invalid("length is not 13"));
+            return None;
+        }
+
+        let color_type = chunk.data[9];
+        let bit_depth = chunk.data[8];
+        let Ok(ihdr) = png.ihdr() else {
+            diagnostics.push(Diagnostic::InvalidColorTypeBitDepth { color_type, bit_depth });
+            return None;
+        };
+
+        if ihdr.width == 0 || ihdr.height == 0 || ihdr.width > i32::MAX as u32 || ihdr.height > i32::MAX as u32 {
+            diagnostics.push(invalid("width and height must be between 1 and 2^31 - 1"));
+        }
+        if !ihdr.color_type.allowed_bit_depths().contains(&ihdr.bit_depth) {
+            diagnostics.push(Diagnostic::InvalidColorTypeBitDepth { color_type, bit_depth });
+        }
+        if ihdr.compression_method != 0 {
+            diagnostics.push(invalid("unknown compression method"));
+        }
+        if ihdr.filter_method != 0 {
+            diagnostics.push(invalid("unknown filter method"));
+        }
+        if ihdr.interlace_method > 1 {
+            diagnostics.push(invalid("unknown interlace method"));
+        }
+
+        let valid = diagnostics.iter().all(|diagnostic| !matches!(diagnostic, Diagnostic::InvalidIHDR { .. } | Diagnostic::InvalidColorTypeBitDepth { .. }));
+        if valid { Some(ihdr) } else { None }
+    }
+
+    fn validate_order(&self, png: &PNG, ihdr: &IHDR, diagnostics: &mut Vec<Diagnostic>) {
+        let position = |name: &str| png.chunks.iter().position(|chunk| chunk.chunk_type.is(name));
+        let first_idat = position("IDAT");
+        let plte = position("PLTE");
+        let iend = position("IEND");
+
+        match (ihdr.color_type, plte) {
+            (ColorType::Palette, None) => diagnostics.push(Diagnostic::MissingPLTE),
+            (ColorType::Grayscale | ColorType::GrayscaleAlpha, Some(_)) => diagnostics.push(Diagnostic::UnexpectedPLTE),
+            _ => {}
+        }
+
+        if let Some(plte) = plte {
+            let data = &png.chunks[plte].data;
+            let max_entries = if ihdr.color_type == ColorType::Palette { 1 << ihdr.bit_depth } else { 256 };
+            if !data.len().is_multiple_of(3) || data.is_empty() || data.len() / 3 > max_entries {
+                diagnostics.push(Diagnostic::InvalidPLTE { entries: data.len() / 3 });
+            }
+        }
+
+        if first_idat.is_none() {
+            diagnostics.push(Diagnostic::MissingIDAT);
+        }
+        if iend.is_none() {
+            diagnostics.push(Diagnostic::MissingIEND);
+        }
+
+        let mut seen: Vec<&str> = Vec::new();
+        let mut previous_idat = None;
+
+        for (index, chunk) in png.chunks.iter().enumerate() {
+            let name = chunk.chunk_type.name();
+            let misplaced = |reason| Diagnostic::MisplacedChunk { chunk: index, name: name.to_string(), reason };
+
+            if iend.is_some_and(|iend| index > iend) {
+                diagnostics.push(Diagnostic::ChunkAfterIEND { chunk: index });
+                continue;
+            }
+
+            match name {
+                "IDAT" => {
+                    if previous_idat.is_some_and(|previous| previous + 1 != index) {
+                        diagnostics.push(Diagnostic::NonContiguousIDAT { chunk: index });
+                    }
+                    previous_idat = Some(index);
+                }
+                "IEND" if !chunk.data.is_empty() => diagnostics.push(Diagnostic::NonEmptyIEND),
+                "PLTE" if first_idat.is_some_and(|idat| index > idat) => diagnostics.push(misplaced("before IDAT")),
+                _ => {}
+            }
+
+            if BEFORE_PLTE.contains(&name) && plte.is_some_and(|plte| index > plte) {
+                diagnostics.push(misplaced("before PLTE"));
+            }
+            if AFTER_PLTE.contains(&name) && plte.is_some_and(|plte| index < plte) {
+                diagnostics.push(misplaced("after PLTE"));
+            }
+            if (BEFORE_PLTE.contains(&name) || AFTER_PLTE.contains(&name) || name == "pHYs") && first_idat.is_some_and(|idat| index > idat) {
+                diagnostics.push(misplaced("before IDAT"));
+            }
+
+            let multiple_allowed = matches!(name, "IDAT" | "sPLT" | "tEXt" | "zTXt" | "iTXt");
+            if !multiple_allowed && seen.contains(&name) {
+                diagnostics.push(Diagnostic::DuplicateChunk { chunk: index, name: name.to_string() });
+            }
+            seen.push(name);
+        }
+    }
+
+    fn validate_image_data(&self, png: &PNG, ihdr: &IHDR, diagnostics: &mut Vec<Diagnostic>) {
+        let compressed: Vec<u8> = png.chunks.iter()
+            .filter(|chunk| chunk.chunk_type == ChunkType::IDAT)
+            .flat_map(|chunk| chunk.data.iter().copied())
+            .collect();
+        if compressed.is_empty() {
+            return;
+        }
+
+        let reader = PNGReader {};
+        let expected = reader.image_data_size(ihdr);
+
+        // Inflating more than the scanlines need would let a small zlib stream exhaust the memory
+        let inflated = inflate(&compressed, expected.saturating_add(1));
+        let data = inflated.data;
+        match inflated.error {
+            Some(message) => diagnostics.push(Diagnostic::ZlibError { message }),
+            None if data.len() <= expected && inflated.consumed < compressed.len() => {
+                diagnostics.push(Diagnostic::TrailingZlibData { bytes: compressed.len() - inflated.consumed });
+            }
+            None => {}
+        }
+
+        if data.len() < expected {
+            diagnostics.push(Diagnostic::NotEnoughImageData { expected, actual: data.len() });
+        } else if data.len() > expected {
+            diagnostics.push(Diagnostic::TooMuchImageData { expected });
+        }
+
+        let passes: Vec<(usize, usize)> = reader.passes(ihdr).iter()
+            .map(|&(.., pass_width, pass_height)| (pass_width, pass_height))
+            .collect();
+
+        let mut offset = 0;
+        let mut scanline = 0;
+        for (pass_width, pass_height) in passes {
+            for _ in 0..pass_height {
+                if offset >= data.len() {
+                    return;
+                }
+                if data[offset] > 4 {
+                    diagnostics.push(Diagnostic::InvalidFilterType { scanline, filter_type: data[offset] });
+                }
+                offset += 1 + ihdr.scanline_length(pass_width);
+                scanline += 1;
+            }
+        }
+    }
+}
@@ -1,31 +1,322 @@
-use std::io::Write;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
 
-use crc::{Crc, CRC_32_ISO_HDLC};
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
 
 use crate::{common::*, png::{ihdr::{ColorType, IHDR}, Chunk, ChunkType, PNG, paeth_predictor}};
+use crate::png::plte::PLTE;
+use crate::png::trns::TRNS;
+use crate::png_reader::scale_sample;
 use crate::binary_serializable::BinarySerializable;
+use crate::zopfli;
 
+#[derive(Clone)]
 pub struct Settings {
+    /// Ignored for `ColorMode::Auto`, which also picks the bit depth
     pub bit_depth: u8,
-    pub color_type: ColorType,
+    pub color_type: ColorMode,
     pub interlace_method: u8,
+    pub filter: FilterStrategy,
+    pub compression_level: u32,
+    pub deflate: Deflate,
+    pub conversion: Conversion,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            bit_depth: 8,
+            color_type: ColorMode::Fixed(ColorType::RGBA),
+            interlace_method: 0,
+            filter: FilterStrategy::BruteForce,
+            compression_level: 6,
+            deflate: Deflate::Flate2,
+            conversion: Conversion::default(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Smallest color type and bit depth representing the image losslessly
+    Auto,
+    Fixed(ColorType),
+}
+
+impl From<ColorType> for ColorMode {
+    fn from(color_type: ColorType) -> Self {
+        ColorMode::Fixed(color_type)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Deflate {
+    /// flate2 at the compression level
+    #[default]
+    Flate2,
+    /// Much slower iterative optimal parsing giving smaller output, the compression level is ignored
+    Zopfli { iterations: u32 },
+}
+
+/// What to do with alpha when the color type has no alpha channel
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum AlphaConversion {
+    /// Keep the color as is and drop alpha
+    #[default]
+    Discard,
+    /// Composite over the background color, e.g. the one from `PNG::background`
+    Flatten(Color),
+}
+
+/// How colors are converted to a color type or bit depth not able to hold them exactly
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Conversion {
+    pub luminance: Luminance,
+    pub alpha: AlphaConversion,
+    /// Fail with an error instead of converting lossily
+    pub strict: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FilterStrategy {
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+    /// Per scanline filter with the minimum sum of absolute differences
+    MinSum,
+    /// Per scanline filter giving the smallest compressed scanline
+    BruteForce,
+}
+
+impl FilterStrategy {
+    pub const ALL: [FilterStrategy; 7] = [
+        FilterStrategy::None,
+        FilterStrategy::Sub,
+        FilterStrategy::Up,
+        FilterStrategy::Average,
+        FilterStrategy::Paeth,
+        FilterStrategy::MinSum,
+        FilterStrategy::BruteForce,
+    ];
 }
 
 pub struct PNGWriter {
     pub settings: Settings
 }
 
+/// Packs samples of the given bit depth into bytes, most significant bits first
+pub(crate) fn pack_samples(samples: &[u16], bit_depth: u8) -> Vec<u8> {
+    match bit_depth {
+        16 => samples.iter().flat_map(|sample| sample.to_be_bytes()).collect(),
+        8 => samples.iter().map(|&sample| sample as u8).collect(),
+        _ => {
+            let mut packed = vec![0u8; (samples.len() * bit_depth as usize).div_ceil(8)];
+            for (i, &sample) in samples.iter().enumerate() {
+                let bit = i * bit_depth as usize;
+                packed[bit / 8] |= (sample as u8) << (8 - bit_depth as usize - bit % 8);
+            }
+            packed
+        }
+    }
+}
+
+/// Scales an 8 bit value to a sample of the given bit depth
+pub(crate) fn to_sample(value: u8, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 => value as u16 * 257,
+        8 => value as u16,
+        _ => value as u16 >> (8 - bit_depth),
+    }
+}
+
+/// Packs a row of colors into a scanline, `palette` maps colors to indices for the Palette color type
+pub(crate) fn pack_scanline(row: &[Color], color_type: ColorType, bit_depth: u8, palette: Option<&HashMap<Color, u8>>) -> Vec<u8> {
+    let sample = |value: u8| to_sample(value, bit_depth);
+
+    let samples: Vec<u16> = row.iter().flat_map(|color|
+        match color_type {
+            ColorType::Grayscale      => vec![sample(color.r)],
+            ColorType::RGB            => vec![sample(color.r), sample(color.g), sample(color.b)],
+            ColorType::Palette        => vec![palette.expect("Palette is required for Palette color type")[color] as u16],
+            ColorType::GrayscaleAlpha => vec![sample(color.r), sample(color.a)],
+            ColorType::RGBA           => vec![sample(color.r), sample(color.g), sample(color.b), sample(color.a)],
+        }
+    ).collect();
+
+    pack_samples(&samples, bit_depth)
+}
+
+/// Unique colors of the image with translucent ones first, so the tRNS chunk stays short
+pub(crate) fn palette(image: &Image, max_colors: usize) -> Option<Vec<Color>> {
+    let mut seen = HashSet::new();
+    let mut colors = Vec::new();
+
+    for color in image.pixels.iter().flatten() {
+        if seen.insert(*color) {
+            if colors.len() == max_colors {
+                return None;
+            }
+            colors.push(*color);
+        }
+    }
+
+    colors.sort_by_key(|color| color.a == 255);
+    Some(colors)
+}
+
+/// Properties of the image deciding which color types can store it losslessly
+pub(crate) struct ColorAnalysis {
+    pub opaque: bool,
+    pub gray: bool,
+    /// Fully transparent color replaceable by a tRNS color key
+    pub key: Option<Color>,
+    pub gray_bit_depth: u8,
+    pub palette: Option<Vec<Color>>,
+}
+
+impl ColorAnalysis {
+    pub fn new(image: &Image) -> ColorAnalysis {
+        let pixels = || image.pixels.iter().flatten();
+        let opaque = pixels().all(|color| color.a == 255);
+        let gray = pixels().all(|color| color.r == color.g && color.g == color.b);
+        let key = if opaque { None } else { Self::color_key(image) };
+
+        ColorAnalysis {
+            opaque,
+            gray,
+            key,
+            gray_bit_depth: Self::gray_bit_depth(pixels().map(|color| color.r)),
+            palette: palette(image, 256),
+        }
+    }
+
+    /// Smallest grayscale bit depth representing all the values exactly
+    fn gray_bit_depth(values: impl Iterator<Item = u8> + Clone) -> u8 {
+        [1, 2, 4].into_iter()
+            .find(|&depth| values.clone().all(|value| scale_sample(to_sample(value, depth), depth) == value))
+            .unwrap_or(8)
+    }
+
+    /// Color key exists if all transparency is binary and transparent pixels share a color unused by opaque ones
+    fn color_key(image: &Image) -> Option<Color> {
+        let mut key = None;
+
+        for color in image.pixels.iter().flatten().filter(|color| color.a != 255) {
+            if color.a != 0 || key.is_some_and(|key| key != *color) {
+                return None;
+            }
+            key = Some(*color);
+        }
+
+        let key = key?;
+        let opaque_match = image.pixels.iter().flatten().any(|color| color.r == key.r && color.g == key.g && color.b == key.b && color.a == 255);
+        if opaque_match { None } else { Some(key) }
+    }
+
+    pub fn palette_bit_depth(colors: &[Color]) -> u8 {
+        match colors.len() {
+            0..=2 => 1,
+            3..=4 => 2,
+            5..=16 => 4,
+            _ => 8,
+        }
+    }
+
+    /// Transparency chunk for a color key at the given color type and bit depth
+    pub fn key_transparency(&self, color_type: ColorType, bit_depth: u8) -> Option<TRNS> {
+        let key = self.key?;
+        let sample = |value: u8| to_sample(value, bit_depth);
+
+        match color_type {
+            ColorType::Grayscale => Some(TRNS::Gray(sample(key.r))),
+            ColorType::RGB => Some(TRNS::RGB(sample(key.r), sample(key.g), sample(key.b))),
+            _ => None,
+        }
+    }
+
+    /// Color type and bit depth giving the fewest bits per pixel
+    pub fn color_type(&self) -> (ColorType, u8) {
+        let palette_bit_depth = self.palette.as_deref().map(Self::palette_bit_depth);
+
+        if self.gray && (self.opaque || self.key.is_some()) && palette_bit_depth.is_none_or(|depth| self.gray_bit_depth <= depth) {
+            (ColorType::Grayscale, self.gray_bit_depth)
+        } else if let Some(depth) = palette_bit_depth {
+            (ColorType::Palette, depth)
+        } else if self.gray && self.key.is_none() {
+            (ColorType::GrayscaleAlpha, 8)
+        } else if self.opaque || self.key.is_some() {
+            (ColorType::RGB, 8)
+        } else {
+            (ColorType::RGBA, 8)
+        }
+    }
+}
+
+/// PLTE and optional tRNS chunks for the palette
+pub(crate) fn palette_chunks(colors: &[Color]) -> Vec<Chunk> {
+    let plte = PLTE { colors: colors.iter().map(|color| Color::from_rgb(color.r, color.g, color.b)).collect() };
+    let mut chunks = vec![Chunk::new(ChunkType::PLTE, plte.to_data())];
+
+    let translucent = colors.iter().take_while(|color| color.a != 255).count();
+    if translucent > 0 {
+        let trns = TRNS::Palette(colors[..translucent].iter().map(|color| color.a).collect());
+        chunks.push(Chunk::new(ChunkType::Other("tRNS".to_string()), trns.to_data()));
+    }
+
+    chunks
+}
+
 impl PNGWriter {
-    fn crc(&self, chunk_type: &[u8], chunk_data: &[u8]) -> u32 {
-        let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC);
-        let mut digest = crc.digest();
-        
-        digest.update(chunk_type);
-        digest.update(chunk_data);
-        
-        digest.finalize()
+    fn lossy_error(&self, message: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, format!("Lossy conversion: {}", message))
+    }
+
+    /// Converts colors to fit the color type and bit depth according to the conversion settings
+    fn convert(&self, image: &Image, color_type: ColorType, bit_depth: u8) -> io::Result<Vec<Vec<Color>>> {
+        let conversion = &self.settings.conversion;
+        let has_alpha = matches!(color_type, ColorType::GrayscaleAlpha | ColorType::RGBA | ColorType::Palette);
+        let is_gray = matches!(color_type, ColorType::Grayscale | ColorType::GrayscaleAlpha);
+
+        let mut pixels = Vec::with_capacity(image.height());
+        for row in image.pixels.iter() {
+            let mut converted = Vec::with_capacity(row.len());
+
+            for &color in row.iter() {
+                let mut color = color;
+
+                if !has_alpha && color.a != 255 {
+                    if conversion.strict {
+                        return Err(self.lossy_error("translucent pixel in a color type without alpha"));
+                    }
+                    color = match conversion.alpha {
+                        AlphaConversion::Discard => Color { a: 255, ..color },
+                        AlphaConversion::Flatten(background) => color.flatten(background),
+                    };
+                }
+
+                if is_gray && !color.is_gray() {
+                    if conversion.strict {
+                        return Err(self.lossy_error("colored pixel in a grayscale color type"));
+                    }
+                    let gray = color.luminance(conversion.luminance);
+                    color = Color::new(gray, gray, gray, color.a);
+                }
+
+                if conversion.strict && bit_depth < 8 && color_type != ColorType::Palette
+                    && [color.r, color.g, color.b, color.a].iter().any(|&value| scale_sample(to_sample(value, bit_depth), bit_depth) != value) {
+                    return Err(self.lossy_error("sample not representable at the bit depth"));
+                }
+
+                converted.push(color);
+            }
+
+            pixels.push(converted);
+        }
+
+        Ok(pixels)
     }
 
     fn filter_scanline(&self, filter_type: u8, scanline: &[u8], prev_scanline: &[u8], bpp: usize) -> Vec<u8> {
@@ -68,29 +359,102 @@ impl PNGWriter {
     }
 
     fn compressed_data_len(&self, data: &[u8]) -> usize {
-        let mut encoder: ZlibEncoder<Vec<u8>> = ZlibEncoder::new(Vec::new(), Compression::default());
+        let mut encoder: ZlibEncoder<Vec<u8>> = ZlibEncoder::new(Vec::new(), Compression::new(self.settings.compression_level));
         encoder.write_all(data).expect("Failed to write data");
         let compressed_data = encoder.finish().expect("Failed to finish compression");
         compressed_data.len()
     }
-}
 
-impl Writer for PNGWriter {
-    fn extension(&self) -> &str {
-        "png"
+    /// Filters scanlines according to the filter strategy, prepending each with its filter type
+    pub(crate) fn filter_scanlines(&self, scanlines: &[Vec<u8>], bpp: usize) -> Vec<u8> {
+        let mut filtered = Vec::<u8>::with_capacity(scanlines.iter().map(|row| row.len() + 1).sum());
+
+        for (i, row) in scanlines.iter().enumerate() {
+            let prev_row = if i == 0 {
+                vec![0; row.len()]
+            } else {
+                scanlines[i-1].to_vec()
+            };
+
+            let (filter_type, filtered_row) = match self.settings.filter {
+                FilterStrategy::None    => (0, self.filter_scanline(0, row, &prev_row, bpp)),
+                FilterStrategy::Sub     => (1, self.filter_scanline(1, row, &prev_row, bpp)),
+                FilterStrategy::Up      => (2, self.filter_scanline(2, row, &prev_row, bpp)),
+                FilterStrategy::Average => (3, self.filter_scanline(3, row, &prev_row, bpp)),
+                FilterStrategy::Paeth   => (4, self.filter_scanline(4, row, &prev_row, bpp)),
+                FilterStrategy::MinSum => {
+                    let sum = |row: &[u8]| row.iter().map(|&byte| (byte as i8).unsigned_abs() as u64).sum::<u64>();
+                    (0..=4)
+                        .map(|filter_type| (filter_type, self.filter_scanline(filter_type, row, &prev_row, bpp)))
+                        .min_by_key(|(_, filtered_row)| sum(filtered_row))
+                        .unwrap()
+                }
+                FilterStrategy::BruteForce => {
+                    let mut best_filter = 0;
+                    let mut best_filtered_row = self.filter_scanline(0, row, &prev_row, bpp);
+                    let mut best_compressed_size = self.compressed_data_len(&best_filtered_row);
+
+                    for filter_type in 1..=4 {
+                        let filtered_row = self.filter_scanline(filter_type, row, &prev_row, bpp);
+                        let compressed_size = self.compressed_data_len(&filtered_row);
+                        if compressed_size < best_compressed_size {
+                            best_filter = filter_type;
+                            best_filtered_row = filtered_row;
+                            best_compressed_size = compressed_size;
+                        }
+                    }
+
+                    (best_filter, best_filtered_row)
+                }
+            };
+
+            filtered.push(filter_type);
+            filtered.extend(filtered_row);
+        }
+
+        filtered
     }
 
-    fn write(&self, image: Image, path: &str) {
-        println!("Writing PNG file at path: {}", path);
+    pub(crate) fn compress(&self, data: &[u8]) -> Vec<u8> {
+        if let Deflate::Zopfli { iterations } = self.settings.deflate {
+            return zopfli::compress(data, iterations);
+        }
 
+        let mut compressed = Vec::<u8>::new();
+        let mut zlibencoder = ZlibEncoder::new(&mut compressed, Compression::new(self.settings.compression_level));
+        zlibencoder.write_all(data).expect("Can't encode");
+        zlibencoder.finish().expect("Can't finish");
+        compressed
+    }
+
+    pub fn encode(&self, image: &Image) -> io::Result<PNG> {
         let width = image.width();
         let height = image.height();
 
+        let mut transparency = None;
+        let (color_type, bit_depth) = match self.settings.color_type {
+            ColorMode::Fixed(color_type) => (color_type, self.settings.bit_depth),
+            ColorMode::Auto => {
+                let analysis = ColorAnalysis::new(image);
+                let (color_type, bit_depth) = analysis.color_type();
+                transparency = analysis.key_transparency(color_type, bit_depth);
+                (color_type, bit_depth)
+            }
+        };
+
+        if !color_type.allowed_bit_depths().contains(&bit_depth) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Bit depth {} is not allowed for {:?}", bit_depth, color_type)));
+        }
+
+        if self.settings.interlace_method == 1 {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "Interlaced PNGs are not supported yet"));
+        }
+
         let ihdr = IHDR {
             width: width as u32,
             height: height as u32,
-            bit_depth: self.settings.bit_depth,
-            color_type: self.settings.color_type,
+            bit_depth,
+            color_type,
             compression_method: 0,
             filter_method: 0,
             interlace_method: self.settings.interlace_method,
@@ -98,84 +462,52 @@ impl Writer for PNGWriter {
 
         let mut ihdr_data: Vec<u8> = Vec::new();
         ihdr.write(&mut ihdr_data).unwrap();
+        let mut chunks = vec![Chunk::new(ChunkType::IHDR, ihdr_data)];
 
-        let ihdr_crc = self.crc(b"IHDR", &ihdr_data); 
-        let ihdr_chunk = Chunk {
-            length: 13,
-            chunk_type: ChunkType::IHDR,
-            data: ihdr_data,
-            crc: ihdr_crc,
+        // Automatically selected color types hold the image as is
+        let converted;
+        let image = match self.settings.color_type {
+            ColorMode::Fixed(_) => {
+                converted = Image::from_mat(width, height, self.convert(image, color_type, bit_depth)?);
+                &converted
+            }
+            ColorMode::Auto => image,
         };
 
-        let pixels_data: Vec<Vec<u8>> = image.pixels.iter().map(|row|
-            row.iter().flat_map(|color|
-                return match self.settings.color_type {
-                    ColorType::Grayscale      => vec![color.r],
-                    ColorType::RGB            => vec![color.r, color.g, color.b],
-                    ColorType::Palette        => unimplemented!("Palette color type is not supported yet"),
-                    ColorType::GrayscaleAlpha => vec![color.r, color.a],
-                    ColorType::RGBA           => vec![color.r, color.g, color.b, color.a],
-                }
-            ).collect::<Vec<u8>>()
-        ).collect::<Vec<Vec<u8>>>();
+        let mut palette_indices = None;
+        if color_type == ColorType::Palette {
+            let colors = palette(image, 1 << bit_depth)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Too many colors for the palette bit depth"))?;
 
-        let bpp = ihdr.bytes_per_pixel();
-        let mut finilized = Vec::<u8>::with_capacity((image.width() + 1) * image.height() * bpp); // E.g. filtered and optionally interlaced
-        if ihdr.interlace_method == 1 {
-            panic!("Interlaced PNGs are not supported yet");
-        } else {
-            for (i, row) in pixels_data.iter().enumerate() {                
-                let prev_row = if i == 0 {
-                    vec![0; row.len()]
-                } else {
-                    pixels_data[i-1].to_vec()
-                };
-        
-                let mut best_filter = 0;
-                let mut best_filtered_row = self.filter_scanline(0, row, &prev_row, bpp);
-                let mut best_compressed_size = self.compressed_data_len(&best_filtered_row);
-        
-                for filter_type in 1..=4 {
-                    let filtered_row = self.filter_scanline(filter_type, row, &prev_row, bpp);
-                    let compressed_size = self.compressed_data_len(&filtered_row);
-                    if compressed_size < best_compressed_size {
-                        best_filter = filter_type;
-                        best_filtered_row = filtered_row;
-                        best_compressed_size = compressed_size;
-                    }
-                }
-        
-                finilized.push(best_filter as u8);
-                finilized.extend(best_filtered_row);
-            }
+            chunks.extend(palette_chunks(&colors));
+            palette_indices = Some(colors.iter().enumerate().map(|(i, color)| (*color, i as u8)).collect::<HashMap<_, _>>());
         }
 
-        let mut compressed = Vec::<u8>::new();
-        let mut zlibencoder = ZlibEncoder::new(&mut compressed, Compression::default());
-        zlibencoder.write_all(&finilized).expect("Can't decode");
-        zlibencoder.finish().expect("Can't finish");
+        if let Some(trns) = transparency {
+            chunks.push(Chunk::new(ChunkType::Other("tRNS".to_string()), trns.to_data()));
+        }
 
-        let idat = compressed;
-        let idat_crc = self.crc(b"IDAT", &idat); 
-        let idat_chunk = Chunk {
-            length: idat.len() as u32,
-            chunk_type: ChunkType::IDAT,
-            data: idat,
-            crc: idat_crc,
-        };
+        let pixels_data: Vec<Vec<u8>> = image.pixels.iter()
+            .map(|row| pack_scanline(row, color_type, bit_depth, palette_indices.as_ref()))
+            .collect();
 
-        let iend_crc = self.crc(b"IEND", &[]); 
-        let iend_chunk = Chunk {
-            length: 0,
-            chunk_type: ChunkType::IEND,
-            data: vec![],
-            crc: iend_crc,
-        };
-        
-        let png = PNG {
-            chunks: vec![ihdr_chunk, idat_chunk, iend_chunk],
-        };
+        let finilized = self.filter_scanlines(&pixels_data, ihdr.bytes_per_pixel()); // E.g. filtered and optionally interlaced
+        chunks.push(Chunk::new(ChunkType::IDAT, self.compress(&finilized)));
+        chunks.push(Chunk::new(ChunkType::IEND, vec![]));
 
+        Ok(PNG { chunks })
+    }
+}
+
+impl Writer for PNGWriter {
+    fn extension(&self) -> &str {
+        "png"
+    }
+
+    fn write(&self, image: Image, path: &str) {
+        println!("Writing PNG file at path: {}", path);
+
+        let png = self.encode(&image).expect("Can't encode PNG");
         png.to_file(path)
     }
-}
\ No newline at end of file
+}
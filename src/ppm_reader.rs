@@ -1,49 +1,47 @@
-use std::fs::read_to_string;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
 use crate::common::*;
+use crate::netpbm::{self, Encoding, Tokenizer};
+
+pub use crate::netpbm::Images;
 
 pub struct PPMReader {
 
 }
 
+impl PPMReader {
+    fn decode(&self, tokenizer: &mut Tokenizer) -> io::Result<Image> {
+        let header = netpbm::read_header(tokenizer, true)?;
+        let encoding = match header.magic.as_str() {
+            "P3" => Encoding::ASCII,
+            "P6" => Encoding::Binary,
+            _ => return Err(netpbm::invalid_data("Invalid PPM file")),
+        };
+
+        let (width, height) = (header.width, header.height);
+        let samples = netpbm::read_samples(tokenizer, encoding, netpbm::sample_count(width, height, 3)?, header.max_value)?;
+
+        let pixels = samples.chunks_exact(3)
+            .map(|rgb| Color::from_rgb(rgb[0], rgb[1], rgb[2]))
+            .collect::<Vec<Color>>()
+            .chunks(width.max(1))
+            .map(<[Color]>::to_vec)
+            .collect();
+
+        Ok(Image::from_mat(width, height, pixels))
+    }
+
+    /// Reads all images concatenated in the stream
+    pub fn images<'a, R: BufRead + 'a>(&self, stream: R) -> Images<'a> {
+        Images::new(stream, |tokenizer| PPMReader {}.decode(tokenizer))
+    }
+}
+
 impl Reader for PPMReader {
-    fn read(&self, path: &str) -> std::io::Result<Image> {
+    fn read(&self, path: &str) -> io::Result<Image> {
         println!("Reading PPM file");
 
-        let content = read_to_string(path)?;
-
-        let mut lines = content.lines();
-        let p3 = lines.next().unwrap();
-        if p3 != "P3" {
-            return Result::Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid PPM file"));
-        }
-
-        let mut size_str = lines.next().unwrap().split_whitespace();
-        let (width, height) = (size_str.next().unwrap().parse().unwrap(), size_str.next().unwrap().parse().unwrap());
-        let _max_value: usize = lines.next().unwrap().parse().unwrap();
-
-        let mut pixels = Vec::with_capacity(height);
-        let mut pixel_str = lines.next().unwrap().split_whitespace();
-        for _ in 0..height {
-            let mut row = Vec::with_capacity(width);
-            for _ in 0..width {
-                let r = match pixel_str.next() { // Pixels data can be placed not in width len on line
-                    Some(value) => value.parse().unwrap(),
-                    None => {
-                        pixel_str = match lines.next() {
-                            Some(line) => line.split_whitespace(),
-                            None => panic!("Not enough data"),
-                        };
-                        pixel_str.next().unwrap().parse().unwrap()
-                    }
-                };
-
-                let g = pixel_str.next().unwrap().parse().unwrap();
-                let b = pixel_str.next().unwrap().parse().unwrap();
-                row.push(Color::from_rgb(r, g, b));
-            }
-            pixels.push(row);
-        }
-
-        Result::Ok(Image::from_mat(width, height, pixels))
+        self.decode(&mut Tokenizer::new(BufReader::new(File::open(path)?)))
     }
-}
\ No newline at end of file
+}
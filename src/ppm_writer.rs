@@ -1,39 +1,78 @@
 use std::fs;
+use std::io::{self, Write};
 
 use crate::common::*;
+use crate::netpbm;
 
-pub struct PPMWriter {
+pub use crate::netpbm::Encoding;
 
+#[derive(Default)]
+pub struct Settings {
+    pub encoding: Encoding,
 }
 
-impl Writer for PPMWriter {
-    fn extension(&self) -> &str {
-        "ppm"
-    }
-
-    fn write(&self, image: Image, path: &str) {
-        println!("Writing PPM file at path: {}", path);
+pub struct PPMWriter {
+    pub settings: Settings
+}
 
+impl PPMWriter {
+    fn write_ascii(&self, image: &Image) -> Vec<u8> {
         let header_size = 2 + 3 + 3;
         let data_size = image.width() * image.height() * 3 * 4;
         let mut data = String::with_capacity(header_size + data_size);
         
         data.push_str("P3\n");
         data.push_str((image.width().to_string() + " " + image.height().to_string().as_str() + "\n").as_str());
-        data.push_str((std::u8::MAX.to_string() + "\n").as_str());
+        data.push_str((u8::MAX.to_string() + "\n").as_str());
 
         for i in 0..image.height() {
             for j in 0..image.width() {
                 data.push_str(image.pixels[i][j].r.to_string().as_str());
-                data.push_str(" ");
+                data.push(' ');
                 data.push_str(image.pixels[i][j].g.to_string().as_str());
-                data.push_str(" ");
+                data.push(' ');
                 data.push_str(image.pixels[i][j].b.to_string().as_str());
-                data.push_str(" ");
+                data.push(' ');
             }
-            data.push_str("\n");
+            data.push('\n');
+        }
+
+        data.into_bytes()
+    }
+
+    fn write_binary(&self, image: &Image) -> Vec<u8> {
+        let header = netpbm::header("P6", image.width(), image.height(), Some(u8::MAX as usize));
+        let mut data = Vec::with_capacity(header.len() + image.width() * image.height() * 3);
+
+        data.extend_from_slice(header.as_bytes());
+        for color in image.pixels.iter().flatten() {
+            data.extend_from_slice(&[color.r, color.g, color.b]);
+        }
+
+        data
+    }
+
+    pub fn encode(&self, image: &Image) -> Vec<u8> {
+        match self.settings.encoding {
+            Encoding::ASCII => self.write_ascii(image),
+            Encoding::Binary => self.write_binary(image),
         }
+    }
+
+    /// Appends the image to a stream of concatenated images
+    pub fn write_frame<W: Write>(&self, image: &Image, stream: &mut W) -> io::Result<()> {
+        stream.write_all(&self.encode(image))
+    }
+}
+
+impl Writer for PPMWriter {
+    fn extension(&self) -> &str {
+        "ppm"
+    }
 
-        fs::write(path, data).expect("Can't save output PPM file");
+    fn write(&self, image: Image, path: &str) {
+        println!("Writing PPM file at path: {}", path);
+
+        fs::write(path, self.encode(&image)).expect("Can't save output PPM file");
     }
-}
\ No newline at end of file
+}
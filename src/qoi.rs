@@ -0,0 +1,92 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::binary_serializable::BinarySerializable;
+use crate::common::Color;
+
+pub const MAGIC: [u8; 4] = *b"qoif";
+
+/// Seven zero bytes followed by 1
+pub const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+pub(crate) const OP_INDEX: u8 = 0x00;
+pub(crate) const OP_DIFF: u8 = 0x40;
+pub(crate) const OP_LUMA: u8 = 0x80;
+pub(crate) const OP_RUN: u8 = 0xC0;
+pub(crate) const OP_RGB: u8 = 0xFE;
+pub(crate) const OP_RGBA: u8 = 0xFF;
+pub(crate) const MASK_2: u8 = 0xC0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum Channels {
+    RGB = 3,
+    #[default]
+    RGBA = 4,
+}
+
+/// Informative only, samples are stored as they are
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum ColorSpace {
+    /// sRGB with linear alpha
+    #[default]
+    SRGB = 0,
+    /// All channels linear
+    Linear = 1,
+}
+
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub width: u32,
+    pub height: u32,
+    pub channels: Channels,
+    pub color_space: ColorSpace,
+}
+
+impl Header {
+    pub const SIZE: usize = 14;
+}
+
+impl BinarySerializable for Header {
+    fn read<R: Read>(reader: &mut R) -> io::Result<Self> where Self: Sized {
+        let invalid_data = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid_data("Invalid QOI file"));
+        }
+
+        let width = reader.read_u32::<BigEndian>()?;
+        let height = reader.read_u32::<BigEndian>()?;
+        let channels = match reader.read_u8()? {
+            3 => Channels::RGB,
+            4 => Channels::RGBA,
+            _ => return Err(invalid_data("Invalid QOI channels")),
+        };
+        let color_space = match reader.read_u8()? {
+            0 => ColorSpace::SRGB,
+            1 => ColorSpace::Linear,
+            _ => return Err(invalid_data("Invalid QOI color space")),
+        };
+
+        Ok(Header { width, height, channels, color_space })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_u32::<BigEndian>(self.width)?;
+        writer.write_u32::<BigEndian>(self.height)?;
+        writer.write_u8(self.channels as u8)?;
+        writer.write_u8(self.color_space as u8)?;
+
+        Ok(())
+    }
+}
+
+/// Position of the color in the array of previously seen colors
+pub(crate) fn hash(color: Color) -> usize {
+    (color.r as usize * 3 + color.g as usize * 5 + color.b as usize * 7 + color.a as usize * 11) % 64
+}
@@ -0,0 +1,78 @@
+use std::fs;
+use std::io::{self, Cursor};
+
+use crate::binary_serializable::BinarySerializable;
+use crate::common::*;
+use crate::qoi::{self, Header};
+
+pub struct QOIReader {
+
+}
+
+impl QOIReader {
+    pub fn decode(&self, data: &[u8]) -> io::Result<Image> {
+        let header = Header::read(&mut Cursor::new(data))?;
+        let (width, height) = (header.width as usize, header.height as usize);
+        let pixel_count = width.checked_mul(height)
+            .filter(|&count| count <= (data.len() - Header::SIZE) * 62)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid QOI size"))?;
+
+        let mut index = [Color::new(0, 0, 0, 0); 64];
+        let mut color = Color::black();
+        let mut colors = Vec::with_capacity(pixel_count);
+        let mut position = Header::SIZE;
+
+        let mut next = || {
+            let byte = data.get(position).copied();
+            position += 1;
+            byte.ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete QOI data"))
+        };
+
+        while colors.len() < pixel_count {
+            let op = next()?;
+
+            match op {
+                qoi::OP_RGB => color = Color::new(next()?, next()?, next()?, color.a),
+                qoi::OP_RGBA => color = Color::new(next()?, next()?, next()?, next()?),
+                _ => match op & qoi::MASK_2 {
+                    qoi::OP_INDEX => color = index[op as usize],
+                    qoi::OP_DIFF => {
+                        color.r = color.r.wrapping_add((op >> 4) & 0x03).wrapping_sub(2);
+                        color.g = color.g.wrapping_add((op >> 2) & 0x03).wrapping_sub(2);
+                        color.b = color.b.wrapping_add(op & 0x03).wrapping_sub(2);
+                    },
+                    qoi::OP_LUMA => {
+                        let second = next()?;
+                        let dg = (op & 0x3F).wrapping_sub(32);
+                        color.r = color.r.wrapping_add(dg).wrapping_add(second >> 4).wrapping_sub(8);
+                        color.g = color.g.wrapping_add(dg);
+                        color.b = color.b.wrapping_add(dg).wrapping_add(second & 0x0F).wrapping_sub(8);
+                    },
+                    _ => {
+                        // A run at the start repeats the initial color, which also goes into the index
+                        index[qoi::hash(color)] = color;
+                        let run = (op & 0x3F) as usize + 1;
+                        colors.extend(std::iter::repeat_n(color, run.min(pixel_count - colors.len())));
+                        continue;
+                    },
+                },
+            }
+
+            index[qoi::hash(color)] = color;
+            colors.push(color);
+        }
+
+        let pixels = colors.chunks(width.max(1)).map(<[Color]>::to_vec).collect();
+
+        Ok(Image::from_mat(width, height, pixels))
+    }
+}
+
+impl Reader for QOIReader {
+    fn read(&self, path: &str) -> io::Result<Image> {
+        println!("Reading QOI file");
+
+        let data = fs::read(path)?;
+        self.decode(&data)
+    }
+}
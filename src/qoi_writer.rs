@@ -0,0 +1,142 @@
+use std::fs;
+use std::io::{self, Write};
+
+use crate::binary_serializable::BinarySerializable;
+use crate::common::*;
+use crate::qoi::{self, Header};
+
+pub use crate::qoi::{Channels, ColorSpace};
+
+#[derive(Default)]
+pub struct Settings {
+    pub channels: Channels,
+    pub color_space: ColorSpace,
+}
+
+pub struct QOIWriter {
+    pub settings: Settings
+}
+
+/// Encodes pixels as they arrive, in rows from top to bottom
+pub struct QOIEncoder<W: Write> {
+    writer: W,
+    channels: Channels,
+    remaining: u64,
+    index: [Color; 64],
+    previous: Color,
+    run: u8,
+}
+
+impl<W: Write> QOIEncoder<W> {
+    /// Writes the header
+    pub fn new(mut writer: W, width: u32, height: u32, channels: Channels, color_space: ColorSpace) -> io::Result<Self> {
+        Header { width, height, channels, color_space }.write(&mut writer)?;
+
+        Ok(QOIEncoder {
+            writer,
+            channels,
+            remaining: width as u64 * height as u64,
+            index: [Color::new(0, 0, 0, 0); 64],
+            previous: Color::black(),
+            run: 0,
+        })
+    }
+
+    fn flush_run(&mut self) -> io::Result<()> {
+        if self.run > 0 {
+            self.writer.write_all(&[qoi::OP_RUN | (self.run - 1)])?;
+            self.run = 0;
+        }
+        Ok(())
+    }
+
+    fn encode(&mut self, color: Color) -> io::Result<()> {
+        let color = match self.channels {
+            Channels::RGB => Color { a: 255, ..color },
+            Channels::RGBA => color,
+        };
+
+        if color == self.previous {
+            self.run += 1;
+            if self.run == 62 {
+                self.flush_run()?;
+            }
+            return Ok(());
+        }
+        self.flush_run()?;
+
+        let hash = qoi::hash(color);
+        if self.index[hash] == color {
+            self.writer.write_all(&[qoi::OP_INDEX | hash as u8])?;
+        } else {
+            self.index[hash] = color;
+
+            if color.a == self.previous.a {
+                let dr = color.r.wrapping_sub(self.previous.r) as i8;
+                let dg = color.g.wrapping_sub(self.previous.g) as i8;
+                let db = color.b.wrapping_sub(self.previous.b) as i8;
+                let (dr_dg, db_dg) = (dr.wrapping_sub(dg), db.wrapping_sub(dg));
+
+                if (-2..=1).contains(&dr) && (-2..=1).contains(&dg) && (-2..=1).contains(&db) {
+                    self.writer.write_all(&[qoi::OP_DIFF | ((dr + 2) as u8) << 4 | ((dg + 2) as u8) << 2 | (db + 2) as u8])?;
+                } else if (-32..=31).contains(&dg) && (-8..=7).contains(&dr_dg) && (-8..=7).contains(&db_dg) {
+                    self.writer.write_all(&[qoi::OP_LUMA | (dg + 32) as u8, ((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8])?;
+                } else {
+                    self.writer.write_all(&[qoi::OP_RGB, color.r, color.g, color.b])?;
+                }
+            } else {
+                self.writer.write_all(&[qoi::OP_RGBA, color.r, color.g, color.b, color.a])?;
+            }
+        }
+
+        self.previous = color;
+        Ok(())
+    }
+
+    pub fn write_pixels(&mut self, colors: &[Color]) -> io::Result<()> {
+        if colors.len() as u64 > self.remaining {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "More pixels than the image size"));
+        }
+        self.remaining -= colors.len() as u64;
+
+        colors.iter().try_for_each(|&color| self.encode(color))
+    }
+
+    /// Writes the end marker and returns the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.remaining > 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Fewer pixels than the image size"));
+        }
+        self.flush_run()?;
+        self.writer.write_all(&qoi::END_MARKER)?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+impl QOIWriter {
+    pub fn encode(&self, image: &Image) -> io::Result<Vec<u8>> {
+        let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "Image too large for QOI");
+        let width = u32::try_from(image.width()).map_err(|_| too_large())?;
+        let height = u32::try_from(image.height()).map_err(|_| too_large())?;
+        let mut encoder = QOIEncoder::new(Vec::new(), width, height, self.settings.channels, self.settings.color_space)?;
+        for row in image.pixels.iter() {
+            encoder.write_pixels(row)?;
+        }
+        encoder.finish()
+    }
+}
+
+impl Writer for QOIWriter {
+    fn extension(&self) -> &str {
+        "qoi"
+    }
+
+    fn write(&self, image: Image, path: &str) {
+        println!("Writing QOI file at path: {}", path);
+
+        let data = self.encode(&image).expect("Can't encode QOI file");
+        fs::write(path, data).expect("Can't save output QOI file");
+    }
+}
@@ -0,0 +1,125 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::binary_serializable::BinarySerializable;
+
+/// TGA 2.0 footer signature
+pub const SIGNATURE: &[u8; 18] = b"TRUEVISION-XFILE.\0";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ImageType {
+    ColorMapped = 1,
+    TrueColor = 2,
+    Grayscale = 3,
+    RLEColorMapped = 9,
+    RLETrueColor = 10,
+    RLEGrayscale = 11,
+}
+
+impl ImageType {
+    pub fn is_rle(&self) -> bool {
+        (*self as u8) & 8 != 0
+    }
+
+    /// Image type without run length encoding
+    pub fn base(&self) -> ImageType {
+        match self {
+            ImageType::RLEColorMapped => ImageType::ColorMapped,
+            ImageType::RLETrueColor => ImageType::TrueColor,
+            ImageType::RLEGrayscale => ImageType::Grayscale,
+            image_type => *image_type,
+        }
+    }
+}
+
+impl TryFrom<u8> for ImageType {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(ImageType::ColorMapped),
+            2 => Ok(ImageType::TrueColor),
+            3 => Ok(ImageType::Grayscale),
+            9 => Ok(ImageType::RLEColorMapped),
+            10 => Ok(ImageType::RLETrueColor),
+            11 => Ok(ImageType::RLEGrayscale),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub id_length: u8,
+    pub color_map_type: u8,
+    pub image_type: ImageType,
+    pub color_map_first: u16,
+    pub color_map_length: u16,
+    pub color_map_entry_size: u8,
+    pub x_origin: u16,
+    pub y_origin: u16,
+    pub width: u16,
+    pub height: u16,
+    pub pixel_depth: u8,
+    /// Alpha bits in the low nibble, origin in bits 4 and 5
+    pub descriptor: u8,
+}
+
+impl Header {
+    pub const SIZE: usize = 18;
+
+    pub fn alpha_bits(&self) -> u8 {
+        self.descriptor & 0x0F
+    }
+
+    pub fn is_right_to_left(&self) -> bool {
+        self.descriptor & 0x10 != 0
+    }
+
+    pub fn is_top_to_bottom(&self) -> bool {
+        self.descriptor & 0x20 != 0
+    }
+}
+
+impl BinarySerializable for Header {
+    fn read<R: Read>(reader: &mut R) -> io::Result<Self> where Self: Sized {
+        let id_length = reader.read_u8()?;
+        let color_map_type = reader.read_u8()?;
+        let image_type = ImageType::try_from(reader.read_u8()?)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Unknown TGA image type"))?;
+
+        Ok(Header {
+            id_length,
+            color_map_type,
+            image_type,
+            color_map_first: reader.read_u16::<LittleEndian>()?,
+            color_map_length: reader.read_u16::<LittleEndian>()?,
+            color_map_entry_size: reader.read_u8()?,
+            x_origin: reader.read_u16::<LittleEndian>()?,
+            y_origin: reader.read_u16::<LittleEndian>()?,
+            width: reader.read_u16::<LittleEndian>()?,
+            height: reader.read_u16::<LittleEndian>()?,
+            pixel_depth: reader.read_u8()?,
+            descriptor: reader.read_u8()?,
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u8(self.id_length)?;
+        writer.write_u8(self.color_map_type)?;
+        writer.write_u8(self.image_type as u8)?;
+        writer.write_u16::<LittleEndian>(self.color_map_first)?;
+        writer.write_u16::<LittleEndian>(self.color_map_length)?;
+        writer.write_u8(self.color_map_entry_size)?;
+        writer.write_u16::<LittleEndian>(self.x_origin)?;
+        writer.write_u16::<LittleEndian>(self.y_origin)?;
+        writer.write_u16::<LittleEndian>(self.width)?;
+        writer.write_u16::<LittleEndian>(self.height)?;
+        writer.write_u8(self.pixel_depth)?;
+        writer.write_u8(self.descriptor)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use std::fs;
+use std::io::{self, Cursor};
+
+use crate::binary_serializable::BinarySerializable;
+use crate::common::*;
+use crate::tga::{Header, ImageType};
+
+pub struct TGAReader {
+
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn unexpected_eof() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete TGA data")
+}
+
+/// Scales a 5 bit channel to 8 bits
+fn scale_5_bit(value: u16) -> u8 {
+    ((value & 0x1F) * 255 / 31) as u8
+}
+
+impl TGAReader {
+    /// Color of a true color pixel or color map entry, alpha is only used when the header declares alpha bits
+    fn color(&self, bytes: &[u8], alpha: bool) -> Color {
+        match bytes.len() {
+            2 => {
+                let value = u16::from_le_bytes([bytes[0], bytes[1]]);
+                let a = if alpha && value & 0x8000 == 0 { 0 } else { 255 };
+                Color::new(scale_5_bit(value >> 10), scale_5_bit(value >> 5), scale_5_bit(value), a)
+            },
+            3 => Color::from_rgb(bytes[2], bytes[1], bytes[0]),
+            _ => Color::new(bytes[2], bytes[1], bytes[0], if alpha { bytes[3] } else { 255 }),
+        }
+    }
+
+    /// Expands run length encoded packets to raw pixel bytes
+    fn decode_rle(&self, data: &[u8], pixel_count: usize, pixel_size: usize) -> io::Result<Vec<u8>> {
+        let size = pixel_count * pixel_size;
+        // Each packet expands to at most 128 pixels, so the input bounds the output
+        let mut pixels = Vec::with_capacity(size.min(data.len().saturating_mul(128)));
+        let mut position = 0;
+
+        while pixels.len() < size {
+            let packet = *data.get(position).ok_or_else(unexpected_eof)?;
+            let count = (packet & 0x7F) as usize + 1;
+            position += 1;
+
+            if packet & 0x80 != 0 {
+                let pixel = data.get(position..position + pixel_size).ok_or_else(unexpected_eof)?;
+                for _ in 0..count {
+                    pixels.extend_from_slice(pixel);
+                }
+                position += pixel_size;
+            } else {
+                let raw = data.get(position..position + count * pixel_size).ok_or_else(unexpected_eof)?;
+                pixels.extend_from_slice(raw);
+                position += count * pixel_size;
+            }
+        }
+
+        pixels.truncate(size);
+        Ok(pixels)
+    }
+
+    pub fn decode(&self, data: &[u8]) -> io::Result<Image> {
+        let header = Header::read(&mut Cursor::new(data))?;
+        let (width, height) = (header.width as usize, header.height as usize);
+        let alpha = header.alpha_bits() > 0;
+        let mut position = Header::SIZE + header.id_length as usize;
+
+        let mut color_map = Vec::new();
+        if header.color_map_type == 1 {
+            let entry_size = (header.color_map_entry_size as usize).div_ceil(8);
+            if !(2..=4).contains(&entry_size) {
+                return Err(invalid_data("Unsupported TGA color map entry size"));
+            }
+            let length = header.color_map_length as usize * entry_size;
+            let entries = data.get(position..position + length).ok_or_else(unexpected_eof)?;
+            color_map = entries.chunks_exact(entry_size).map(|entry| self.color(entry, alpha)).collect();
+            position += length;
+        }
+
+        let pixel_size = (header.pixel_depth as usize).div_ceil(8);
+        let valid = match header.image_type.base() {
+            ImageType::ColorMapped => header.color_map_type == 1 && (pixel_size == 1 || pixel_size == 2),
+            ImageType::Grayscale => pixel_size == 1 || pixel_size == 2,
+            _ => (2..=4).contains(&pixel_size),
+        };
+        if !valid {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "Unsupported TGA pixel depth"));
+        }
+
+        let data = &data[position.min(data.len())..];
+        let pixels = if header.image_type.is_rle() {
+            self.decode_rle(data, width * height, pixel_size)?
+        } else {
+            data.get(..width * height * pixel_size).ok_or_else(unexpected_eof)?.to_vec()
+        };
+
+        let colors = pixels.chunks_exact(pixel_size)
+            .map(|bytes| match header.image_type.base() {
+                ImageType::ColorMapped => {
+                    let index = if pixel_size == 2 { u16::from_le_bytes([bytes[0], bytes[1]]) } else { bytes[0] as u16 };
+                    index.checked_sub(header.color_map_first)
+                        .and_then(|index| color_map.get(index as usize).copied())
+                        .ok_or_else(|| invalid_data("TGA color map index out of range"))
+                },
+                ImageType::Grayscale => Ok(match bytes {
+                    [gray, a] if alpha => Color::new(*gray, *gray, *gray, *a),
+                    _ => Color::from_rgb(bytes[0], bytes[0], bytes[0]),
+                }),
+                _ => Ok(self.color(bytes, alpha)),
+            })
+            .collect::<io::Result<Vec<Color>>>()?;
+
+        let mut rows: Vec<Vec<Color>> = colors.chunks(width.max(1)).map(<[Color]>::to_vec).collect();
+        if !header.is_top_to_bottom() {
+            rows.reverse();
+        }
+        if header.is_right_to_left() {
+            rows.iter_mut().for_each(|row| row.reverse());
+        }
+
+        Ok(Image::from_mat(width, height, rows))
+    }
+}
+
+impl Reader for TGAReader {
+    fn read(&self, path: &str) -> io::Result<Image> {
+        println!("Reading TGA file");
+
+        let data = fs::read(path)?;
+        self.decode(&data)
+    }
+}
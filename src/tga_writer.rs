@@ -0,0 +1,113 @@
+use std::fs;
+use std::io;
+
+use crate::binary_serializable::BinarySerializable;
+use crate::common::*;
+use crate::tga::{self, Header, ImageType};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PixelFormat {
+    /// 24 bit BGR, alpha is dropped
+    RGB,
+    /// 32 bit BGRA with 8 alpha bits
+    #[default]
+    RGBA,
+}
+
+#[derive(Default)]
+pub struct Settings {
+    pub pixel_format: PixelFormat,
+    /// Run length encodes each row
+    pub rle: bool,
+}
+
+pub struct TGAWriter {
+    pub settings: Settings
+}
+
+impl TGAWriter {
+    /// Packets of up to 128 pixels, runs of at least two equal pixels are repeated
+    fn encode_rle(&self, row: &[Vec<u8>], data: &mut Vec<u8>) {
+        let mut i = 0;
+        while i < row.len() {
+            let run = row[i..].iter().take(128).take_while(|pixel| **pixel == row[i]).count();
+            if run > 1 {
+                data.push(0x80 | (run - 1) as u8);
+                data.extend_from_slice(&row[i]);
+                i += run;
+                continue;
+            }
+
+            let start = i;
+            while i < row.len() && i - start < 128 && (i + 1 >= row.len() || row[i] != row[i + 1]) {
+                i += 1;
+            }
+            data.push((i - start - 1) as u8);
+            row[start..i].iter().for_each(|pixel| data.extend_from_slice(pixel));
+        }
+    }
+
+    pub fn encode(&self, image: &Image) -> io::Result<Vec<u8>> {
+        if image.width() > u16::MAX as usize || image.height() > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image is too large for TGA"));
+        }
+
+        let (pixel_depth, alpha_bits) = match self.settings.pixel_format {
+            PixelFormat::RGB => (24, 0),
+            PixelFormat::RGBA => (32, 8),
+        };
+
+        // Rows are written from top to bottom
+        let header = Header {
+            id_length: 0,
+            color_map_type: 0,
+            image_type: if self.settings.rle { ImageType::RLETrueColor } else { ImageType::TrueColor },
+            color_map_first: 0,
+            color_map_length: 0,
+            color_map_entry_size: 0,
+            x_origin: 0,
+            y_origin: 0,
+            width: image.width() as u16,
+            height: image.height() as u16,
+            pixel_depth,
+            descriptor: 0x20 | alpha_bits,
+        };
+
+        let mut data = Vec::new();
+        header.write(&mut data)?;
+
+        for row in image.pixels.iter() {
+            let row: Vec<Vec<u8>> = row.iter()
+                .map(|color| match self.settings.pixel_format {
+                    PixelFormat::RGB => vec![color.b, color.g, color.r],
+                    PixelFormat::RGBA => vec![color.b, color.g, color.r, color.a],
+                })
+                .collect();
+
+            if self.settings.rle {
+                self.encode_rle(&row, &mut data);
+            } else {
+                row.iter().for_each(|pixel| data.extend_from_slice(pixel));
+            }
+        }
+
+        // TGA 2.0 footer without extension and developer areas
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(tga::SIGNATURE);
+
+        Ok(data)
+    }
+}
+
+impl Writer for TGAWriter {
+    fn extension(&self) -> &str {
+        "tga"
+    }
+
+    fn write(&self, image: Image, path: &str) {
+        println!("Writing TGA file at path: {}", path);
+
+        let data = self.encode(&image).expect("Can't encode TGA file");
+        fs::write(path, data).expect("Can't save output TGA file");
+    }
+}
@@ -0,0 +1,495 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+pub const LITTLE_ENDIAN_MAGIC: [u8; 4] = *b"II\x2A\0";
+pub const BIG_ENDIAN_MAGIC: [u8; 4] = *b"MM\0\x2A";
+
+pub const IMAGE_WIDTH: u16 = 256;
+pub const IMAGE_LENGTH: u16 = 257;
+pub const BITS_PER_SAMPLE: u16 = 258;
+pub const COMPRESSION: u16 = 259;
+pub const PHOTOMETRIC_INTERPRETATION: u16 = 262;
+pub const STRIP_OFFSETS: u16 = 273;
+pub const SAMPLES_PER_PIXEL: u16 = 277;
+pub const ROWS_PER_STRIP: u16 = 278;
+pub const STRIP_BYTE_COUNTS: u16 = 279;
+pub const X_RESOLUTION: u16 = 282;
+pub const Y_RESOLUTION: u16 = 283;
+pub const PLANAR_CONFIGURATION: u16 = 284;
+pub const RESOLUTION_UNIT: u16 = 296;
+pub const SOFTWARE: u16 = 305;
+pub const DATE_TIME: u16 = 306;
+pub const PREDICTOR: u16 = 317;
+pub const EXTRA_SAMPLES: u16 = 338;
+
+const LZW_CLEAR: u16 = 256;
+const LZW_END: u16 = 257;
+const LZW_MAX_CODES: usize = 4096;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ByteOrder {
+    #[default]
+    LittleEndian,
+    BigEndian,
+}
+
+impl ByteOrder {
+    pub fn u16(&self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        match self {
+            ByteOrder::LittleEndian => u16::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u16::from_be_bytes(bytes),
+        }
+    }
+
+    pub fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match self {
+            ByteOrder::LittleEndian => u32::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u32::from_be_bytes(bytes),
+        }
+    }
+
+    pub fn u16_bytes(&self, value: u16) -> [u8; 2] {
+        match self {
+            ByteOrder::LittleEndian => value.to_le_bytes(),
+            ByteOrder::BigEndian => value.to_be_bytes(),
+        }
+    }
+
+    pub fn u32_bytes(&self, value: u32) -> [u8; 4] {
+        match self {
+            ByteOrder::LittleEndian => value.to_le_bytes(),
+            ByteOrder::BigEndian => value.to_be_bytes(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    LZW,
+    Deflate,
+    PackBits,
+}
+
+impl Compression {
+    pub fn from_value(value: u32) -> Option<Compression> {
+        match value {
+            1 => Some(Compression::None),
+            5 => Some(Compression::LZW),
+            // 32946 is the value used before Deflate was registered
+            8 | 32946 => Some(Compression::Deflate),
+            32773 => Some(Compression::PackBits),
+            _ => None,
+        }
+    }
+
+    pub fn value(&self) -> u16 {
+        match self {
+            Compression::None => 1,
+            Compression::LZW => 5,
+            Compression::Deflate => 8,
+            Compression::PackBits => 32773,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ResolutionUnit {
+    None,
+    #[default]
+    Inch,
+    Centimeter,
+}
+
+/// Tag value of one of the field types used by baseline TIFF
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Byte(Vec<u8>),
+    Ascii(String),
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Rational(Vec<(u32, u32)>),
+}
+
+impl Value {
+    fn field_type(&self) -> u16 {
+        match self {
+            Value::Byte(_) => 1,
+            Value::Ascii(_) => 2,
+            Value::Short(_) => 3,
+            Value::Long(_) => 4,
+            Value::Rational(_) => 5,
+        }
+    }
+
+    fn count(&self) -> usize {
+        match self {
+            Value::Byte(values) => values.len(),
+            // Including the terminating NUL
+            Value::Ascii(text) => text.len() + 1,
+            Value::Short(values) => values.len(),
+            Value::Long(values) => values.len(),
+            Value::Rational(values) => values.len(),
+        }
+    }
+
+    fn bytes(&self, order: ByteOrder) -> Vec<u8> {
+        match self {
+            Value::Byte(values) => values.clone(),
+            Value::Ascii(text) => [text.as_bytes(), &[0]].concat(),
+            Value::Short(values) => values.iter().flat_map(|&value| order.u16_bytes(value)).collect(),
+            Value::Long(values) => values.iter().flat_map(|&value| order.u32_bytes(value)).collect(),
+            Value::Rational(values) => values.iter().flat_map(|&(numerator, denominator)| [order.u32_bytes(numerator), order.u32_bytes(denominator)]).flatten().collect(),
+        }
+    }
+
+    /// Integer values of BYTE, SHORT and LONG fields
+    pub fn integers(&self) -> Option<Vec<u32>> {
+        match self {
+            Value::Byte(values) => Some(values.iter().map(|&value| value as u32).collect()),
+            Value::Short(values) => Some(values.iter().map(|&value| value as u32).collect()),
+            Value::Long(values) => Some(values.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn integer(&self) -> Option<u32> {
+        self.integers()?.first().copied()
+    }
+
+    pub fn rational(&self) -> Option<f64> {
+        match self {
+            Value::Rational(values) => values.first().filter(|(_, denominator)| *denominator != 0).map(|&(numerator, denominator)| numerator as f64 / denominator as f64),
+            _ => None,
+        }
+    }
+
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Value::Ascii(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+/// Descriptive tags read and written next to the image
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    /// Horizontal and vertical pixels per resolution unit
+    pub resolution: Option<(f64, f64)>,
+    pub resolution_unit: ResolutionUnit,
+    pub software: Option<String>,
+    /// "YYYY:MM:DD HH:MM:SS"
+    pub date_time: Option<String>,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Metadata { resolution: Some((72.0, 72.0)), resolution_unit: ResolutionUnit::Inch, software: None, date_time: None }
+    }
+}
+
+impl Metadata {
+    pub fn from_ifd(ifd: &BTreeMap<u16, Value>) -> Metadata {
+        let rational = |tag: u16| ifd.get(&tag).and_then(Value::rational);
+        let text = |tag: u16| ifd.get(&tag).and_then(Value::text).map(str::to_string);
+        Metadata {
+            resolution: rational(X_RESOLUTION).zip(rational(Y_RESOLUTION)),
+            resolution_unit: match ifd.get(&RESOLUTION_UNIT).and_then(Value::integer) {
+                Some(1) => ResolutionUnit::None,
+                Some(3) => ResolutionUnit::Centimeter,
+                _ => ResolutionUnit::Inch,
+            },
+            software: text(SOFTWARE),
+            date_time: text(DATE_TIME),
+        }
+    }
+
+    pub fn to_ifd(&self, ifd: &mut BTreeMap<u16, Value>) {
+        // Whole resolutions are kept exact, others to a thousandth
+        let rational = |value: f64| if value.fract() == 0.0 { (value as u32, 1) } else { ((value * 1000.0).round() as u32, 1000) };
+        if let Some((x, y)) = self.resolution {
+            ifd.insert(X_RESOLUTION, Value::Rational(vec![rational(x)]));
+            ifd.insert(Y_RESOLUTION, Value::Rational(vec![rational(y)]));
+            ifd.insert(RESOLUTION_UNIT, Value::Short(vec![self.resolution_unit as u16 + 1]));
+        }
+        if let Some(software) = &self.software {
+            ifd.insert(SOFTWARE, Value::Ascii(software.clone()));
+        }
+        if let Some(date_time) = &self.date_time {
+            ifd.insert(DATE_TIME, Value::Ascii(date_time.clone()));
+        }
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Entries of the IFD at the offset, fields of other types are skipped
+pub fn read_ifd(data: &[u8], offset: usize, order: ByteOrder) -> io::Result<BTreeMap<u16, Value>> {
+    let bytes = |offset: usize, size: usize| data.get(offset..offset + size).ok_or_else(|| invalid_data("Incomplete TIFF directory"));
+    let count = order.u16(bytes(offset, 2)?) as usize;
+
+    let mut ifd = BTreeMap::new();
+    for i in 0..count {
+        let entry = bytes(offset + 2 + i * 12, 12)?;
+        let (tag, field_type, count) = (order.u16(&entry[0..2]), order.u16(&entry[2..4]), order.u32(&entry[4..8]) as usize);
+
+        let size = match field_type {
+            1 | 2 => 1,
+            3 => 2,
+            4 => 4,
+            5 => 8,
+            _ => continue,
+        };
+        let value = match count * size {
+            size if size <= 4 => &entry[8..8 + size],
+            size => bytes(order.u32(&entry[8..12]) as usize, size)?,
+        };
+
+        let value = match field_type {
+            1 => Value::Byte(value.to_vec()),
+            2 => Value::Ascii(String::from_utf8_lossy(value).trim_end_matches('\0').to_string()),
+            3 => Value::Short(value.chunks_exact(2).map(|bytes| order.u16(bytes)).collect()),
+            4 => Value::Long(value.chunks_exact(4).map(|bytes| order.u32(bytes)).collect()),
+            _ => Value::Rational(value.chunks_exact(8).map(|bytes| (order.u32(&bytes[..4]), order.u32(&bytes[4..]))).collect()),
+        };
+        ifd.insert(tag, value);
+    }
+
+    Ok(ifd)
+}
+
+/// Appends the IFD with values not fitting an entry after it, the next IFD offset is 0
+pub fn write_ifd(data: &mut Vec<u8>, ifd: &BTreeMap<u16, Value>, order: ByteOrder) {
+    let mut values_offset = data.len() + 2 + ifd.len() * 12 + 4;
+    let mut values = Vec::new();
+
+    data.extend_from_slice(&order.u16_bytes(ifd.len() as u16));
+    for (&tag, value) in ifd.iter() {
+        data.extend_from_slice(&order.u16_bytes(tag));
+        data.extend_from_slice(&order.u16_bytes(value.field_type()));
+        data.extend_from_slice(&order.u32_bytes(value.count() as u32));
+
+        let mut bytes = value.bytes(order);
+        if bytes.len() <= 4 {
+            bytes.resize(4, 0);
+            data.extend_from_slice(&bytes);
+        } else {
+            // Values start on a word boundary
+            data.extend_from_slice(&order.u32_bytes(values_offset as u32));
+            bytes.resize(bytes.len().next_multiple_of(2), 0);
+            values_offset += bytes.len();
+            values.extend_from_slice(&bytes);
+        }
+    }
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&values);
+}
+
+/// Undoes horizontal differencing of rows of 8 or 16 bit samples
+pub fn undo_predictor(data: &mut [u8], row_size: usize, samples_per_pixel: usize, bit_depth: u8, order: ByteOrder) {
+    for row in data.chunks_mut(row_size) {
+        if bit_depth == 16 {
+            for i in (samples_per_pixel * 2..row.len() - row.len() % 2).step_by(2) {
+                let value = order.u16(&row[i..]).wrapping_add(order.u16(&row[i - samples_per_pixel * 2..]));
+                row[i..i + 2].copy_from_slice(&order.u16_bytes(value));
+            }
+        } else {
+            for i in samples_per_pixel..row.len() {
+                row[i] = row[i].wrapping_add(row[i - samples_per_pixel]);
+            }
+        }
+    }
+}
+
+/// Replaces samples with the difference to the previous sample of the same channel
+pub fn apply_predictor(data: &mut [u8], row_size: usize, samples_per_pixel: usize, bit_depth: u8, order: ByteOrder) {
+    for row in data.chunks_mut(row_size) {
+        if bit_depth == 16 {
+            for i in (samples_per_pixel * 2..row.len() - row.len() % 2).step_by(2).rev() {
+                let value = order.u16(&row[i..]).wrapping_sub(order.u16(&row[i - samples_per_pixel * 2..]));
+                row[i..i + 2].copy_from_slice(&order.u16_bytes(value));
+            }
+        } else {
+            for i in (samples_per_pixel..row.len()).rev() {
+                row[i] = row[i].wrapping_sub(row[i - samples_per_pixel]);
+            }
+        }
+    }
+}
+
+/// Runs are a count followed by the byte repeated 1 - count times, nonnegative counts precede count + 1 literal bytes
+pub fn packbits_decode(data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    // A two byte run expands to at most 128 bytes
+    let mut output = Vec::with_capacity(size.min(data.len() * 64));
+    let mut position = 0;
+
+    while position < data.len() && output.len() < size {
+        let count = data[position] as i8;
+        position += 1;
+
+        match count {
+            // No operation
+            -128 => {},
+            0.. => {
+                let literal = data.get(position..position + count as usize + 1).ok_or_else(|| invalid_data("Invalid TIFF PackBits data"))?;
+                output.extend_from_slice(literal);
+                position += literal.len();
+            },
+            _ => {
+                let value = *data.get(position).ok_or_else(|| invalid_data("Invalid TIFF PackBits data"))?;
+                output.extend(std::iter::repeat_n(value, (1 - count as isize) as usize));
+                position += 1;
+            },
+        }
+    }
+
+    Ok(output)
+}
+
+pub fn packbits_encode(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut literal_start = 0;
+    let mut position = 0;
+
+    let flush_literal = |output: &mut Vec<u8>, literal: &[u8]| {
+        for chunk in literal.chunks(128) {
+            output.push(chunk.len() as u8 - 1);
+            output.extend_from_slice(chunk);
+        }
+    };
+
+    while position < data.len() {
+        let run = data[position..].iter().take(128).take_while(|&&byte| byte == data[position]).count();
+        // Runs of two are only worth it without a literal before them
+        if run >= 3 || (run == 2 && literal_start == position) {
+            flush_literal(&mut output, &data[literal_start..position]);
+            output.extend_from_slice(&[(1 - run as isize) as u8, data[position]]);
+            position += run;
+            literal_start = position;
+        } else {
+            position += run;
+        }
+    }
+    flush_literal(&mut output, &data[literal_start..]);
+
+    output
+}
+
+/// Decodes codes packed most significant bit first, widening them one code early as TIFF requires
+pub fn lzw_decode(data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let mut table: Vec<Vec<u8>> = (0..=255).map(|byte| vec![byte]).collect();
+    table.resize(258, Vec::new());
+
+    // The size comes from the file, so the capacity is only a guess from the compressed length
+    let mut output = Vec::with_capacity(size.min(data.len() * 8));
+    let mut code_size = 9;
+    let mut previous: Option<usize> = None;
+    let (mut buffer, mut bits, mut position) = (0u32, 0u8, 0usize);
+
+    while output.len() < size {
+        while bits < code_size {
+            let Some(&byte) = data.get(position) else { return Ok(output) };
+            buffer = buffer << 8 | byte as u32;
+            position += 1;
+            bits += 8;
+        }
+        let code = ((buffer >> (bits - code_size)) & ((1 << code_size) - 1)) as u16;
+        bits -= code_size;
+
+        if code == LZW_CLEAR {
+            table.truncate(258);
+            code_size = 9;
+            previous = None;
+            continue;
+        }
+        if code == LZW_END {
+            break;
+        }
+
+        let code = code as usize;
+        let entry = match previous {
+            None if code < 256 => table[code].clone(),
+            None => return Err(invalid_data("Invalid TIFF LZW code")),
+            Some(previous) if code < table.len() => {
+                let entry = table[code].clone();
+                if table.len() < LZW_MAX_CODES {
+                    table.push([table[previous].as_slice(), &entry[..1]].concat());
+                }
+                entry
+            },
+            Some(previous) if code == table.len() => {
+                let entry = [table[previous].as_slice(), &table[previous][..1]].concat();
+                if table.len() < LZW_MAX_CODES {
+                    table.push(entry.clone());
+                }
+                entry
+            },
+            _ => return Err(invalid_data("Invalid TIFF LZW code")),
+        };
+        output.extend_from_slice(&entry);
+
+        if previous.is_some() && table.len() + 1 >= 1 << code_size && code_size < 12 {
+            code_size += 1;
+        }
+        previous = Some(code);
+    }
+
+    output.truncate(size);
+    Ok(output)
+}
+
+pub fn lzw_encode(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0u8);
+    let mut write = |code: u16, code_size: u8| {
+        buffer = buffer << code_size | code as u32;
+        bits += code_size;
+        while bits >= 8 {
+            output.push((buffer >> (bits - 8)) as u8);
+            bits -= 8;
+        }
+    };
+
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let (mut code_size, mut next) = (9u8, LZW_END + 1);
+    write(LZW_CLEAR, code_size);
+
+    if let Some((&first, rest)) = data.split_first() {
+        let mut prefix = first as u16;
+        for &byte in rest {
+            if let Some(&code) = table.get(&(prefix, byte)) {
+                prefix = code;
+                continue;
+            }
+
+            write(prefix, code_size);
+            table.insert((prefix, byte), next);
+            next += 1;
+            if next as usize == LZW_MAX_CODES - 2 {
+                write(LZW_CLEAR, code_size);
+                table.clear();
+                code_size = 9;
+                next = LZW_END + 1;
+            } else if next >= 1 << code_size {
+                code_size += 1;
+            }
+            prefix = byte as u16;
+        }
+        write(prefix, code_size);
+        next += 1;
+        if next >= 1 << code_size && code_size < 12 {
+            code_size += 1;
+        }
+    }
+
+    write(LZW_END, code_size);
+    // Seven zero bits flush the last partial byte
+    write(0, 7);
+    output
+}
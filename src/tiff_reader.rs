@@ -0,0 +1,162 @@
+use std::fs;
+use std::io::{self, Read};
+
+use flate2::read::ZlibDecoder;
+
+use crate::common::*;
+use crate::png_reader::scale_sample;
+use crate::tiff::{self, ByteOrder, Compression, Metadata, Value};
+
+pub struct TIFFReader {
+
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn unsupported(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, message.to_string())
+}
+
+impl TIFFReader {
+    fn decompress(&self, compression: Compression, data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+        match compression {
+            Compression::None => Ok(data.to_vec()),
+            Compression::PackBits => tiff::packbits_decode(data, size),
+            Compression::LZW => tiff::lzw_decode(data, size),
+            Compression::Deflate => {
+                let mut output = Vec::new();
+                ZlibDecoder::new(data).take(size as u64).read_to_end(&mut output)?;
+                Ok(output)
+            },
+        }
+    }
+
+    /// Decodes the first image of the file, which must be made of strips of interleaved 8 or 16 bit samples
+    pub fn decode(&self, data: &[u8]) -> io::Result<(Image, Metadata)> {
+        let order = match data.get(..4) {
+            Some(magic) if magic == tiff::LITTLE_ENDIAN_MAGIC => ByteOrder::LittleEndian,
+            Some(magic) if magic == tiff::BIG_ENDIAN_MAGIC => ByteOrder::BigEndian,
+            _ => return Err(invalid_data("Invalid TIFF file")),
+        };
+        let ifd_offset = data.get(4..8).map(|bytes| order.u32(bytes) as usize).ok_or_else(|| invalid_data("Invalid TIFF file"))?;
+        let ifd = tiff::read_ifd(data, ifd_offset, order)?;
+
+        let integers = |tag: u16| ifd.get(&tag).and_then(Value::integers);
+        let integer = |tag: u16, default: Option<u32>| ifd.get(&tag).and_then(Value::integer).or(default)
+            .ok_or_else(|| invalid_data(&format!("Missing TIFF tag {}", tag)));
+
+        let (width, height) = (integer(tiff::IMAGE_WIDTH, None)? as usize, integer(tiff::IMAGE_LENGTH, None)? as usize);
+        if width == 0 || height == 0 {
+            return Err(invalid_data("Invalid TIFF size"));
+        }
+        let samples_per_pixel = integer(tiff::SAMPLES_PER_PIXEL, Some(1))? as usize;
+        let bits_per_sample = integers(tiff::BITS_PER_SAMPLE).unwrap_or(vec![1]);
+        let bit_depth = *bits_per_sample.first().ok_or_else(|| invalid_data("Empty TIFF bits per sample"))?;
+        if bits_per_sample.iter().any(|&bits| bits != bit_depth) || (bit_depth != 8 && bit_depth != 16) {
+            return Err(unsupported("Only 8 and 16 bit TIFF samples are supported"));
+        }
+
+        let compression = Compression::from_value(integer(tiff::COMPRESSION, Some(1))?)
+            .ok_or_else(|| unsupported("Unsupported TIFF compression"))?;
+        let photometric = integer(tiff::PHOTOMETRIC_INTERPRETATION, None)?;
+        let color_samples = match photometric {
+            0 | 1 => 1,
+            2 => 3,
+            _ => return Err(unsupported("Only grayscale and RGB TIFF is supported")),
+        };
+        if samples_per_pixel < color_samples {
+            return Err(invalid_data("Too few TIFF samples per pixel"));
+        }
+        if integer(tiff::PLANAR_CONFIGURATION, Some(1))? != 1 {
+            return Err(unsupported("Only interleaved TIFF samples are supported"));
+        }
+        let predictor = integer(tiff::PREDICTOR, Some(1))?;
+        if predictor > 2 {
+            return Err(unsupported("Unsupported TIFF predictor"));
+        }
+
+        let rows_per_strip = (integer(tiff::ROWS_PER_STRIP, Some(u32::MAX))? as usize).clamp(1, height);
+        let offsets = integers(tiff::STRIP_OFFSETS).ok_or_else(|| invalid_data("Missing TIFF strip offsets"))?;
+        let too_large = || invalid_data("TIFF image too large");
+        let row_size = width.checked_mul(samples_per_pixel * bit_depth as usize / 8).ok_or_else(too_large)?;
+        let image_size = row_size.checked_mul(height).ok_or_else(too_large)?;
+        let strip_count = height.div_ceil(rows_per_strip);
+
+        // Strip byte counts may be missing from uncompressed files
+        let byte_counts = match integers(tiff::STRIP_BYTE_COUNTS) {
+            Some(byte_counts) => byte_counts,
+            None => vec![rows_per_strip.checked_mul(row_size).and_then(|size| u32::try_from(size).ok()).ok_or_else(too_large)?; offsets.len()],
+        };
+        // Uncompressed strips must hold the whole image, which bounds the sizes by the file length
+        if compression == Compression::None && byte_counts.iter().take(strip_count).map(|&count| count as usize).sum::<usize>() < image_size {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete TIFF data"));
+        }
+
+        // Decompressed strips may be much larger than their byte counts, so the samples grow as strips are decoded
+        let mut samples = Vec::new();
+        for (strip, (&offset, &count)) in offsets.iter().zip(byte_counts.iter()).enumerate().take(strip_count) {
+            let strip_size = rows_per_strip.min(height - strip * rows_per_strip) * row_size;
+            let compressed = data.get(offset as usize..offset as usize + count as usize)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete TIFF strip"))?;
+
+            let mut strip_samples = self.decompress(compression, compressed, strip_size)?;
+            if strip_samples.len() < strip_size {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete TIFF strip"));
+            }
+            strip_samples.truncate(strip_size);
+            if predictor == 2 {
+                tiff::undo_predictor(&mut strip_samples, row_size, samples_per_pixel, bit_depth as u8, order);
+            }
+            samples.extend_from_slice(&strip_samples);
+        }
+        if samples.len() < image_size {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete TIFF data"));
+        }
+
+        let sample = |index: usize| match bit_depth {
+            16 => scale_sample(order.u16(&samples[index * 2..]), 16),
+            _ => samples[index],
+        };
+        // The first extra sample is alpha, associated alpha means premultiplied colors
+        let has_alpha = samples_per_pixel > color_samples;
+        let premultiplied = has_alpha && integers(tiff::EXTRA_SAMPLES).and_then(|values| values.first().copied()) == Some(1);
+
+        let pixels = (0..height).map(|y| (0..width).map(|x| {
+            let first = (y * width + x) * samples_per_pixel;
+            let alpha = if has_alpha { sample(first + color_samples) } else { u8::MAX };
+            let unpremultiply = |value: u8| match (premultiplied, alpha) {
+                (false, _) | (_, 0) => value,
+                _ => (value as u32 * 255 / alpha as u32).min(255) as u8,
+            };
+
+            match photometric {
+                0 => {
+                    let gray = unpremultiply(u8::MAX - sample(first));
+                    Color::new(gray, gray, gray, alpha)
+                },
+                1 => {
+                    let gray = unpremultiply(sample(first));
+                    Color::new(gray, gray, gray, alpha)
+                },
+                _ => Color::new(unpremultiply(sample(first)), unpremultiply(sample(first + 1)), unpremultiply(sample(first + 2)), alpha),
+            }
+        }).collect()).collect();
+
+        Ok((Image::from_mat(width, height, pixels), Metadata::from_ifd(&ifd)))
+    }
+
+    pub fn read_tiff(&self, path: &str) -> io::Result<(Image, Metadata)> {
+        println!("Reading TIFF file");
+
+        let data = fs::read(path)?;
+        self.decode(&data)
+    }
+}
+
+impl Reader for TIFFReader {
+    fn read(&self, path: &str) -> io::Result<Image> {
+        Ok(self.read_tiff(path)?.0)
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+
+use flate2::write::ZlibEncoder;
+
+use crate::common::*;
+use crate::tiff::{self, ByteOrder, Compression, Metadata, Value};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PixelFormat {
+    Grayscale,
+    GrayscaleAlpha,
+    RGB,
+    #[default]
+    RGBA,
+}
+
+impl PixelFormat {
+    fn samples_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Grayscale => 1,
+            PixelFormat::GrayscaleAlpha => 2,
+            PixelFormat::RGB => 3,
+            PixelFormat::RGBA => 4,
+        }
+    }
+
+    fn has_alpha(&self) -> bool {
+        matches!(self, PixelFormat::GrayscaleAlpha | PixelFormat::RGBA)
+    }
+}
+
+pub struct Settings {
+    pub byte_order: ByteOrder,
+    pub pixel_format: PixelFormat,
+    /// 8 or 16
+    pub bit_depth: u8,
+    pub compression: Compression,
+    /// Horizontal differencing, which helps LZW and Deflate on smooth images
+    pub predictor: bool,
+    /// None gives strips of about 8 KB
+    pub rows_per_strip: Option<usize>,
+    pub luminance: Luminance,
+    pub metadata: Metadata,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            byte_order: ByteOrder::default(),
+            pixel_format: PixelFormat::default(),
+            bit_depth: 8,
+            compression: Compression::default(),
+            predictor: false,
+            rows_per_strip: None,
+            luminance: Luminance::default(),
+            metadata: Metadata::default(),
+        }
+    }
+}
+
+pub struct TIFFWriter {
+    pub settings: Settings
+}
+
+impl TIFFWriter {
+    fn row(&self, row: &[Color]) -> Vec<u8> {
+        let order = self.settings.byte_order;
+        let mut data = Vec::with_capacity(row.len() * self.settings.pixel_format.samples_per_pixel() * self.settings.bit_depth as usize / 8);
+
+        for color in row.iter() {
+            let gray = color.luminance(self.settings.luminance);
+            let samples: &[u8] = match self.settings.pixel_format {
+                PixelFormat::Grayscale => &[gray],
+                PixelFormat::GrayscaleAlpha => &[gray, color.a],
+                PixelFormat::RGB => &[color.r, color.g, color.b],
+                PixelFormat::RGBA => &[color.r, color.g, color.b, color.a],
+            };
+            for &sample in samples {
+                match self.settings.bit_depth {
+                    16 => data.extend_from_slice(&order.u16_bytes(sample as u16 * 257)),
+                    _ => data.push(sample),
+                }
+            }
+        }
+
+        data
+    }
+
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self.settings.compression {
+            Compression::None => Ok(data.to_vec()),
+            Compression::PackBits => Ok(tiff::packbits_encode(data)),
+            Compression::LZW => Ok(tiff::lzw_encode(data)),
+            Compression::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            },
+        }
+    }
+
+    /// Strips follow the header and the single IFD comes last
+    pub fn encode(&self, image: &Image) -> io::Result<Vec<u8>> {
+        if self.settings.bit_depth != 8 && self.settings.bit_depth != 16 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "TIFF bit depth must be 8 or 16"));
+        }
+
+        let order = self.settings.byte_order;
+        let samples_per_pixel = self.settings.pixel_format.samples_per_pixel();
+        let row_size = image.width() * samples_per_pixel * self.settings.bit_depth as usize / 8;
+        let rows_per_strip = self.settings.rows_per_strip.unwrap_or((8192 / row_size.max(1)).max(1)).clamp(1, image.height().max(1));
+
+        let mut data = match order {
+            ByteOrder::LittleEndian => tiff::LITTLE_ENDIAN_MAGIC.to_vec(),
+            ByteOrder::BigEndian => tiff::BIG_ENDIAN_MAGIC.to_vec(),
+        };
+        data.extend_from_slice(&[0; 4]);
+
+        let (mut offsets, mut byte_counts) = (Vec::new(), Vec::new());
+        for rows in image.pixels.chunks(rows_per_strip) {
+            let mut strip: Vec<u8> = rows.iter().flat_map(|row| self.row(row)).collect();
+            if self.settings.predictor {
+                tiff::apply_predictor(&mut strip, row_size, samples_per_pixel, self.settings.bit_depth, order);
+            }
+            let strip = self.compress(&strip)?;
+
+            offsets.push(data.len() as u32);
+            byte_counts.push(strip.len() as u32);
+            data.extend_from_slice(&strip);
+        }
+
+        // The IFD starts on a word boundary
+        data.resize(data.len().next_multiple_of(2), 0);
+        let ifd_offset = data.len() as u32;
+        data[4..8].copy_from_slice(&order.u32_bytes(ifd_offset));
+
+        let gray = matches!(self.settings.pixel_format, PixelFormat::Grayscale | PixelFormat::GrayscaleAlpha);
+        let mut ifd = BTreeMap::from([
+            (tiff::IMAGE_WIDTH, Value::Long(vec![image.width() as u32])),
+            (tiff::IMAGE_LENGTH, Value::Long(vec![image.height() as u32])),
+            (tiff::BITS_PER_SAMPLE, Value::Short(vec![self.settings.bit_depth as u16; samples_per_pixel])),
+            (tiff::COMPRESSION, Value::Short(vec![self.settings.compression.value()])),
+            (tiff::PHOTOMETRIC_INTERPRETATION, Value::Short(vec![if gray { 1 } else { 2 }])),
+            (tiff::STRIP_OFFSETS, Value::Long(offsets)),
+            (tiff::SAMPLES_PER_PIXEL, Value::Short(vec![samples_per_pixel as u16])),
+            (tiff::ROWS_PER_STRIP, Value::Long(vec![rows_per_strip as u32])),
+            (tiff::STRIP_BYTE_COUNTS, Value::Long(byte_counts)),
+            (tiff::PLANAR_CONFIGURATION, Value::Short(vec![1])),
+        ]);
+        if self.settings.predictor {
+            ifd.insert(tiff::PREDICTOR, Value::Short(vec![2]));
+        }
+        if self.settings.pixel_format.has_alpha() {
+            // Unassociated alpha
+            ifd.insert(tiff::EXTRA_SAMPLES, Value::Short(vec![2]));
+        }
+        self.settings.metadata.to_ifd(&mut ifd);
+        tiff::write_ifd(&mut data, &ifd, order);
+
+        Ok(data)
+    }
+}
+
+impl Writer for TIFFWriter {
+    fn extension(&self) -> &str {
+        "tiff"
+    }
+
+    fn write(&self, image: Image, path: &str) {
+        println!("Writing TIFF file at path: {}", path);
+
+        let data = self.encode(&image).expect("Can't encode TIFF file");
+        fs::write(path, data).expect("Can't save output TIFF file");
+    }
+}
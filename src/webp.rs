@@ -0,0 +1,70 @@
+use std::io;
+
+pub const RIFF_MAGIC: &[u8; 4] = b"RIFF";
+pub const WEBP_MAGIC: &[u8; 4] = b"WEBP";
+
+pub const VP8: &[u8; 4] = b"VP8 ";
+pub const VP8L: &[u8; 4] = b"VP8L";
+pub const VP8X: &[u8; 4] = b"VP8X";
+pub const ALPH: &[u8; 4] = b"ALPH";
+pub const ANIM: &[u8; 4] = b"ANIM";
+
+pub const VP8L_SIGNATURE: u8 = 0x2F;
+
+/// Offsets (x, y) to the left and up of the current pixel for the first 120 distance codes
+pub(crate) const DISTANCE_MAP: [(i8, i8); 120] = [
+    (0, 1), (1, 0), (1, 1), (-1, 1), (0, 2), (2, 0), (1, 2),
+    (-1, 2), (2, 1), (-2, 1), (2, 2), (-2, 2), (0, 3), (3, 0),
+    (1, 3), (-1, 3), (3, 1), (-3, 1), (2, 3), (-2, 3), (3, 2),
+    (-3, 2), (0, 4), (4, 0), (1, 4), (-1, 4), (4, 1), (-4, 1),
+    (3, 3), (-3, 3), (2, 4), (-2, 4), (4, 2), (-4, 2), (0, 5),
+    (3, 4), (-3, 4), (4, 3), (-4, 3), (5, 0), (1, 5), (-1, 5),
+    (5, 1), (-5, 1), (2, 5), (-2, 5), (5, 2), (-5, 2), (4, 4),
+    (-4, 4), (3, 5), (-3, 5), (5, 3), (-5, 3), (0, 6), (6, 0),
+    (1, 6), (-1, 6), (6, 1), (-6, 1), (2, 6), (-2, 6), (6, 2),
+    (-6, 2), (4, 5), (-4, 5), (5, 4), (-5, 4), (3, 6), (-3, 6),
+    (6, 3), (-6, 3), (0, 7), (7, 0), (1, 7), (-1, 7), (5, 5),
+    (-5, 5), (7, 1), (-7, 1), (4, 6), (-4, 6), (6, 4), (-6, 4),
+    (2, 7), (-2, 7), (7, 2), (-7, 2), (3, 7), (-3, 7), (7, 3),
+    (-7, 3), (5, 6), (-5, 6), (6, 5), (-6, 5), (8, 0), (4, 7),
+    (-4, 7), (7, 4), (-7, 4), (8, 1), (8, 2), (6, 6), (-6, 6),
+    (8, 3), (5, 7), (-5, 7), (7, 5), (-7, 5), (8, 4), (6, 7),
+    (-6, 7), (7, 6), (-7, 6), (8, 5), (7, 7), (-7, 7), (8, 6),
+    (8, 7),
+];
+
+/// Order in which the code lengths of the code length code are stored
+pub(crate) const CODE_LENGTH_ORDER: [usize; 19] = [17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+pub struct Chunk<'a> {
+    pub id: [u8; 4],
+    pub data: &'a [u8],
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Chunks of a RIFF WEBP file, in file order
+pub fn chunks(data: &[u8]) -> io::Result<Vec<Chunk<'_>>> {
+    if data.len() < 12 || &data[0..4] != RIFF_MAGIC || &data[8..12] != WEBP_MAGIC {
+        return Err(invalid_data("Invalid WebP file"));
+    }
+    let riff_size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    let data = &data[..(riff_size + 8).min(data.len())];
+
+    let mut chunks = Vec::new();
+    let mut position = 12;
+    while position + 8 <= data.len() {
+        let id = [data[position], data[position + 1], data[position + 2], data[position + 3]];
+        let size = u32::from_le_bytes([data[position + 4], data[position + 5], data[position + 6], data[position + 7]]) as usize;
+        let chunk_data = data.get(position + 8..position + 8 + size)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete WebP chunk"))?;
+        chunks.push(Chunk { id, data: chunk_data });
+
+        // Chunks are padded to an even size
+        position += 8 + size + size % 2;
+    }
+
+    Ok(chunks)
+}
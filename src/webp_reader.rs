@@ -0,0 +1,522 @@
+use std::fs;
+use std::io;
+
+use crate::common::*;
+use crate::huffman;
+use crate::webp::{self, CODE_LENGTH_ORDER, DISTANCE_MAP};
+
+pub struct WebPReader {
+
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn unsupported(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, message.to_string())
+}
+
+/// Least significant bit first
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u64,
+    bits: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader { data, position: 0, buffer: 0, bits: 0 }
+    }
+
+    fn fill(&mut self) {
+        while self.bits <= 56 && self.position < self.data.len() {
+            self.buffer |= (self.data[self.position] as u64) << self.bits;
+            self.position += 1;
+            self.bits += 8;
+        }
+    }
+
+    /// Past the end of the data the bits are zeros
+    fn peek(&mut self, count: u8) -> u32 {
+        self.fill();
+        (self.buffer & ((1 << count) - 1)) as u32
+    }
+
+    fn consume(&mut self, count: u8) -> io::Result<()> {
+        if self.bits < count {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Incomplete WebP data"));
+        }
+        self.buffer >>= count;
+        self.bits -= count;
+        Ok(())
+    }
+
+    fn read(&mut self, count: u8) -> io::Result<u32> {
+        let value = self.peek(count);
+        self.consume(count)?;
+        Ok(value)
+    }
+}
+
+const LOOKUP_BITS: u8 = 8;
+
+struct PrefixCode {
+    /// Set when the code has a single symbol, which takes no bits
+    single: Option<u16>,
+    /// Symbol and length for the next bits, length 0 for longer codes
+    lookup: Vec<(u16, u8)>,
+    length_counts: [u16; 16],
+    /// Symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl PrefixCode {
+    fn new(lengths: &[u8]) -> io::Result<PrefixCode> {
+        let mut used = lengths.iter().enumerate().filter(|(_, &length)| length > 0);
+        match (used.next(), used.next()) {
+            (None, _) => return Err(invalid_data("Empty WebP prefix code")),
+            (Some((symbol, _)), None) => {
+                return Ok(PrefixCode { single: Some(symbol as u16), lookup: Vec::new(), length_counts: [0; 16], symbols: Vec::new() });
+            },
+            _ => {},
+        }
+
+        let mut length_counts = [0u16; 16];
+        lengths.iter().filter(|&&length| length > 0).for_each(|&length| length_counts[length as usize] += 1);
+        let mut left = 1i32;
+        for &count in length_counts[1..].iter() {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(invalid_data("Over-subscribed WebP prefix code"));
+            }
+        }
+
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16).filter(|&symbol| lengths[symbol as usize] > 0).collect();
+        symbols.sort_by_key(|&symbol| lengths[symbol as usize]);
+
+        let mut lookup = vec![(0, 0); 1 << LOOKUP_BITS];
+        for (symbol, (&code, &length)) in huffman::canonical_codes(lengths).iter().zip(lengths.iter()).enumerate() {
+            if length == 0 || length > LOOKUP_BITS {
+                continue;
+            }
+            let reversed = huffman::reverse_bits(code, length) as usize;
+            for index in (reversed..lookup.len()).step_by(1 << length) {
+                lookup[index] = (symbol as u16, length);
+            }
+        }
+
+        Ok(PrefixCode { single: None, lookup, length_counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> io::Result<u16> {
+        if let Some(symbol) = self.single {
+            return Ok(symbol);
+        }
+        let (symbol, length) = self.lookup[reader.peek(LOOKUP_BITS) as usize];
+        if length > 0 {
+            reader.consume(length)?;
+            return Ok(symbol);
+        }
+
+        // Codes longer than the lookup are walked bit by bit
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in self.length_counts[1..].iter() {
+            code |= reader.read(1)? as i32;
+            if code - first < count as i32 {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count as i32;
+            first = (first + count as i32) << 1;
+            code <<= 1;
+        }
+        Err(invalid_data("Invalid WebP prefix code"))
+    }
+
+    fn read(reader: &mut BitReader, alphabet_size: usize) -> io::Result<PrefixCode> {
+        let mut lengths = vec![0u8; alphabet_size];
+
+        // Simple codes list one or two symbols
+        if reader.read(1)? == 1 {
+            let count = reader.read(1)? + 1;
+            let first_bits = if reader.read(1)? == 1 { 8 } else { 1 };
+            let mut symbols = vec![reader.read(first_bits)? as usize];
+            if count == 2 {
+                symbols.push(reader.read(8)? as usize);
+            }
+            for symbol in symbols {
+                *lengths.get_mut(symbol).ok_or_else(|| invalid_data("Invalid WebP prefix code symbol"))? = 1;
+            }
+            return PrefixCode::new(&lengths);
+        }
+
+        let mut length_code_lengths = [0u8; 19];
+        let count = reader.read(4)? as usize + 4;
+        for &symbol in CODE_LENGTH_ORDER[..count].iter() {
+            length_code_lengths[symbol] = reader.read(3)? as u8;
+        }
+        let length_code = PrefixCode::new(&length_code_lengths)?;
+
+        let mut max_symbol = match reader.read(1)? {
+            1 => {
+                let bits = 2 + 2 * reader.read(3)? as u8;
+                2 + reader.read(bits)? as usize
+            },
+            _ => alphabet_size,
+        };
+        if max_symbol > alphabet_size {
+            return Err(invalid_data("Invalid WebP prefix code length count"));
+        }
+
+        let (mut symbol, mut previous) = (0, 8);
+        while symbol < alphabet_size && max_symbol > 0 {
+            max_symbol -= 1;
+            let (length, repeat) = match length_code.decode(reader)? as u8 {
+                length @ 0..=15 => {
+                    if length > 0 {
+                        previous = length;
+                    }
+                    (length, 1)
+                },
+                16 => (previous, 3 + reader.read(2)? as usize),
+                17 => (0, 3 + reader.read(3)? as usize),
+                _ => (0, 11 + reader.read(7)? as usize),
+            };
+            let lengths = lengths.get_mut(symbol..symbol + repeat).ok_or_else(|| invalid_data("Invalid WebP prefix code lengths"))?;
+            lengths.fill(length);
+            symbol += repeat;
+        }
+
+        PrefixCode::new(&lengths)
+    }
+}
+
+/// Codes for one region of the image
+struct PrefixGroup {
+    green: PrefixCode,
+    red: PrefixCode,
+    blue: PrefixCode,
+    alpha: PrefixCode,
+    distance: PrefixCode,
+}
+
+impl PrefixGroup {
+    fn read(reader: &mut BitReader, cache_size: usize) -> io::Result<PrefixGroup> {
+        Ok(PrefixGroup {
+            green: PrefixCode::read(reader, 256 + 24 + cache_size)?,
+            red: PrefixCode::read(reader, 256)?,
+            blue: PrefixCode::read(reader, 256)?,
+            alpha: PrefixCode::read(reader, 256)?,
+            distance: PrefixCode::read(reader, 40)?,
+        })
+    }
+}
+
+enum Transform {
+    Predictor { bits: u8, modes: Vec<u32> },
+    Color { bits: u8, elements: Vec<u32> },
+    SubtractGreen,
+    /// Pixels pack 1 << bits indices into their green channel
+    ColorIndexing { bits: u8, palette: Vec<u32> },
+}
+
+fn channels(pixel: u32) -> [u8; 4] {
+    pixel.to_be_bytes()
+}
+
+fn from_channels(channels: [u8; 4]) -> u32 {
+    u32::from_be_bytes(channels)
+}
+
+fn per_channel(a: u32, b: u32, f: impl Fn(u8, u8) -> u8) -> u32 {
+    let (a, b) = (channels(a), channels(b));
+    from_channels([f(a[0], b[0]), f(a[1], b[1]), f(a[2], b[2]), f(a[3], b[3])])
+}
+
+fn add_pixels(a: u32, b: u32) -> u32 {
+    per_channel(a, b, u8::wrapping_add)
+}
+
+fn average(a: u32, b: u32) -> u32 {
+    per_channel(a, b, |a, b| ((a as u16 + b as u16) / 2) as u8)
+}
+
+fn select(left: u32, top: u32, top_left: u32) -> u32 {
+    let distance = |a: u32, b: u32| channels(a).iter().zip(channels(b).iter()).map(|(&a, &b)| a.abs_diff(b) as u32).sum::<u32>();
+    if distance(top, top_left) < distance(left, top_left) { left } else { top }
+}
+
+fn predict(mode: u32, left: u32, top: u32, top_left: u32, top_right: u32) -> u32 {
+    match mode {
+        1 => left,
+        2 => top,
+        3 => top_right,
+        4 => top_left,
+        5 => average(average(left, top_right), top),
+        6 => average(left, top_left),
+        7 => average(left, top),
+        8 => average(top_left, top),
+        9 => average(top, top_right),
+        10 => average(average(left, top_left), average(top, top_right)),
+        11 => select(left, top, top_left),
+        12 => {
+            let (left, top, top_left) = (channels(left), channels(top), channels(top_left));
+            from_channels([0, 1, 2, 3].map(|i| (left[i] as i16 + top[i] as i16 - top_left[i] as i16).clamp(0, 255) as u8))
+        },
+        13 => {
+            let (average, top_left) = (channels(average(left, top)), channels(top_left));
+            from_channels([0, 1, 2, 3].map(|i| (average[i] as i16 + (average[i] as i16 - top_left[i] as i16) / 2).clamp(0, 255) as u8))
+        },
+        _ => 0xFF000000,
+    }
+}
+
+fn color_delta(factor: u8, color: u8) -> u8 {
+    ((factor as i8 as i32 * color as i8 as i32) >> 5) as u8
+}
+
+impl Transform {
+    /// Undoes the transform of an image that is `width` pixels wide once restored
+    fn inverse(&self, mut pixels: Vec<u32>, width: usize, height: usize) -> Vec<u32> {
+        match self {
+            Transform::Predictor { bits, modes } => {
+                let blocks_width = width.div_ceil(1 << bits);
+                for y in 0..height {
+                    for x in 0..width {
+                        let index = y * width + x;
+                        let predicted = match (x, y) {
+                            (0, 0) => 0xFF000000,
+                            (_, 0) => pixels[index - 1],
+                            (0, _) => pixels[index - width],
+                            _ => {
+                                let mode = modes[(y >> bits) * blocks_width + (x >> bits)] >> 8 & 0xFF;
+                                // The top right of the last column is the first pixel of the row
+                                predict(mode, pixels[index - 1], pixels[index - width], pixels[index - width - 1], pixels[index - width + 1])
+                            },
+                        };
+                        pixels[index] = add_pixels(pixels[index], predicted);
+                    }
+                }
+                pixels
+            },
+            Transform::Color { bits, elements } => {
+                let blocks_width = width.div_ceil(1 << bits);
+                for y in 0..height {
+                    for x in 0..width {
+                        let [_, red_to_blue, green_to_blue, green_to_red] = channels(elements[(y >> bits) * blocks_width + (x >> bits)]);
+                        let [alpha, mut red, green, mut blue] = channels(pixels[y * width + x]);
+                        red = red.wrapping_add(color_delta(green_to_red, green));
+                        blue = blue.wrapping_add(color_delta(green_to_blue, green));
+                        blue = blue.wrapping_add(color_delta(red_to_blue, red));
+                        pixels[y * width + x] = from_channels([alpha, red, green, blue]);
+                    }
+                }
+                pixels
+            },
+            Transform::SubtractGreen => {
+                for pixel in pixels.iter_mut() {
+                    let [alpha, red, green, blue] = channels(*pixel);
+                    *pixel = from_channels([alpha, red.wrapping_add(green), green, blue.wrapping_add(green)]);
+                }
+                pixels
+            },
+            Transform::ColorIndexing { bits, palette } => {
+                let packed_width = width.div_ceil(1 << bits);
+                let index_bits = 8 >> bits;
+                let mask = (1 << index_bits) - 1;
+                (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| {
+                    let packed = pixels[y * packed_width + (x >> bits)] >> 8 & 0xFF;
+                    let index = packed >> ((x & ((1 << bits) - 1)) * index_bits) & mask;
+                    // Indices past the palette are transparent black
+                    palette.get(index as usize).copied().unwrap_or(0)
+                }).collect()
+            },
+        }
+    }
+}
+
+fn prefix_value(prefix: u16, reader: &mut BitReader) -> io::Result<usize> {
+    if prefix < 4 {
+        return Ok(prefix as usize + 1);
+    }
+    let extra_bits = (prefix - 2) >> 1;
+    let offset = (2 + (prefix as usize & 1)) << extra_bits;
+    Ok(offset + reader.read(extra_bits as u8)? as usize + 1)
+}
+
+/// ARGB pixels of an entropy coded image, only the main image may use several prefix groups
+fn decode_entropy_image(reader: &mut BitReader, width: usize, height: usize, main: bool) -> io::Result<Vec<u32>> {
+    let cache_bits = match reader.read(1)? {
+        1 => match reader.read(4)? as u8 {
+            bits @ 1..=11 => bits,
+            _ => return Err(invalid_data("Invalid WebP color cache size")),
+        },
+        _ => 0,
+    };
+    let cache_size = if cache_bits > 0 { 1 << cache_bits } else { 0 };
+
+    let (prefix_bits, group_indices) = match main && reader.read(1)? == 1 {
+        true => {
+            let bits = reader.read(3)? as u8 + 2;
+            let indices = decode_entropy_image(reader, width.div_ceil(1 << bits), height.div_ceil(1 << bits), false)?;
+            (bits, indices.iter().map(|index| (index >> 8 & 0xFFFF) as usize).collect())
+        },
+        false => (0, Vec::new()),
+    };
+    let group_count = group_indices.iter().max().map_or(1, |max| max + 1);
+    let groups = (0..group_count).map(|_| PrefixGroup::read(reader, cache_size)).collect::<io::Result<Vec<_>>>()?;
+
+    let groups_width = width.div_ceil(1 << prefix_bits);
+    let mut pixels = vec![0u32; width * height];
+    let mut cache = vec![0u32; cache_size];
+    let (mut position, mut cached) = (0, 0);
+    while position < pixels.len() {
+        let (x, y) = (position % width, position / width);
+        let group = match prefix_bits {
+            0 => &groups[0],
+            _ => &groups[group_indices[(y >> prefix_bits) * groups_width + (x >> prefix_bits)]],
+        };
+
+        let green = group.green.decode(reader)?;
+        match green {
+            0..=255 => {
+                let red = group.red.decode(reader)? as u32;
+                let blue = group.blue.decode(reader)? as u32;
+                let alpha = group.alpha.decode(reader)? as u32;
+                pixels[position] = alpha << 24 | red << 16 | (green as u32) << 8 | blue;
+                position += 1;
+            },
+            256..=279 => {
+                let length = prefix_value(green - 256, reader)?;
+                let distance_symbol = group.distance.decode(reader)?;
+                let distance = match prefix_value(distance_symbol, reader)? {
+                    code @ 121.. => code - 120,
+                    code => {
+                        let (dx, dy) = DISTANCE_MAP[code - 1];
+                        (dx as isize + dy as isize * width as isize).max(1) as usize
+                    },
+                };
+                if distance > position || position + length > pixels.len() {
+                    return Err(invalid_data("Invalid WebP backward reference"));
+                }
+                for index in position..position + length {
+                    pixels[index] = pixels[index - distance];
+                }
+                position += length;
+            },
+            _ => {
+                pixels[position] = *cache.get(green as usize - 280).ok_or_else(|| invalid_data("Invalid WebP color cache index"))?;
+                position += 1;
+            },
+        }
+
+        if cache_bits > 0 {
+            for &pixel in pixels[cached..position].iter() {
+                cache[(0x1E35A7BD_u32.wrapping_mul(pixel) >> (32 - cache_bits)) as usize] = pixel;
+            }
+            cached = position;
+        }
+    }
+
+    Ok(pixels)
+}
+
+/// Transforms followed by the main image, without the VP8L header
+fn decode_image_stream(reader: &mut BitReader, width: usize, height: usize) -> io::Result<Vec<u32>> {
+    let mut transforms = Vec::new();
+    let mut coded_width = width;
+    let mut used = [false; 4];
+    while reader.read(1)? == 1 {
+        let kind = reader.read(2)? as usize;
+        if std::mem::replace(&mut used[kind], true) {
+            return Err(invalid_data("Repeated WebP transform"));
+        }
+
+        let transform = match kind {
+            0 | 1 => {
+                let bits = reader.read(3)? as u8 + 2;
+                let data = decode_entropy_image(reader, coded_width.div_ceil(1 << bits), height.div_ceil(1 << bits), false)?;
+                match kind {
+                    0 => Transform::Predictor { bits, modes: data },
+                    _ => Transform::Color { bits, elements: data },
+                }
+            },
+            2 => Transform::SubtractGreen,
+            _ => {
+                let size = reader.read(8)? as usize + 1;
+                let mut palette = decode_entropy_image(reader, size, 1, false)?;
+                // Entries are stored as differences to the previous one
+                for index in 1..size {
+                    palette[index] = add_pixels(palette[index], palette[index - 1]);
+                }
+                let bits = match size {
+                    0..=2 => 3,
+                    3..=4 => 2,
+                    5..=16 => 1,
+                    _ => 0,
+                };
+                Transform::ColorIndexing { bits, palette }
+            },
+        };
+
+        transforms.push((coded_width, transform));
+        if let Some((_, Transform::ColorIndexing { bits, .. })) = transforms.last() {
+            coded_width = coded_width.div_ceil(1 << bits);
+        }
+    }
+
+    let mut pixels = decode_entropy_image(reader, coded_width, height, true)?;
+    for (width, transform) in transforms.iter().rev() {
+        pixels = transform.inverse(pixels, *width, height);
+    }
+    Ok(pixels)
+}
+
+impl WebPReader {
+    /// Contents of a VP8L chunk
+    pub fn decode_vp8l(&self, data: &[u8]) -> io::Result<Image> {
+        let mut reader = BitReader::new(data);
+        if reader.read(8)? != webp::VP8L_SIGNATURE as u32 {
+            return Err(invalid_data("Invalid VP8L signature"));
+        }
+        let width = reader.read(14)? as usize + 1;
+        let height = reader.read(14)? as usize + 1;
+        let _alpha_used = reader.read(1)?;
+        if reader.read(3)? != 0 {
+            return Err(unsupported("Unsupported VP8L version"));
+        }
+
+        let pixels = decode_image_stream(&mut reader, width, height)?;
+        let pixels = pixels.chunks_exact(width).map(|row| row.iter().map(|&pixel| {
+            let [alpha, red, green, blue] = channels(pixel);
+            Color::new(red, green, blue, alpha)
+        }).collect()).collect();
+
+        Ok(Image::from_mat(width, height, pixels))
+    }
+
+    /// Lossless WebP, simple or extended
+    pub fn decode(&self, data: &[u8]) -> io::Result<Image> {
+        let chunks = webp::chunks(data)?;
+        if chunks.iter().any(|chunk| &chunk.id == webp::ANIM) {
+            return Err(unsupported("Animated WebP is not supported"));
+        }
+        if let Some(chunk) = chunks.iter().find(|chunk| &chunk.id == webp::VP8L) {
+            return self.decode_vp8l(chunk.data);
+        }
+        if chunks.iter().any(|chunk| &chunk.id == webp::VP8) {
+            return Err(unsupported("Lossy WebP is not supported"));
+        }
+        Err(invalid_data("WebP file has no image"))
+    }
+}
+
+impl Reader for WebPReader {
+    fn read(&self, path: &str) -> io::Result<Image> {
+        println!("Reading WebP file");
+
+        let data = fs::read(path)?;
+        self.decode(&data)
+    }
+}
@@ -0,0 +1,587 @@
+//! Deflate encoder trading speed for size in the style of Zopfli: blocks are split on a greedy parse,
+//! then each block is parsed optimally against a cost model refined over several iterations.
+
+use crate::huffman::{canonical_codes, code_lengths, reverse_bits};
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 8192;
+const HASH_SIZE: usize = 1 << 16;
+
+const MAX_BLOCKS: usize = 15;
+const MIN_BLOCK_SYMBOLS: usize = 1024;
+
+const END_OF_BLOCK: usize = 256;
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// Order in which code length code lengths are stored in a dynamic block header
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Symbol {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+fn length_code(length: u16) -> usize {
+    LENGTH_BASE.partition_point(|&base| base <= length) - 1
+}
+
+fn distance_code(distance: u16) -> usize {
+    DISTANCE_BASE.partition_point(|&base| base <= distance) - 1
+}
+
+/// Symbol counts of a parse
+#[derive(Clone)]
+struct Statistics {
+    literal_lengths: [f64; 288],
+    distances: [f64; 32],
+}
+
+impl Statistics {
+    fn new(symbols: &[Symbol]) -> Statistics {
+        let mut statistics = Statistics { literal_lengths: [0.0; 288], distances: [0.0; 32] };
+
+        for symbol in symbols.iter() {
+            match *symbol {
+                Symbol::Literal(byte) => statistics.literal_lengths[byte as usize] += 1.0,
+                Symbol::Match { length, distance } => {
+                    statistics.literal_lengths[257 + length_code(length)] += 1.0;
+                    statistics.distances[distance_code(distance)] += 1.0;
+                }
+            }
+        }
+        statistics.literal_lengths[END_OF_BLOCK] = 1.0;
+
+        statistics
+    }
+
+    fn blend(&mut self, other: &Statistics, weight: f64) {
+        for (count, other) in self.literal_lengths.iter_mut().zip(other.literal_lengths.iter()) {
+            *count += other * weight;
+        }
+        for (count, other) in self.distances.iter_mut().zip(other.distances.iter()) {
+            *count += other * weight;
+        }
+        self.literal_lengths[END_OF_BLOCK] = 1.0;
+    }
+
+    /// Replaces a third of the counts with random other counts to escape local minima
+    fn randomize(&mut self, random: &mut Random) {
+        for counts in [&mut self.literal_lengths[..], &mut self.distances[..]] {
+            for i in 0..counts.len() {
+                if (random.next() >> 4).is_multiple_of(3) {
+                    counts[i] = counts[random.next() as usize % counts.len()];
+                }
+            }
+        }
+        self.literal_lengths[END_OF_BLOCK] = 1.0;
+    }
+}
+
+/// Multiply-with-carry generator, deterministic so output is reproducible
+struct Random {
+    w: u32,
+    z: u32,
+}
+
+impl Random {
+    fn next(&mut self) -> u32 {
+        self.z = 36969u32.wrapping_mul(self.z & 65535).wrapping_add(self.z >> 16);
+        self.w = 18000u32.wrapping_mul(self.w & 65535).wrapping_add(self.w >> 16);
+        (self.z << 16).wrapping_add(self.w)
+    }
+}
+
+/// Estimated bits per symbol from its share of the counts
+struct CostModel {
+    literal_lengths: [f64; 288],
+    distances: [f64; 32],
+    /// Bits of the length symbol and its extra bits, by match length
+    lengths: [f64; MAX_MATCH + 1],
+}
+
+impl CostModel {
+    fn new(statistics: &Statistics) -> CostModel {
+        fn entropy<const N: usize>(counts: &[f64; N]) -> [f64; N] {
+            let sum: f64 = counts.iter().sum();
+            let log_sum = if sum > 0.0 { sum.log2() } else { 0.0 };
+            counts.map(|count| if count > 0.0 { log_sum - count.log2() } else { log_sum })
+        }
+
+        let literal_lengths = entropy(&statistics.literal_lengths);
+        let mut lengths = [0.0; MAX_MATCH + 1];
+        for (length, cost) in lengths.iter_mut().enumerate().skip(MIN_MATCH) {
+            let length_code = length_code(length as u16);
+            *cost = literal_lengths[257 + length_code] + LENGTH_EXTRA[length_code] as f64;
+        }
+
+        CostModel {
+            literal_lengths,
+            distances: entropy(&statistics.distances),
+            lengths,
+        }
+    }
+
+    fn literal(&self, byte: u8) -> f64 {
+        self.literal_lengths[byte as usize]
+    }
+
+    fn distance(&self, distance: u16) -> f64 {
+        let distance_code = distance_code(distance);
+        self.distances[distance_code] + DISTANCE_EXTRA[distance_code] as f64
+    }
+}
+
+/// For every position, the shortest distance reaching each match length
+struct MatchCache {
+    /// Start of the position's entries in `matches`
+    offsets: Vec<usize>,
+    /// Increasing lengths, each reachable with its distance and all shorter lengths with a distance no longer
+    matches: Vec<(u16, u16)>,
+}
+
+impl MatchCache {
+    fn new(data: &[u8]) -> MatchCache {
+        let hash = |i: usize| ((data[i] as usize) << 8 ^ (data[i + 1] as usize) << 4 ^ data[i + 2] as usize) % HASH_SIZE;
+
+        let mut head = vec![usize::MAX; HASH_SIZE];
+        let mut previous = vec![usize::MAX; data.len()];
+        let mut offsets = Vec::with_capacity(data.len() + 1);
+        let mut matches = Vec::new();
+
+        for i in 0..data.len() {
+            offsets.push(matches.len());
+            let max_length = MAX_MATCH.min(data.len() - i);
+            if max_length < MIN_MATCH {
+                continue;
+            }
+
+            let h = hash(i);
+            let mut best = MIN_MATCH - 1;
+            let mut candidate = head[h];
+            let mut chain = 0;
+
+            while candidate != usize::MAX && i - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                if data[candidate + best] == data[i + best] {
+                    let length = (0..max_length).find(|&k| data[candidate + k] != data[i + k]).unwrap_or(max_length);
+                    if length > best {
+                        best = length;
+                        matches.push((length as u16, (i - candidate) as u16));
+                        if length == max_length {
+                            break;
+                        }
+                    }
+                }
+                candidate = previous[candidate];
+                chain += 1;
+            }
+
+            previous[i] = head[h];
+            head[h] = i;
+        }
+        offsets.push(matches.len());
+
+        MatchCache { offsets, matches }
+    }
+
+    fn at(&self, position: usize) -> &[(u16, u16)] {
+        &self.matches[self.offsets[position]..self.offsets[position + 1]]
+    }
+}
+
+/// Longest match at every position
+fn greedy_parse(data: &[u8], cache: &MatchCache) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    let mut i = 0;
+
+    while i < data.len() {
+        match cache.at(i).last() {
+            Some(&(length, distance)) => {
+                symbols.push(Symbol::Match { length, distance });
+                i += length as usize;
+            }
+            None => {
+                symbols.push(Symbol::Literal(data[i]));
+                i += 1;
+            }
+        }
+    }
+
+    symbols
+}
+
+/// Cheapest parse of `data[start..end]` under the cost model
+fn optimal_parse(data: &[u8], start: usize, end: usize, cache: &MatchCache, costs: &CostModel) -> Vec<Symbol> {
+    let size = end - start;
+    let mut cost = vec![f64::INFINITY; size + 1];
+    let mut step = vec![(1u16, 0u16); size + 1];
+    cost[0] = 0.0;
+
+    for k in 0..size {
+        let i = start + k;
+
+        let literal = cost[k] + costs.literal(data[i]);
+        if literal < cost[k + 1] {
+            cost[k + 1] = literal;
+            step[k + 1] = (1, 0);
+        }
+
+        let mut shortest = MIN_MATCH;
+        for &(longest, distance) in cache.at(i).iter() {
+            let longest = (longest as usize).min(end - i);
+            let base = cost[k] + costs.distance(distance);
+            for length in shortest..=longest {
+                let matched = base + costs.lengths[length];
+                if matched < cost[k + length] {
+                    cost[k + length] = matched;
+                    step[k + length] = (length as u16, distance);
+                }
+            }
+            shortest = shortest.max(longest + 1);
+        }
+    }
+
+    let mut symbols = Vec::new();
+    let mut k = size;
+    while k > 0 {
+        let (length, distance) = step[k];
+        k -= length as usize;
+        symbols.push(if distance == 0 { Symbol::Literal(data[start + k]) } else { Symbol::Match { length, distance } });
+    }
+    symbols.reverse();
+
+    symbols
+}
+
+/// Code lengths for literal/length and distance alphabets
+struct Codes {
+    literal_lengths: Vec<u8>,
+    distances: Vec<u8>,
+}
+
+impl Codes {
+    fn fixed() -> Codes {
+        let mut literal_lengths = vec![8u8; 288];
+        literal_lengths[144..256].fill(9);
+        literal_lengths[256..280].fill(7);
+        Codes { literal_lengths, distances: vec![5; 32] }
+    }
+
+    fn dynamic(symbols: &[Symbol]) -> Codes {
+        let statistics = Statistics::new(symbols);
+        let literal_lengths = code_lengths(&statistics.literal_lengths.map(|count| count as u64), 15);
+        let mut distances = code_lengths(&statistics.distances.map(|count| count as u64), 15);
+
+        // Some inflaters reject distance codes with fewer than two symbols
+        match distances.iter().filter(|&&length| length > 0).count() {
+            0 => distances[..2].fill(1),
+            1 => {
+                let unused = if distances[0] == 0 { 0 } else { 1 };
+                distances[unused] = 1;
+            }
+            _ => {}
+        }
+
+        Codes { literal_lengths, distances }
+    }
+
+    fn data_bits(&self, symbols: &[Symbol]) -> usize {
+        let mut bits = self.literal_lengths[END_OF_BLOCK] as usize;
+
+        for symbol in symbols.iter() {
+            bits += match *symbol {
+                Symbol::Literal(byte) => self.literal_lengths[byte as usize] as usize,
+                Symbol::Match { length, distance } => {
+                    let length_code = length_code(length);
+                    let distance_code = distance_code(distance);
+                    self.literal_lengths[257 + length_code] as usize + LENGTH_EXTRA[length_code] as usize
+                        + self.distances[distance_code] as usize + DISTANCE_EXTRA[distance_code] as usize
+                }
+            };
+        }
+
+        bits
+    }
+
+    /// Run length encoded code lengths as (code length symbol, extra bits value)
+    fn header_symbols(&self) -> (usize, usize, Vec<(usize, u16)>) {
+        let literal_count = 257.max(self.literal_lengths.iter().rposition(|&length| length > 0).map_or(0, |i| i + 1));
+        let distance_count = 1.max(self.distances.iter().rposition(|&length| length > 0).map_or(0, |i| i + 1));
+        let lengths: Vec<u8> = [&self.literal_lengths[..literal_count], &self.distances[..distance_count]].concat();
+
+        let mut symbols = Vec::new();
+        let mut i = 0;
+        while i < lengths.len() {
+            let length = lengths[i];
+            let run = lengths[i..].iter().take_while(|&&other| other == length).count();
+
+            if length == 0 && run >= 11 {
+                let run = run.min(138);
+                symbols.push((18, (run - 11) as u16));
+                i += run;
+            } else if length == 0 && run >= 3 {
+                symbols.push((17, (run - 3) as u16));
+                i += run;
+            } else if length != 0 && run >= 4 {
+                let run = (run - 1).min(6);
+                symbols.push((length as usize, 0));
+                symbols.push((16, (run - 3) as u16));
+                i += run + 1;
+            } else {
+                symbols.push((length as usize, 0));
+                i += 1;
+            }
+        }
+
+        (literal_count, distance_count, symbols)
+    }
+
+    fn code_length_lengths(header_symbols: &[(usize, u16)]) -> Vec<u8> {
+        let mut frequencies = [0u64; 19];
+        for &(symbol, _) in header_symbols.iter() {
+            frequencies[symbol] += 1;
+        }
+        code_lengths(&frequencies, 7)
+    }
+
+    fn header_bits(&self) -> usize {
+        let (_, _, symbols) = self.header_symbols();
+        let lengths = Self::code_length_lengths(&symbols);
+        let stored = 4.max(CODE_LENGTH_ORDER.iter().rposition(|&symbol| lengths[symbol] > 0).map_or(0, |i| i + 1));
+        let extra = |symbol: usize| match symbol { 16 => 2, 17 => 3, 18 => 7, _ => 0 };
+
+        5 + 5 + 4 + 3 * stored + symbols.iter().map(|&(symbol, _)| lengths[symbol] as usize + extra(symbol)).sum::<usize>()
+    }
+}
+
+/// Deflate bit stream, least significant bit first
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, bits: u8) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += bits as u32;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.write(0, 8 - self.count as u8);
+        }
+    }
+
+    fn write_block(&mut self, codes: &Codes, symbols: &[Symbol]) {
+        let literal_codes = canonical_codes(&codes.literal_lengths);
+        let distance_codes = canonical_codes(&codes.distances);
+        let write_code = |writer: &mut BitWriter, codes: &[u16], lengths: &[u8], symbol: usize| {
+            writer.write(reverse_bits(codes[symbol], lengths[symbol]) as u32, lengths[symbol]);
+        };
+
+        for symbol in symbols.iter() {
+            match *symbol {
+                Symbol::Literal(byte) => write_code(self, &literal_codes, &codes.literal_lengths, byte as usize),
+                Symbol::Match { length, distance } => {
+                    let length_code = length_code(length);
+                    write_code(self, &literal_codes, &codes.literal_lengths, 257 + length_code);
+                    self.write((length - LENGTH_BASE[length_code]) as u32, LENGTH_EXTRA[length_code]);
+
+                    let distance_code = distance_code(distance);
+                    write_code(self, &distance_codes, &codes.distances, distance_code);
+                    self.write((distance - DISTANCE_BASE[distance_code]) as u32, DISTANCE_EXTRA[distance_code]);
+                }
+            }
+        }
+
+        write_code(self, &literal_codes, &codes.literal_lengths, END_OF_BLOCK);
+    }
+
+    fn write_dynamic_header(&mut self, codes: &Codes) {
+        let (literal_count, distance_count, symbols) = codes.header_symbols();
+        let lengths = Codes::code_length_lengths(&symbols);
+        let length_codes = canonical_codes(&lengths);
+        let stored = 4.max(CODE_LENGTH_ORDER.iter().rposition(|&symbol| lengths[symbol] > 0).map_or(0, |i| i + 1));
+
+        self.write((literal_count - 257) as u32, 5);
+        self.write((distance_count - 1) as u32, 5);
+        self.write((stored - 4) as u32, 4);
+        for &symbol in CODE_LENGTH_ORDER[..stored].iter() {
+            self.write(lengths[symbol] as u32, 3);
+        }
+
+        for &(symbol, extra) in symbols.iter() {
+            self.write(reverse_bits(length_codes[symbol], lengths[symbol]) as u32, lengths[symbol]);
+            match symbol {
+                16 => self.write(extra as u32, 2),
+                17 => self.write(extra as u32, 3),
+                18 => self.write(extra as u32, 7),
+                _ => {}
+            }
+        }
+    }
+
+    /// Writes the block in whichever of the stored, fixed or dynamic forms is smallest
+    fn write_smallest_block(&mut self, data: &[u8], symbols: &[Symbol], last: bool) {
+        let fixed = Codes::fixed();
+        let dynamic = Codes::dynamic(symbols);
+
+        let fixed_bits = 3 + fixed.data_bits(symbols);
+        let dynamic_bits = 3 + dynamic.header_bits() + dynamic.data_bits(symbols);
+        let stored_bits = data.len().div_ceil(65535).max(1) * 5 * 8 + data.len() * 8 + 7;
+
+        if stored_bits < fixed_bits.min(dynamic_bits) {
+            let chunks: Vec<&[u8]> = if data.is_empty() { vec![data] } else { data.chunks(65535).collect() };
+            for (i, chunk) in chunks.iter().enumerate() {
+                self.write((last && i == chunks.len() - 1) as u32, 1);
+                self.write(0, 2);
+                self.align();
+                self.bytes.extend((chunk.len() as u16).to_le_bytes());
+                self.bytes.extend((!(chunk.len() as u16)).to_le_bytes());
+                self.bytes.extend_from_slice(chunk);
+            }
+        } else if fixed_bits <= dynamic_bits {
+            self.write(last as u32, 1);
+            self.write(1, 2);
+            self.write_block(&fixed, symbols);
+        } else {
+            self.write(last as u32, 1);
+            self.write(2, 2);
+            self.write_dynamic_header(&dynamic);
+            self.write_block(&dynamic, symbols);
+        }
+    }
+}
+
+fn block_bits(symbols: &[Symbol]) -> usize {
+    let codes = Codes::dynamic(symbols);
+    codes.header_bits() + codes.data_bits(symbols)
+}
+
+/// Symbol indices splitting the parse into blocks which are cheaper to encode separately
+fn split_points(symbols: &[Symbol]) -> Vec<usize> {
+    let mut points = vec![0, symbols.len()];
+
+    while points.len() <= MAX_BLOCKS {
+        let mut best: Option<(usize, usize, usize)> = None; // Gain, index in points, split
+
+        for i in 0..points.len() - 1 {
+            let (start, end) = (points[i], points[i + 1]);
+            if end - start < 2 * MIN_BLOCK_SYMBOLS {
+                continue;
+            }
+
+            let whole = block_bits(&symbols[start..end]);
+            let cost = |split: usize| block_bits(&symbols[start..split]) + block_bits(&symbols[split..end]);
+
+            // Coarse search followed by narrowing around the best candidate
+            let (mut low, mut high) = (start + MIN_BLOCK_SYMBOLS, end - MIN_BLOCK_SYMBOLS);
+            let mut split = (low + high) / 2;
+            while high - low > 8 {
+                let step = (high - low) / 8;
+                split = (0..=8).map(|k| low + k * step).min_by_key(|&split| cost(split)).unwrap();
+                low = split.saturating_sub(step).max(start + MIN_BLOCK_SYMBOLS);
+                high = (split + step).min(end - MIN_BLOCK_SYMBOLS);
+            }
+
+            let split_cost = cost(split);
+            if split_cost < whole && best.is_none_or(|(gain, _, _)| whole - split_cost > gain) {
+                best = Some((whole - split_cost, i, split));
+            }
+        }
+
+        match best {
+            Some((_, i, split)) => points.insert(i + 1, split),
+            None => break,
+        }
+    }
+
+    points
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk.iter() {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// Raw deflate stream, refining each block's cost model over `iterations`
+pub fn deflate(data: &[u8], iterations: u32) -> Vec<u8> {
+    let cache = MatchCache::new(data);
+    let greedy = greedy_parse(data, &cache);
+    let points = split_points(&greedy);
+
+    let mut writer = BitWriter { bytes: Vec::new(), buffer: 0, count: 0 };
+    let mut random = Random { w: 1, z: 2 };
+    let mut start = 0;
+
+    for (i, block) in points.windows(2).enumerate() {
+        let mut best = greedy[block[0]..block[1]].to_vec();
+        let end = start + best.iter().map(|symbol| match *symbol {
+            Symbol::Literal(_) => 1,
+            Symbol::Match { length, .. } => length as usize,
+        }).sum::<usize>();
+        let mut best_bits = block_bits(&best);
+
+        let mut statistics = Statistics::new(&best);
+        let mut best_statistics = statistics.clone();
+        let mut last_bits = usize::MAX;
+        let mut randomized = false;
+
+        for iteration in 0..iterations {
+            let symbols = optimal_parse(data, start, end, &cache, &CostModel::new(&statistics));
+            let bits = block_bits(&symbols);
+
+            let previous = statistics;
+            statistics = Statistics::new(&symbols);
+            if bits < best_bits {
+                best_bits = bits;
+                best = symbols;
+                best_statistics = statistics.clone();
+            }
+
+            if randomized {
+                statistics.blend(&previous, 0.5);
+            }
+            if iteration > 5 && bits == last_bits {
+                statistics = best_statistics.clone();
+                statistics.randomize(&mut random);
+                randomized = true;
+            }
+            last_bits = bits;
+        }
+
+        writer.write_smallest_block(&data[start..end], &best, i == points.len() - 2);
+        start = end;
+    }
+
+    writer.align();
+    writer.bytes
+}
+
+/// Zlib stream of the data compressed with `deflate`
+pub fn compress(data: &[u8], iterations: u32) -> Vec<u8> {
+    let mut compressed = vec![0x78, 0xda];
+    compressed.extend(deflate(data, iterations));
+    compressed.extend(adler32(data).to_be_bytes());
+    compressed
+}